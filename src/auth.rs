@@ -0,0 +1,136 @@
+use std::{env, fs, path::PathBuf};
+
+use crate::{cli::CranAuth, util};
+
+/// Environment variable carrying the CRAN mirror user name to R scripts.
+pub const CRAN_USER_ENV: &str = "REVDEPRUN_CRAN_USER";
+/// Environment variable carrying the CRAN mirror password to R scripts.
+pub const CRAN_PASSWORD_ENV: &str = "REVDEPRUN_CRAN_PASSWORD";
+
+/// Returns the credentials for `mirror`: `explicit` if given, otherwise the
+/// matching `machine` entry of `$NETRC` or `~/.netrc`.
+pub fn resolve(explicit: Option<&CranAuth>, mirror: &str) -> Option<CranAuth> {
+    if let Some(auth) = explicit {
+        return Some(auth.clone());
+    }
+    let host = mirror_host(mirror)?;
+    let contents = fs::read_to_string(netrc_path()?).ok()?;
+    parse_netrc(&contents, host)
+}
+
+/// R code assigning `source_repo` to `mirror`, with the credentials from
+/// [`CRAN_USER_ENV`] and [`CRAN_PASSWORD_ENV`] spliced into the URL at run
+/// time so they never appear in the script itself.
+pub fn source_repo_setup(mirror: &str) -> String {
+    format!(
+        r#"source_repo <- {}
+cran_user <- Sys.getenv("{CRAN_USER_ENV}")
+if (nzchar(cran_user)) {{
+  cran_credentials <- paste0(
+    utils::URLencode(cran_user, reserved = TRUE), ":",
+    utils::URLencode(Sys.getenv("{CRAN_PASSWORD_ENV}"), reserved = TRUE), "@"
+  )
+  source_repo <- sub("://", paste0("://", cran_credentials), source_repo, fixed = TRUE)
+}}"#,
+        util::r_string_literal(mirror)
+    )
+}
+
+fn netrc_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("NETRC") {
+        return Some(PathBuf::from(path));
+    }
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".netrc"))
+}
+
+fn mirror_host(url: &str) -> Option<&str> {
+    let (_, rest) = url.split_once("://")?;
+    let authority = rest.split('/').next()?;
+    let host = authority.rsplit('@').next()?;
+    let host = host.split(':').next()?;
+    (!host.is_empty()).then_some(host)
+}
+
+/// Finds the `login`/`password` pair for `host`, falling back to `default`.
+fn parse_netrc(contents: &str, host: &str) -> Option<CranAuth> {
+    let mut tokens = contents.split_whitespace();
+    let mut matched = None;
+    let mut fallback = None;
+    let mut current: Option<(bool, Option<String>, Option<String>)> = None;
+
+    let mut finish = |entry: Option<(bool, Option<String>, Option<String>)>| {
+        if let Some((is_match, Some(user), password)) = entry {
+            let auth = CranAuth {
+                user,
+                password: password.unwrap_or_default(),
+            };
+            if is_match {
+                matched.get_or_insert(auth);
+            } else {
+                fallback.get_or_insert(auth);
+            }
+        }
+    };
+
+    while let Some(token) = tokens.next() {
+        match token {
+            "machine" => {
+                finish(current.take());
+                let name = tokens.next().unwrap_or_default();
+                current = (name == host).then_some((true, None, None));
+            }
+            "default" => {
+                finish(current.take());
+                current = Some((false, None, None));
+            }
+            "login" => {
+                let value = tokens.next().map(str::to_string);
+                if let Some(entry) = current.as_mut() {
+                    entry.1 = value;
+                }
+            }
+            "password" => {
+                let value = tokens.next().map(str::to_string);
+                if let Some(entry) = current.as_mut() {
+                    entry.2 = value;
+                }
+            }
+            _ => {}
+        }
+    }
+    finish(current.take());
+
+    matched.or(fallback)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_credentials_from_netrc() {
+        let netrc = "machine github.com login octocat password gh-token\n\
+                     machine ppm.example.com\n  login ci\n  password s3cret\n\
+                     default login anonymous password guest\n";
+        let auth = parse_netrc(netrc, "ppm.example.com").expect("matching machine");
+        assert_eq!(auth.user, "ci");
+        assert_eq!(auth.password, "s3cret");
+
+        let auth = parse_netrc(netrc, "other.example.com").expect("default entry");
+        assert_eq!(auth.user, "anonymous");
+        assert!(parse_netrc("machine github.com login a password b", "x.org").is_none());
+
+        assert_eq!(
+            mirror_host("https://ci@ppm.example.com:8443/cran/latest"),
+            Some("ppm.example.com")
+        );
+    }
+
+    #[test]
+    fn source_repo_setup_reads_credentials_from_environment() {
+        let setup = source_repo_setup("https://ppm.example.com/cran/latest");
+        assert!(setup.starts_with("source_repo <- 'https://ppm.example.com/cran/latest'"));
+        assert!(setup.contains("Sys.getenv(\"REVDEPRUN_CRAN_USER\")"));
+        assert!(setup.contains("Sys.getenv(\"REVDEPRUN_CRAN_PASSWORD\")"));
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{cli::CleanCacheArgs, util, workspace};
+
+/// Prefix of the files and directories `tempfile` creates, which a killed
+/// run leaves behind in the workspace.
+const TEMPFILE_PREFIX: &str = ".tmp";
+
+/// Removes what earlier runs left in the workspace, if it was last modified
+/// more than `--older-than` days ago, and reports the space freed. Nothing
+/// outside the workspace is touched.
+pub fn run(args: &CleanCacheArgs) -> Result<()> {
+    let work_dir = workspace::temp_dir_path(args.work_dir.as_deref())?;
+    let cutoff = SystemTime::now()
+        .checked_sub(Duration::from_secs(args.older_than.saturating_mul(86_400)))
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+
+    let stale = stale_entries(&work_dir, cutoff, is_workspace_leftover)?;
+
+    let mut freed = 0;
+    for path in &stale {
+        let size = util::dir_size(path);
+        let removed = if path.is_dir() {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        };
+        removed.with_context(|| format!("failed to remove {}", path.display()))?;
+        println!("Removed {} ({})", path.display(), util::format_bytes(size));
+        freed += size;
+    }
+    println!(
+        "Freed {} from {} entries older than {} days.",
+        util::format_bytes(freed),
+        stale.len(),
+        args.older_than
+    );
+    Ok(())
+}
+
+/// Entries of `dir` accepted by `matches` and last modified before `cutoff`,
+/// sorted by path. A missing `dir` has none.
+fn stale_entries(
+    dir: &Path,
+    cutoff: SystemTime,
+    matches: fn(&str) -> bool,
+) -> Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("failed to read {}", dir.display())),
+    };
+
+    let mut stale = entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_str().is_some_and(matches))
+        .filter(|entry| {
+            entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| modified < cutoff)
+        })
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    stale.sort();
+    Ok(stale)
+}
+
+/// Generated scripts and extraction directories of interrupted runs, and the
+/// record of system requirement commands that already succeeded.
+fn is_workspace_leftover(name: &str) -> bool {
+    name.starts_with(TEMPFILE_PREFIX) || name == workspace::SYSREQS_DONE_FILE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_only_stale_leftovers() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let old = SystemTime::now() - Duration::from_secs(40 * 86_400);
+        for name in [
+            ".tmpA1b2C3",
+            workspace::SYSREQS_DONE_FILE,
+            "notes.txt",
+            ".tmpNew",
+        ] {
+            let file = fs::File::create(dir.path().join(name)).expect("create");
+            if name != ".tmpNew" {
+                file.set_modified(old).expect("set mtime");
+            }
+        }
+
+        let cutoff = SystemTime::now() - Duration::from_secs(30 * 86_400);
+        let stale = stale_entries(dir.path(), cutoff, is_workspace_leftover).expect("entries");
+        assert_eq!(
+            stale,
+            vec![
+                dir.path().join(".tmpA1b2C3"),
+                dir.path().join(workspace::SYSREQS_DONE_FILE),
+            ]
+        );
+        assert!(
+            stale_entries(&dir.path().join("missing"), cutoff, is_workspace_leftover)
+                .expect("missing dir")
+                .is_empty()
+        );
+    }
+}
@@ -1,27 +1,1152 @@
-use std::{num::NonZeroUsize, path::PathBuf};
+use std::{fmt, num::NonZeroUsize, path::PathBuf, str::FromStr, time::Duration};
 
-use clap::Parser;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum, error::ErrorKind};
+
+/// Source used to compute the list of reverse dependencies.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum RevdepsSource {
+    /// `tools::package_dependencies()` on the CRAN package database.
+    #[default]
+    Tools,
+    /// `revdepcheck::cran_revdeps()`.
+    Revdepcheck,
+}
+
+impl RevdepsSource {
+    /// Short label used in progress messages.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Tools => "tools::package_dependencies",
+            Self::Revdepcheck => "revdepcheck::cran_revdeps",
+        }
+    }
+}
+
+/// Phase of the check pipeline that `--continue-from` resumes at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum ContinueFrom {
+    /// Install system requirements, then revdeps, then check.
+    Sysreqs,
+    /// Install revdeps, then check.
+    Install,
+    /// Check with the revdep library already installed.
+    Check,
+}
+
+impl ContinueFrom {
+    /// Short label used in progress messages.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Sysreqs => "sysreqs",
+            Self::Install => "install",
+            Self::Check => "check",
+        }
+    }
+}
+
+/// Package types used when installing revdep dependencies.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum CranType {
+    /// Posit Package Manager binaries, falling back to source where none exist.
+    #[default]
+    Binary,
+    /// Source packages from the CRAN mirror only.
+    Source,
+    /// Whichever of the Posit binary and the CRAN source is newer.
+    Both,
+}
+
+/// Tool the install phase installs packages with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Installer {
+    /// `install.packages()`, after a separate system requirements phase.
+    #[default]
+    Base,
+    /// `pak::pkg_install()`, which also installs system requirements as it goes.
+    Pak,
+}
+
+/// Dependencies of the target package itself to install before the check.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TargetDeps {
+    /// Depends, Imports, and LinkingTo only.
+    Hard,
+    /// Hard dependencies plus Suggests.
+    All,
+}
+
+/// Check outcome categories that make the run exit with a non-zero status.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum FailOn {
+    /// Reverse dependencies whose check log has a NOTE, WARNING, or ERROR.
+    Note,
+    /// Reverse dependencies whose check log has a WARNING or ERROR.
+    Warning,
+    /// Reverse dependencies whose check reported an ERROR.
+    Error,
+    /// Reverse dependencies whose check hit a time limit.
+    Timeout,
+}
+
+/// Format of the machine-readable progress event stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum EventFormat {
+    /// One JSON object per line.
+    Ndjson,
+}
+
+/// A `KEY=VALUE` environment variable passed to the R subprocesses.
+///
+/// The `Debug` output redacts the value so secrets never reach logs.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EnvVar {
+    /// Variable name.
+    pub key: String,
+    /// Variable value.
+    pub value: String,
+}
+
+impl FromStr for EnvVar {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (key, value) = spec
+            .split_once('=')
+            .ok_or_else(|| format!("expected KEY=VALUE, got '{spec}'"))?;
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(format!("missing variable name in '{spec}'"));
+        }
+        Ok(Self {
+            key: key.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+impl fmt::Debug for EnvVar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}=<redacted>", self.key)
+    }
+}
+
+/// Extra HTTP request header given as `Name: Value` via `--http-header`.
+///
+/// The `Debug` output redacts the value, which may be a token.
+#[derive(Clone, PartialEq, Eq)]
+pub struct HttpHeader {
+    /// Header name.
+    pub name: String,
+    /// Header value.
+    pub value: String,
+}
+
+impl FromStr for HttpHeader {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (name, value) = spec
+            .split_once(':')
+            .ok_or_else(|| format!("expected 'Name: Value', got '{spec}'"))?;
+        let name = name.trim();
+        let value = value.trim();
+        reqwest::header::HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| format!("invalid HTTP header name '{name}'"))?;
+        reqwest::header::HeaderValue::from_str(value)
+            .map_err(|_| format!("invalid value for HTTP header '{name}'"))?;
+        Ok(Self {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+impl fmt::Debug for HttpHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: <redacted>", self.name)
+    }
+}
+
+/// Credentials for an authenticated CRAN mirror, given as `USER:PASSWORD`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct CranAuth {
+    /// User name.
+    pub user: String,
+    /// Password or token.
+    pub password: String,
+}
+
+impl FromStr for CranAuth {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (user, password) = spec
+            .split_once(':')
+            .ok_or_else(|| "expected USER:PASSWORD".to_string())?;
+        if user.is_empty() {
+            return Err("missing user name in --cran-auth".to_string());
+        }
+        Ok(Self {
+            user: user.to_string(),
+            password: password.to_string(),
+        })
+    }
+}
+
+impl fmt::Debug for CranAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:<redacted>", self.user)
+    }
+}
+
+/// How much history `git clone` fetches for a remote target repository.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CloneDepth {
+    /// Fetch only the last `n` commits.
+    Shallow(u32),
+    /// Fetch the complete history, including tags.
+    Full,
+}
+
+impl FromStr for CloneDepth {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "full" | "0" => Ok(Self::Full),
+            other => other
+                .parse::<u32>()
+                .map(Self::Shallow)
+                .map_err(|_| format!("expected a commit count or 'full', got '{other}'")),
+        }
+    }
+}
+
+/// Time span such as `30s`, `5m`, or `1h`; a bare number is read as seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Interval(pub Duration);
+
+impl FromStr for Interval {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let (number, unit) = match value.find(|ch: char| !ch.is_ascii_digit()) {
+            Some(index) => value.split_at(index),
+            None => (value, "s"),
+        };
+        let multiplier = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            _ => {
+                return Err(format!(
+                    "expected a duration such as 30s, 5m, or 1h, got '{value}'"
+                ));
+            }
+        };
+        match number.parse::<u64>() {
+            Ok(count) if count > 0 => Ok(Self(Duration::from_secs(count * multiplier))),
+            _ => Err(format!(
+                "expected a positive duration such as 30s, 5m, or 1h, got '{value}'"
+            )),
+        }
+    }
+}
+
+/// Regular expression for `--pkg-filter`, compiled when parsed.
+#[derive(Clone, Debug)]
+pub struct PkgFilter(regex_lite::Regex);
+
+impl PkgFilter {
+    /// Whether `name` matches the pattern.
+    pub fn is_match(&self, name: &str) -> bool {
+        self.0.is_match(name)
+    }
+
+    /// The pattern as given.
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl FromStr for PkgFilter {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        regex_lite::Regex::new(value)
+            .map(Self)
+            .map_err(|err| format!("invalid regular expression: {err}"))
+    }
+}
+
+/// An `--only` or `--exclude` entry: a package name, or `@PATH` of a file
+/// listing one package name per line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PackageSpec {
+    /// A single package name.
+    Name(String),
+    /// A file of package names; blank lines and `#` comments are ignored.
+    File(PathBuf),
+}
+
+impl FromStr for PackageSpec {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        if let Some(path) = value.strip_prefix('@') {
+            if path.is_empty() {
+                return Err("expected a file path after '@'".to_string());
+            }
+            return Ok(Self::File(PathBuf::from(path)));
+        }
+        if !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '.') {
+            Ok(Self::Name(value.to_string()))
+        } else {
+            Err(format!("expected a package name or @FILE, got '{value}'"))
+        }
+    }
+}
+
+/// Bioconductor release for `--bioc-version`, given as `MAJOR.MINOR`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BiocVersion(pub String);
+
+impl FromStr for BiocVersion {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let valid = value.split_once('.').is_some_and(|(major, minor)| {
+            [major, minor]
+                .iter()
+                .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+        });
+        if valid {
+            Ok(Self(value.to_string()))
+        } else {
+            Err(format!("expected a release like 3.21, got '{value}'"))
+        }
+    }
+}
+
+/// Distribution codename for `--binary-codename`, e.g. `noble`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Codename(pub String);
+
+impl FromStr for Codename {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim().to_ascii_lowercase();
+        if !value.is_empty() && value.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            Ok(Self(value))
+        } else {
+            Err(format!(
+                "expected a release codename like noble, got '{value}'"
+            ))
+        }
+    }
+}
+
+/// TeX Live package for `--latex-packages`, e.g. `fancyhdr`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LatexPackage(pub String);
+
+impl FromStr for LatexPackage {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        if value.starts_with(|c: char| c.is_ascii_alphanumeric())
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        {
+            Ok(Self(value.to_string()))
+        } else {
+            Err(format!("expected a TeX Live package name, got '{value}'"))
+        }
+    }
+}
+
+/// File format of a `--report`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Human-readable Markdown.
+    Markdown,
+    /// Structured JSON for tooling.
+    Json,
+}
+
+/// A `--report` destination, given as `FORMAT=PATH` or a bare `PATH` whose
+/// format is taken from its extension (Markdown unless it ends in `.json`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReportTarget {
+    /// Format to write.
+    pub format: ReportFormat,
+    /// File to write it to.
+    pub path: PathBuf,
+}
+
+impl FromStr for ReportTarget {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let explicit = value.split_once('=').and_then(|(format, path)| {
+            let format = match format.trim().to_ascii_lowercase().as_str() {
+                "md" | "markdown" => ReportFormat::Markdown,
+                "json" => ReportFormat::Json,
+                _ => return None,
+            };
+            Some((format, path))
+        });
+        let (format, path) = match explicit {
+            Some(pair) => pair,
+            None if value.to_ascii_lowercase().ends_with(".json") => (ReportFormat::Json, value),
+            None => (ReportFormat::Markdown, value),
+        };
+        if path.trim().is_empty() {
+            return Err(format!("missing report path in '{value}'"));
+        }
+        Ok(Self {
+            format,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+/// Cutoff for `--since`: only revdeps published on CRAN on or after it are checked.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Since {
+    /// A `YYYY-MM-DD` date.
+    Date(String),
+    /// The date of the last completed run for the same target package.
+    LastRun,
+}
+
+impl FromStr for Since {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "last-run" => Ok(Self::LastRun),
+            date if crate::util::is_iso_date(date) => Ok(Self::Date(date.to_string())),
+            other => Err(format!("expected YYYY-MM-DD or last-run, got '{other}'")),
+        }
+    }
+}
 
 /// Command-line arguments for the `revdeprun` CLI.
+///
+/// Without a subcommand, the arguments of `check` are accepted directly so
+/// `revdeprun <REPOSITORY>` keeps working.
 #[derive(Debug, Parser)]
-#[command(author, version, about = "Provision R and run reverse dependency check end-to-end", long_about = None)]
+#[command(
+    author,
+    version,
+    about = "Provision R and run reverse dependency check end-to-end",
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 pub struct Args {
-    /// Git URL, local directory, or source package tarball (.tar.gz) for the target R package.
-    pub repository: String,
+    /// Workflow to run; defaults to `check`.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Arguments for the default `check` workflow.
+    #[command(flatten)]
+    pub check: Option<CheckArgs>,
+}
+
+impl Args {
+    /// Parses the process arguments into a workflow, treating an invocation
+    /// without a subcommand as `check`. Exits with a usage error when neither
+    /// a subcommand nor a repository is given.
+    pub fn parse_command() -> Command {
+        let args = Self::parse();
+        match (args.command, args.check) {
+            (Some(command), _) => command,
+            (None, Some(check)) => Command::Check(Box::new(check)),
+            (None, None) => Self::command()
+                .error(
+                    ErrorKind::MissingRequiredArgument,
+                    "the following required argument was not provided:\n  <REPOSITORY>",
+                )
+                .exit(),
+        }
+    }
+}
+
+/// Workflows offered by the CLI.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Provision R and run the reverse dependency check of a package (default).
+    Check(Box<CheckArgs>),
+    /// Install R and its tools (Quarto, pandoc, TinyTeX) without checking any package.
+    Provision(ProvisionArgs),
+    /// Report the platform, tools, and host reachability revdeprun sees, then exit.
+    Doctor,
+    /// Remove temporary files and sysreqs records left by earlier runs, then exit.
+    CleanCache(CleanCacheArgs),
+}
+
+/// Arguments for `revdeprun clean-cache`.
+#[derive(Debug, clap::Args)]
+pub struct CleanCacheArgs {
+    /// Workspace the runs used (their --work-dir). Defaults to ./revdeprun-work.
+    #[arg(long, value_name = "DIR")]
+    pub work_dir: Option<PathBuf>,
 
-    /// R version to install (e.g., release, 4.3.3, oldrel-1).
+    /// Only remove entries last modified more than this many days ago.
+    #[arg(long, value_name = "DAYS", default_value_t = 30)]
+    pub older_than: u64,
+}
+
+/// Arguments for `revdeprun provision`.
+#[derive(Debug, clap::Args)]
+pub struct ProvisionArgs {
+    /// R version to install (e.g., release, 4.3.3, oldrel-1), or the path of an existing
+    /// R installation prefix (e.g., /custom/R/4.4.1) to link instead.
     #[arg(long = "r-version", default_value = "release")]
     pub r_version: String,
 
-    /// Number of parallel workers for xfun::rev_check().
+    /// Base URL of a mirror to retry the R installer download from, keeping the
+    /// original path, when the primary CDN fails.
+    #[arg(long, value_name = "URL")]
+    pub installer_mirror: Option<String>,
+
+    /// Extra header for the R version API and installer download requests, as 'Name: Value'.
+    /// Can be repeated.
+    #[arg(long, value_name = "HEADER")]
+    pub http_header: Vec<HttpHeader>,
+
+    /// User-Agent for the R version API and installer download requests, instead of
+    /// revdeprun/VERSION.
+    #[arg(long, value_name = "STRING")]
+    pub user_agent: Option<String>,
+
+    /// Fail when Quarto, pandoc, or TinyTeX cannot be provisioned instead of warning.
+    #[arg(long)]
+    pub strict_tools: bool,
+
+    /// TeX Live packages to install with tlmgr after TinyTeX, so vignette builds do not
+    /// fetch them one by one during checks.
+    #[arg(long, value_name = "PKGS", value_delimiter = ',')]
+    pub latex_packages: Vec<LatexPackage>,
+
+    /// Directory for the R, Rscript, Quarto, and TinyTeX symlinks. It is put first on PATH
+    /// for revdeprun's own commands.
+    #[arg(long, value_name = "DIR", default_value = "/usr/local/bin")]
+    pub bin_dir: PathBuf,
+
+    /// Print the complete output of failed commands instead of truncating long output to
+    /// its first and last lines.
+    #[arg(long)]
+    pub full_output: bool,
+
+    /// Print apt output live while installing R prerequisites and system requirements
+    /// instead of only when a command fails.
+    #[arg(long)]
+    pub verbose_apt: bool,
+}
+
+/// Arguments for `revdeprun check`.
+#[derive(Clone, Debug, clap::Args)]
+pub struct CheckArgs {
+    /// Git URL, short spec (gh:, gl:, or bb:org/pkg), GitHub pull request URL, local
+    /// directory, or source package tarball (.tar.gz, .tar.zst, or .tar) for the target R package.
+    #[arg(
+        required_unless_present = "check_dir",
+        default_value = "",
+        hide_default_value = true
+    )]
+    pub repository: String,
+
+    /// Check the package in DIR in place, with revdep/ results and revdeprun's temporary
+    /// files written under it, instead of giving a repository and using a workspace.
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["repository", "work_dir", "tmpfs_workspace"]
+    )]
+    pub check_dir: Option<PathBuf>,
+
+    /// R version to install (e.g., release, 4.3.3, oldrel-1), or the path of an existing
+    /// R installation prefix (e.g., /custom/R/4.4.1) to link instead. Defaults to the
+    /// version pinned in the repository's .Rversion or renv.lock, or release. Several
+    /// comma-separated versions (e.g., release,oldrel-1) are checked one after another.
+    #[arg(long = "r-version")]
+    pub r_version: Option<String>,
+
+    /// Number of parallel workers for xfun::rev_check(), submodule clones, and tarball extraction.
+    /// Defaults to the number of CPU cores.
     #[arg(long, value_name = "N")]
     pub num_workers: Option<NonZeroUsize>,
 
+    /// Allow --num-workers to exceed the number of available CPUs instead of clamping it.
+    #[arg(long)]
+    pub oversubscribe: bool,
+
+    /// Connections R may open per worker, for R's --max-connections (rounded up to a
+    /// multiple of 128, capped at 4096 and the open-file limit). Raise it for checks that
+    /// open many connections.
+    #[arg(long, value_name = "N", default_value_t = crate::util::DEFAULT_CONNECTIONS_PER_WORKER)]
+    pub connections_per_worker: u64,
+
+    /// Connections R may open on top of those per worker, for R's --max-connections.
+    #[arg(long, value_name = "N", default_value_t = crate::util::DEFAULT_CONNECTIONS_BASE)]
+    pub connections_base: u64,
+
+    /// How to compute the list of reverse dependencies.
+    #[arg(long, value_enum, default_value_t = RevdepsSource::Tools)]
+    pub revdeps_source: RevdepsSource,
+
+    /// Only check revdeps published on CRAN on or after this date (YYYY-MM-DD), or since
+    /// the last completed run with `last-run`.
+    #[arg(long, value_name = "DATE")]
+    pub since: Option<Since>,
+
+    /// Only check revdeps whose names match this regular expression, e.g. '^bioc'.
+    #[arg(long, value_name = "REGEX")]
+    pub pkg_filter: Option<PkgFilter>,
+
+    /// Only check these revdeps, given as comma-separated names or @FILE with one name per
+    /// line (# comments allowed). Repeatable.
+    #[arg(long, value_name = "NAMES", value_delimiter = ',')]
+    pub only: Vec<PackageSpec>,
+
+    /// Skip these revdeps, given as comma-separated names or @FILE with one name per line
+    /// (# comments allowed). Repeatable.
+    #[arg(long, value_name = "NAMES", value_delimiter = ',')]
+    pub exclude: Vec<PackageSpec>,
+
+    /// Stop before installing anything when there are more than N revdeps to check, to
+    /// avoid accidentally launching a huge run.
+    #[arg(long, value_name = "N")]
+    pub max_revdeps: Option<usize>,
+
+    /// With --max-revdeps, check the first N revdeps (alphabetically) instead of stopping.
+    #[arg(long, requires = "max_revdeps")]
+    pub truncate: bool,
+
+    /// CRAN-like repository (https:// or file://) to resolve and install packages from.
+    /// Dependencies are installed from source when set. Defaults to Posit Package Manager.
+    #[arg(long, value_name = "URL")]
+    pub cran_mirror: Option<String>,
+
+    /// Credentials for an authenticated --cran-mirror. Defaults to the mirror host's
+    /// entry in ~/.netrc. Never printed.
+    #[arg(long, value_name = "USER:PASSWORD", requires = "cran_mirror")]
+    pub cran_auth: Option<CranAuth>,
+
+    /// Time a small request to each of these comma-separated CRAN mirrors and use the
+    /// fastest one that answers as --cran-mirror.
+    #[arg(
+        long,
+        value_name = "URL,...",
+        value_delimiter = ',',
+        conflicts_with = "cran_mirror"
+    )]
+    pub auto_mirror: Vec<String>,
+
+    /// Bioconductor release to install Bioconductor dependencies of revdeps from,
+    /// e.g. 3.21. Defaults to the release BiocManager pairs with the R version.
+    #[arg(long, value_name = "X.Y")]
+    pub bioc_version: Option<BiocVersion>,
+
+    /// Check first the revdeps that import exported functions defined in R files changed
+    /// since this git ref. All revdeps are still checked.
+    #[arg(long, value_name = "REF")]
+    pub touched_only: Option<String>,
+
     /// Optional workspace directory where temporary files are created.
     #[arg(long)]
     pub work_dir: Option<PathBuf>,
 
+    /// Run in a RAM-backed workspace: --work-dir (default /dev/shm/revdeprun) is used if it
+    /// is already tmpfs, otherwise a tmpfs is mounted on it with sudo. Warns when memory
+    /// looks insufficient for the number of revdeps.
+    #[arg(long)]
+    pub tmpfs_workspace: bool,
+
+    /// Number of times to retry `git clone` after transient network failures.
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    pub clone_retries: u32,
+
+    /// Number of commits to fetch when cloning a remote repository, or `full` (or 0) for
+    /// the complete history with tags, e.g. for builds that run `git describe`.
+    #[arg(long, value_name = "N", default_value = "1")]
+    pub clone_depth: CloneDepth,
+
+    /// Keep the sources extracted from a package tarball after the run instead of
+    /// removing them. The revdep/ library and the check results are kept either way.
+    #[arg(long)]
+    pub keep_tarball_extraction: bool,
+
+    /// Check that all required hosts are reachable before doing any work.
+    #[arg(long)]
+    pub preflight: bool,
+
+    /// Allow cloning into the current directory even when it already contains files, or
+    /// into a --work-dir named after the repository or holding an R package.
+    #[arg(long)]
+    pub allow_dirty_workspace: bool,
+
+    /// Exit with an error instead of successfully when the package has no reverse
+    /// dependencies, e.g. to catch a misconfigured target in CI.
+    #[arg(long)]
+    pub fail_if_empty: bool,
+
+    /// Package type for installing revdep dependencies. Development R and --cran-mirror
+    /// always install from source.
+    #[arg(long, value_enum, default_value_t = CranType::Binary)]
+    pub cran_type: CranType,
+
+    /// Install packages with base R's install.packages() or with pak::pkg_install(). pak
+    /// also installs system requirements, replacing the separate sysreqs phase.
+    #[arg(long, value_enum, default_value_t = Installer::Base)]
+    pub installer: Installer,
+
+    /// Ubuntu codename of the Posit binary repository, e.g. noble, instead of the detected
+    /// one. Useful on a new release Posit has no binaries for yet.
+    #[arg(long, value_name = "CODENAME")]
+    pub binary_codename: Option<Codename>,
+
+    /// Skip installing Suggests dependencies of the target and its revdeps for a faster,
+    /// rougher check.
+    #[arg(long)]
+    pub no_suggests_install: bool,
+
+    /// Dependencies of the target package itself to install, independent of those of its
+    /// revdeps. Defaults to all, or hard with --no-suggests-install.
+    #[arg(long, value_enum, value_name = "KIND")]
+    pub target_deps: Option<TargetDeps>,
+
+    /// Skip re-building revdep vignettes during checks (R CMD check --no-build-vignettes)
+    /// and skip provisioning Quarto and TinyTeX, for a faster check.
+    #[arg(long)]
+    pub no_vignettes: bool,
+
+    /// Do not build or install the target package; check the revdeps with plain R CMD check
+    /// against the version already installed, without comparing to the CRAN release.
+    #[arg(long, conflicts_with = "isolate_target_deps")]
+    pub no_self: bool,
+
+    /// Compare against the CRAN release of the target and report only the revdeps that newly
+    /// break with your changes; fails when the target is not on CRAN.
+    #[arg(long, conflicts_with = "no_self")]
+    pub only_changed_since_cran: bool,
+
+    /// Check like CRAN's incoming checks: only strong revdeps (Depends, Imports, LinkingTo),
+    /// compared against the CRAN release of the target as with --only-changed-since-cran,
+    /// failing on warnings as with --fail-on warning.
+    #[arg(long, conflicts_with_all = ["no_self", "fail_on"])]
+    pub cran_mode: bool,
+
+    /// Install the target's own dependencies into a separate library (revdep/target-library)
+    /// ahead of the shared one, so the target is not built against versions pulled in by revdeps.
+    #[arg(long)]
+    pub isolate_target_deps: bool,
+
+    /// Number of times to retry installing packages that are still missing after the first pass.
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    pub install_retries: u32,
+
+    /// Keep the existing revdep library even when it was built against a different
+    /// version of the target package.
+    #[arg(long)]
+    pub reuse_library: bool,
+
+    /// Unpack this archive of a prebuilt revdep/library (.tar.gz, .tar.zst, or .tar of the
+    /// library contents) before installing, so only missing packages are installed.
+    #[arg(long, value_name = "PATH")]
+    pub library_cache: Option<PathBuf>,
+
+    /// Delete the revdep library (but not check results) before installing, for a
+    /// from-scratch install.
+    #[arg(long, conflicts_with = "reuse_library")]
+    pub clean_library: bool,
+
+    /// Base URL of a mirror to retry the R installer download from, keeping the
+    /// original path, when the primary CDN fails.
+    #[arg(long, value_name = "URL")]
+    pub installer_mirror: Option<String>,
+
+    /// Extra header for the R version API and installer download requests, as 'Name: Value'.
+    /// Can be repeated.
+    #[arg(long, value_name = "HEADER")]
+    pub http_header: Vec<HttpHeader>,
+
+    /// User-Agent for the R version API and installer download requests, instead of
+    /// revdeprun/VERSION.
+    #[arg(long, value_name = "STRING")]
+    pub user_agent: Option<String>,
+
+    /// Fail when Quarto, pandoc, or TinyTeX cannot be provisioned instead of warning.
+    #[arg(long)]
+    pub strict_tools: bool,
+
+    /// TeX Live packages to install with tlmgr after TinyTeX, so vignette builds do not
+    /// fetch them one by one during checks.
+    #[arg(
+        long,
+        value_name = "PKGS",
+        value_delimiter = ',',
+        conflicts_with = "no_vignettes"
+    )]
+    pub latex_packages: Vec<LatexPackage>,
+
+    /// Directory for the R, Rscript, Quarto, and TinyTeX symlinks. It is put first on PATH
+    /// for revdeprun's own commands.
+    #[arg(long, value_name = "DIR", default_value = "/usr/local/bin")]
+    pub bin_dir: PathBuf,
+
+    /// Platform to resolve system requirements for (e.g., ubuntu-22.04). Defaults to
+    /// the detected distribution.
+    #[arg(long, value_name = "PLATFORM")]
+    pub sysreqs_platform: Option<String>,
+
+    /// Use system requirements from this JSON file (with install_scripts and post_install
+    /// fields) instead of resolving them with pak.
+    #[arg(long, value_name = "PATH")]
+    pub sysreqs_file: Option<PathBuf>,
+
+    /// Print the resolved system requirement commands and the revdeps they cover.
+    #[arg(long)]
+    pub print_sysreqs: bool,
+
+    /// Print apt output live while installing R prerequisites and system requirements
+    /// instead of only when a command fails.
+    #[arg(long)]
+    pub verbose_apt: bool,
+
+    /// Resolve and print system requirements, then exit without installing them or running checks.
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Save the generated sysreqs.R, target-deps.R, install.R, and run.R scripts to DIR
+    /// before running them, for inspection or manual re-runs.
+    #[arg(long, value_name = "DIR")]
+    pub dump_scripts: Option<PathBuf>,
+
+    /// Install system requirements for the revdeps, then exit without installing
+    /// packages or running checks.
+    #[arg(long, conflicts_with_all = ["dry_run", "skip_sysreqs"])]
+    pub only_sysreqs: bool,
+
+    /// Skip resolving and installing system requirements, e.g. on an image
+    /// provisioned with --only-sysreqs.
+    #[arg(long)]
+    pub skip_sysreqs: bool,
+
+    /// Resume an earlier run in the same workspace at this phase, reusing its clone and
+    /// revdep list (and for check, its revdep library) instead of redoing earlier phases.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "PHASE",
+        conflicts_with_all = ["dry_run", "clean_library"]
+    )]
+    pub continue_from: Option<ContinueFrom>,
+
+    /// Rebuild the summary, reports, cran-comments section, and history record from the
+    /// revdep/ results of an earlier run in the same workspace, without installing or
+    /// checking anything.
+    #[arg(
+        long,
+        conflicts_with_all = ["dry_run", "only_sysreqs", "continue_from"]
+    )]
+    pub summary_only: bool,
+
+    /// Shell script to run with sh from the package directory after system requirements
+    /// are installed and before revdeps are installed, e.g. to create config files.
+    #[arg(long, value_name = "PATH")]
+    pub pre_check_script: Option<PathBuf>,
+
+    /// Shell script to run with sh from the package directory after the check and the
+    /// reports are written, e.g. to upload results. The results directory is passed as its
+    /// first argument and in REVDEPRUN_RESULTS_DIR. It runs even when --fail-on then fails
+    /// the run, but not when the check itself fails. A failure is reported as a warning
+    /// unless --post-check-fatal.
+    #[arg(long, value_name = "PATH")]
+    pub post_check_script: Option<PathBuf>,
+
+    /// Fail the run when the --post-check-script fails.
+    #[arg(long, requires = "post_check_script")]
+    pub post_check_fatal: bool,
+
+    /// Write the sorted list of reverse dependencies this run considers, one per line,
+    /// to this file.
+    #[arg(long, value_name = "PATH")]
+    pub revdeps_out: Option<PathBuf>,
+
+    /// Write a report summarizing the check results, as FORMAT=PATH with FORMAT md or
+    /// json, or as a PATH whose .json or other extension picks the format. Repeatable.
+    #[arg(long, value_name = "[FORMAT=]PATH")]
+    pub report: Vec<ReportTarget>,
+
+    /// Append a "## revdepcheck results" section summarizing the check, in the format
+    /// CRAN reviewers expect, to this cran-comments.md.
+    #[arg(long, value_name = "PATH")]
+    pub cran_comments: Option<PathBuf>,
+
+    /// Append per-revdep results of this run to a SQLite database for tracking revdep
+    /// health over time.
+    #[arg(long, value_name = "PATH")]
+    pub db: Option<PathBuf>,
+
+    /// Set an environment variable for the R processes that resolve system
+    /// requirements, install revdeps, and run checks. Repeatable.
+    #[arg(long = "env", value_name = "KEY=VALUE")]
+    pub env: Vec<EnvVar>,
+
+    /// Stop the check as soon as any revdep reports an ERROR and report which one failed.
+    #[arg(long)]
+    pub fail_fast: bool,
+
+    /// Comma-separated check outcomes that make revdeprun exit with a non-zero status: the
+    /// lowest severity (note, warning, or error) that counts, and timeout.
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "error")]
+    pub fail_on: Vec<FailOn>,
+
+    /// Print the complete output of failed commands instead of truncating long output to
+    /// its first and last lines.
+    #[arg(long)]
+    pub full_output: bool,
+
+    /// Print only the final result (and any error), without progress spinners, messages,
+    /// or check output.
+    #[arg(long)]
+    pub quiet: bool,
+
+    /// Emit machine-readable progress events (task start/finish/fail, phase transitions,
+    /// messages) to stdout. Check output moves to stderr.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub events: Option<EventFormat>,
+
+    /// Write --events to this file (e.g. a named pipe) instead of stdout.
+    #[arg(long, value_name = "PATH", requires = "events")]
+    pub events_file: Option<PathBuf>,
+
+    /// Print a timestamped "still working" line at this interval (e.g. 5m) while
+    /// dependencies install and checks run, so CI does not stop the job for inactivity.
+    #[arg(long, value_name = "DURATION")]
+    pub heartbeat: Option<Interval>,
+
     /// Skip installing R and reuse the system-wide installation.
     #[arg(long)]
     pub skip_r_install: bool,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The fenced block following `heading` in the README.
+    fn readme_block(heading: &str) -> &'static str {
+        let readme = include_str!("../README.md");
+        let section = &readme[readme.find(heading).expect("README section")..];
+        let start = section.find("```\n").expect("code block") + 4;
+        let end = start + section[start..].find("```\n").expect("end of code block");
+        &section[start..end]
+    }
+
+    /// Output of `revdeprun <args> --help` without its leading `about`
+    /// paragraph and with trailing whitespace removed, as it appears in the
+    /// README.
+    fn help_text(args: &[&str]) -> String {
+        let argv = ["revdeprun"].iter().chain(args).chain(&["--help"]);
+        let err = Args::command()
+            .try_get_matches_from(argv)
+            .expect_err("--help exits");
+        let help = err.render().to_string();
+        let usage = help.find("Usage:").expect("usage line");
+        help[usage..]
+            .lines()
+            .map(str::trim_end)
+            .map(|line| format!("{line}\n"))
+            .collect()
+    }
+
+    #[test]
+    fn readme_mirrors_help() {
+        assert_eq!(readme_block("### Command-line options"), help_text(&[]));
+        assert_eq!(
+            readme_block("### Provisioning only"),
+            help_text(&["provision"])
+        );
+    }
+
+    #[test]
+    fn parses_env_vars_and_redacts_values() {
+        let var: EnvVar = "API_KEY=s3cr=t".parse().expect("valid spec");
+        assert_eq!(var.key, "API_KEY");
+        assert_eq!(var.value, "s3cr=t");
+        assert_eq!(format!("{var:?}"), "API_KEY=<redacted>");
+
+        assert!("NO_VALUE".parse::<EnvVar>().is_err());
+        assert!("=value".parse::<EnvVar>().is_err());
+        assert_eq!("EMPTY=".parse::<EnvVar>().expect("empty value").value, "");
+
+        let header: HttpHeader = "X-Api-Token:  s3cret ".parse().expect("valid header");
+        assert_eq!(header.name, "X-Api-Token");
+        assert_eq!(header.value, "s3cret");
+        assert_eq!(format!("{header:?}"), "X-Api-Token: <redacted>");
+        assert!("NoColon".parse::<HttpHeader>().is_err());
+        assert!("Bad Name: value".parse::<HttpHeader>().is_err());
+        assert!("X-Ok: line\nbreak".parse::<HttpHeader>().is_err());
+    }
+
+    #[test]
+    fn parses_subcommands_and_bare_repository() {
+        let args = Args::try_parse_from(["revdeprun", "provision", "--r-version", "4.4.1"])
+            .expect("provision");
+        assert!(matches!(
+            args.command,
+            Some(Command::Provision(ProvisionArgs { ref r_version, .. })) if r_version == "4.4.1"
+        ));
+
+        let args = Args::try_parse_from(["revdeprun", "check", "ggsci", "--dry-run"])
+            .expect("check subcommand");
+        assert!(matches!(args.command, Some(Command::Check(ref check)) if check.dry_run));
+
+        let args = Args::try_parse_from(["revdeprun", "ggsci", "--dry-run"]).expect("bare");
+        assert!(args.command.is_none());
+        assert_eq!(args.check.expect("check args").repository, "ggsci");
+
+        assert!(Args::try_parse_from(["revdeprun", "provision", "ggsci"]).is_err());
+
+        let args = Args::try_parse_from(["revdeprun", "--check-dir", "."]).expect("check dir");
+        let check = args.check.expect("check args");
+        assert_eq!(check.check_dir, Some(PathBuf::from(".")));
+        assert!(Args::try_parse_from(["revdeprun", "check"]).is_err());
+        assert!(Args::try_parse_from(["revdeprun", "ggsci", "--check-dir", "."]).is_err());
+        assert!(
+            Args::try_parse_from(["revdeprun", "--check-dir", ".", "--work-dir", "/tmp/w"])
+                .is_err()
+        );
+
+        let args = Args::try_parse_from(["revdeprun", "ggsci", "--cran-mode"]).expect("cran mode");
+        assert!(args.check.expect("check args").cran_mode);
+        assert!(
+            Args::try_parse_from(["revdeprun", "ggsci", "--cran-mode", "--fail-on", "note"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn parses_clone_depths() {
+        assert_eq!("1".parse::<CloneDepth>(), Ok(CloneDepth::Shallow(1)));
+        assert_eq!("50".parse::<CloneDepth>(), Ok(CloneDepth::Shallow(50)));
+        assert_eq!("0".parse::<CloneDepth>(), Ok(CloneDepth::Full));
+        assert_eq!("full".parse::<CloneDepth>(), Ok(CloneDepth::Full));
+        assert!("deep".parse::<CloneDepth>().is_err());
+    }
+
+    #[test]
+    fn parses_since_cutoffs() {
+        assert_eq!("last-run".parse::<Since>(), Ok(Since::LastRun));
+        assert_eq!(
+            "2025-06-01".parse::<Since>(),
+            Ok(Since::Date("2025-06-01".to_string()))
+        );
+        assert!("last-week".parse::<Since>().is_err());
+    }
+
+    #[test]
+    fn validates_pkg_filter_patterns() {
+        let filter = "^bioc".parse::<PkgFilter>().expect("valid pattern");
+        assert_eq!(filter.as_str(), "^bioc");
+        assert!(filter.is_match("biocthis"));
+        assert!(!filter.is_match("ggbio"));
+        let err = "gg(".parse::<PkgFilter>().expect_err("unclosed group");
+        assert!(err.starts_with("invalid regular expression"));
+    }
+
+    #[test]
+    fn validates_bioc_versions() {
+        assert_eq!(
+            "3.21".parse::<BiocVersion>(),
+            Ok(BiocVersion("3.21".to_string()))
+        );
+        for invalid in ["3", "3.", "release", "3.21.1", "v3.21"] {
+            assert!(invalid.parse::<BiocVersion>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn validates_codenames() {
+        assert_eq!(
+            "Noble".parse::<Codename>(),
+            Ok(Codename("noble".to_string()))
+        );
+        assert!("".parse::<Codename>().is_err());
+        assert!("../noble".parse::<Codename>().is_err());
+    }
+
+    #[test]
+    fn validates_latex_packages() {
+        assert_eq!(
+            " collection-fontsrecommended ".parse::<LatexPackage>(),
+            Ok(LatexPackage("collection-fontsrecommended".to_string()))
+        );
+        for invalid in ["", "-opt", "fancy hdr", "pkg;rm"] {
+            assert!(invalid.parse::<LatexPackage>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn parses_report_targets() {
+        let target = |format, path: &str| {
+            Ok(ReportTarget {
+                format,
+                path: PathBuf::from(path),
+            })
+        };
+        assert_eq!(
+            "json=out/report.json".parse(),
+            target(ReportFormat::Json, "out/report.json")
+        );
+        assert_eq!(
+            "md=report.txt".parse(),
+            target(ReportFormat::Markdown, "report.txt")
+        );
+        assert_eq!(
+            "revdeps.JSON".parse(),
+            target(ReportFormat::Json, "revdeps.JSON")
+        );
+        assert_eq!(
+            "a=b/report.md".parse(),
+            target(ReportFormat::Markdown, "a=b/report.md")
+        );
+        assert!("json=".parse::<ReportTarget>().is_err());
+    }
+
+    #[test]
+    fn parses_package_specs() {
+        assert_eq!(
+            "data.table".parse(),
+            Ok(PackageSpec::Name("data.table".to_string()))
+        );
+        assert_eq!(
+            "@known-bad.txt".parse(),
+            Ok(PackageSpec::File(PathBuf::from("known-bad.txt")))
+        );
+        assert!("@".parse::<PackageSpec>().is_err());
+        assert!("pkg name".parse::<PackageSpec>().is_err());
+    }
+
+    #[test]
+    fn parses_intervals() {
+        let secs = |secs| Ok(Interval(Duration::from_secs(secs)));
+        assert_eq!("45".parse::<Interval>(), secs(45));
+        assert_eq!("30s".parse::<Interval>(), secs(30));
+        assert_eq!("5m".parse::<Interval>(), secs(300));
+        assert_eq!("1h".parse::<Interval>(), secs(3600));
+        assert!("0m".parse::<Interval>().is_err());
+        assert!("5 days".parse::<Interval>().is_err());
+    }
+}
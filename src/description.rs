@@ -0,0 +1,141 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{Context, Result};
+
+/// Fields parsed from an R package `DESCRIPTION` file.
+#[derive(Clone, Debug, Default)]
+pub struct Description {
+    fields: BTreeMap<String, String>,
+}
+
+impl Description {
+    /// Reads and parses the `DESCRIPTION` file at the root of `repo_path`.
+    pub fn read(repo_path: &Path) -> Result<Self> {
+        let path = repo_path.join("DESCRIPTION");
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read package DESCRIPTION at {}", path.display()))?;
+        Ok(Self::parse(&contents))
+    }
+
+    /// Parses DCF content, joining indented continuation lines onto the
+    /// preceding field. Windows (`\r\n`) line endings are accepted, since
+    /// [`str::lines`] strips them.
+    pub fn parse(contents: &str) -> Self {
+        let mut fields = BTreeMap::new();
+        let mut current: Option<String> = None;
+
+        for line in contents.lines() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if line.starts_with([' ', '\t']) {
+                if let Some(value) = current.as_ref().and_then(|key| fields.get_mut(key)) {
+                    let value: &mut String = value;
+                    if !value.is_empty() {
+                        value.push('\n');
+                    }
+                    value.push_str(line.trim());
+                }
+                continue;
+            }
+            if let Some((key, value)) = line.split_once(':') {
+                let key = key.trim().to_string();
+                fields.insert(key.clone(), value.trim().to_string());
+                current = Some(key);
+            }
+        }
+
+        Self { fields }
+    }
+
+    /// Returns the value of `field`, if present and non-empty.
+    pub fn get(&self, field: &str) -> Option<&str> {
+        self.fields
+            .get(field)
+            .map(String::as_str)
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Returns the `Package` field.
+    pub fn package(&self) -> Option<&str> {
+        self.get("Package")
+    }
+
+    /// Returns the `Version` field.
+    pub fn version(&self) -> Option<&str> {
+        self.get("Version")
+    }
+}
+
+/// Reads the `Package` field from the DESCRIPTION file under `repo_path`.
+pub fn read_package_name(repo_path: &Path) -> Result<String> {
+    Description::read(repo_path)?
+        .package()
+        .map(str::to_string)
+        .with_context(|| {
+            format!(
+                "could not find a non-empty Package field in {}",
+                repo_path.join("DESCRIPTION").display()
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn reads_package_name_from_description() {
+        let dir = tempdir().expect("tempdir");
+        let description_path = dir.path().join("DESCRIPTION");
+        let mut file = File::create(&description_path).expect("create DESCRIPTION");
+        writeln!(file, "Package: example").expect("write package");
+        let name = read_package_name(dir.path()).expect("package name");
+        assert_eq!(name, "example");
+
+        fs::write(&description_path, "Package:\nVersion: 1.0.0\n").expect("write DESCRIPTION");
+        let err = read_package_name(dir.path()).expect_err("empty Package field");
+        assert!(
+            err.to_string()
+                .contains("could not find a non-empty Package field")
+        );
+    }
+
+    #[test]
+    fn handles_crlf_line_endings() {
+        let dir = tempdir().expect("tempdir");
+        fs::write(
+            dir.path().join("DESCRIPTION"),
+            "Package: ggsci\r\nVersion: 3.2.0\r\nImports:\r\n    grDevices,\r\n    scales\r\n",
+        )
+        .expect("write DESCRIPTION");
+
+        assert_eq!(
+            read_package_name(dir.path()).expect("package name"),
+            "ggsci"
+        );
+        let description = Description::read(dir.path()).expect("description");
+        assert_eq!(description.package(), Some("ggsci"));
+        assert_eq!(description.version(), Some("3.2.0"));
+        assert_eq!(description.get("Imports"), Some("grDevices,\nscales"));
+    }
+
+    #[test]
+    fn parses_fields_and_continuations() {
+        let description = Description::parse(
+            "Package: ggsci\nVersion: 3.2.0\nDescription: A collection of\n    color palettes.\nImports:\n    grDevices,\n    scales\n",
+        );
+
+        assert_eq!(description.package(), Some("ggsci"));
+        assert_eq!(description.version(), Some("3.2.0"));
+        assert_eq!(
+            description.get("Description"),
+            Some("A collection of\ncolor palettes.")
+        );
+        assert_eq!(description.get("Imports"), Some("grDevices,\nscales"));
+        assert_eq!(description.get("Missing"), None);
+    }
+}
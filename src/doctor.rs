@@ -0,0 +1,146 @@
+use std::fmt::Write as _;
+
+use anyhow::{Context, Result, bail};
+use xshell::{Shell, cmd};
+
+use crate::{preflight, r_version, revdep, util};
+
+/// Tools revdeprun relies on, with whether a run fails without them. The
+/// others are installed by revdeprun itself.
+const TOOLS: &[(&str, bool)] = &[
+    ("git", true),
+    ("sudo", true),
+    ("R", false),
+    ("Rscript", false),
+    ("quarto", false),
+    ("pandoc", false),
+    ("tlmgr", false),
+];
+
+/// One line of the diagnostic report.
+#[derive(Debug)]
+struct Finding {
+    label: String,
+    value: Result<String, String>,
+    required: bool,
+}
+
+impl Finding {
+    fn new(label: impl Into<String>, value: Result<String, String>, required: bool) -> Self {
+        Self {
+            label: label.into(),
+            value,
+            required,
+        }
+    }
+}
+
+/// Prints what revdeprun sees on this machine: platform, CPU-derived
+/// settings, tools on `PATH`, and reachability of the required hosts.
+/// Fails when something a check cannot do without is missing.
+pub fn run() -> Result<()> {
+    let shell = Shell::new().context("failed to initialise shell environment")?;
+    let cpus = num_cpus::get();
+
+    let mut findings = vec![
+        Finding::new("OS", Ok(std::env::consts::OS.to_string()), true),
+        Finding::new(
+            "Platform",
+            r_version::linux_platform().map_err(|err| format!("{err:#}")),
+            true,
+        ),
+        Finding::new(
+            "Ubuntu codename",
+            revdep::detect_ubuntu_codename().map_err(|err| format!("{err:#}")),
+            false,
+        ),
+        Finding::new("CPUs", Ok(cpus.to_string()), true),
+        Finding::new(
+            "Max connections",
+            Ok(util::optimal_max_connections(cpus, util::ConnectionTuning::default()).to_string()),
+            true,
+        ),
+    ];
+    for &(tool, required) in TOOLS {
+        // Root runs commands directly, so sudo is optional there.
+        let required = required && !(tool == "sudo" && util::running_as_root());
+        findings.push(Finding::new(tool, tool_version(&shell, tool), required));
+    }
+    for status in preflight::probe_hosts()? {
+        findings.push(Finding::new(
+            status.host,
+            status.error.map_or(Ok("reachable".to_string()), Err),
+            true,
+        ));
+    }
+
+    print!("{}", render(&findings));
+
+    let problems = findings
+        .iter()
+        .filter(|finding| finding.required && finding.value.is_err())
+        .count();
+    if problems > 0 {
+        bail!("found {problems} problems that would stop a reverse dependency check");
+    }
+    Ok(())
+}
+
+/// First line of `tool --version`, or why it could not run.
+fn tool_version(shell: &Shell, tool: &str) -> Result<String, String> {
+    let output = cmd!(shell, "{tool} --version")
+        .quiet()
+        .ignore_status()
+        .output()
+        .map_err(|_| "not found on PATH".to_string())?;
+    if !output.status.success() {
+        return Err(format!("`{tool} --version` exited with {}", output.status));
+    }
+    // Older R versions print the version banner to stderr.
+    Ok([output.stdout, output.stderr]
+        .iter()
+        .map(|stream| {
+            let text = String::from_utf8_lossy(stream);
+            text.lines().next().unwrap_or_default().trim().to_string()
+        })
+        .find(|line| !line.is_empty())
+        .unwrap_or_else(|| "found".to_string()))
+}
+
+fn render(findings: &[Finding]) -> String {
+    let width = findings
+        .iter()
+        .map(|finding| finding.label.len())
+        .max()
+        .unwrap_or(0);
+    let mut out = String::new();
+    for finding in findings {
+        let (mark, value) = match &finding.value {
+            Ok(value) => ("ok", value.as_str()),
+            Err(error) if finding.required => ("FAIL", error.as_str()),
+            Err(error) => ("warn", error.as_str()),
+        };
+        let _ = writeln!(out, "{mark:>4}  {:<width$}  {value}", finding.label);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_aligned_findings_with_severity() {
+        let findings = vec![
+            Finding::new("git", Ok("git version 2.43.0".to_string()), true),
+            Finding::new("quarto", Err("not found on PATH".to_string()), false),
+            Finding::new("github.com", Err("timed out".to_string()), true),
+        ];
+        assert_eq!(
+            render(&findings),
+            "  ok  git         git version 2.43.0\n\
+             warn  quarto      not found on PATH\n\
+             FAIL  github.com  timed out\n"
+        );
+    }
+}
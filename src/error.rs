@@ -0,0 +1,97 @@
+use std::fmt;
+
+/// Exit code for a run whose checks found no problems selected by `--fail-on`.
+pub const EXIT_OK: u8 = 0;
+/// Exit code when revdeprun itself could not finish, e.g. invalid arguments,
+/// workspace or toolchain setup, dependency installation, or a failed
+/// `xfun::rev_check()` invocation.
+pub const EXIT_SETUP_FAILED: u8 = 2;
+/// Exit code when the checks ran and found revdep problems selected by `--fail-on`.
+pub const EXIT_REVDEP_PROBLEMS: u8 = 3;
+/// Exit code when the target package itself failed to build or install, so no
+/// revdep could be checked against it.
+pub const EXIT_TARGET_FAILED: u8 = 4;
+
+/// Errors that carry a dedicated exit code.
+///
+/// Any other error returned by [`crate::run`] is treated as an infrastructure
+/// failure and maps to [`EXIT_SETUP_FAILED`].
+#[derive(Debug)]
+pub enum RevdeprunError {
+    /// The checks completed, but these revdeps (with their status labels)
+    /// ended with outcomes selected by `--fail-on`.
+    RevdepProblems(Vec<String>),
+    /// The target package (named here) failed to build or install before any
+    /// revdep was checked.
+    TargetBuildFailed(String),
+}
+
+impl RevdeprunError {
+    /// Process exit code for this error.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::RevdepProblems(_) => EXIT_REVDEP_PROBLEMS,
+            Self::TargetBuildFailed(_) => EXIT_TARGET_FAILED,
+        }
+    }
+}
+
+impl fmt::Display for RevdeprunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RevdepProblems(failures) => write!(
+                f,
+                "{} reverse dependencies failed their checks: {}",
+                failures.len(),
+                failures.join(", ")
+            ),
+            Self::TargetBuildFailed(package) => write!(
+                f,
+                "target package {package} failed to build; fix it before checking its reverse dependencies"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RevdeprunError {}
+
+/// Maps an error returned by [`crate::run`] to the process exit code.
+pub fn exit_code(error: &anyhow::Error) -> u8 {
+    error
+        .downcast_ref::<RevdeprunError>()
+        .map(RevdeprunError::exit_code)
+        .unwrap_or(EXIT_SETUP_FAILED)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{Context, anyhow};
+
+    #[test]
+    fn maps_errors_to_exit_codes() {
+        let problems = anyhow::Error::new(RevdeprunError::RevdepProblems(vec![
+            "pkgA (Failed)".to_string(),
+        ]));
+        assert_eq!(exit_code(&problems), EXIT_REVDEP_PROBLEMS);
+        assert_eq!(
+            problems.to_string(),
+            "1 reverse dependencies failed their checks: pkgA (Failed)"
+        );
+
+        let wrapped = Err::<(), _>(RevdeprunError::RevdepProblems(Vec::new()))
+            .context("while summarising")
+            .unwrap_err();
+        assert_eq!(exit_code(&wrapped), EXIT_REVDEP_PROBLEMS);
+
+        assert_eq!(exit_code(&anyhow!("failed to clone")), EXIT_SETUP_FAILED);
+
+        let target = anyhow::Error::new(RevdeprunError::TargetBuildFailed("ggsci".to_string()));
+        assert_eq!(exit_code(&target), EXIT_TARGET_FAILED);
+        assert!(
+            target
+                .to_string()
+                .starts_with("target package ggsci failed to build")
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+/// Machine-readable progress event written as one JSON object per line.
+#[derive(Debug, Serialize)]
+pub struct Event<'a> {
+    /// `phase`, `task`, or `message`.
+    pub event: &'a str,
+    /// Pipeline phase active when the event was emitted.
+    pub phase: &'a str,
+    /// `started`, `finished`, `failed`, `cancelled`, or `info`.
+    pub status: &'a str,
+    /// Human readable text, matching what the terminal shows.
+    pub message: &'a str,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+}
+
+/// Destination for NDJSON progress events.
+pub struct EventSink {
+    writer: Mutex<Box<dyn Write + Send>>,
+    to_stdout: bool,
+}
+
+impl EventSink {
+    /// Writes events to stdout.
+    pub fn stdout() -> Self {
+        Self {
+            writer: Mutex::new(Box::new(io::stdout())),
+            to_stdout: true,
+        }
+    }
+
+    /// Writes events to `path`, e.g. a named pipe read by a front-end.
+    pub fn file(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("failed to open event stream {}", path.display()))?;
+        Ok(Self {
+            writer: Mutex::new(Box::new(file)),
+            to_stdout: false,
+        })
+    }
+
+    /// Whether events share stdout with other output.
+    pub fn is_stdout(&self) -> bool {
+        self.to_stdout
+    }
+
+    /// Emits a single event. Write errors are ignored so a closed reader never
+    /// aborts the run.
+    pub fn emit(&self, event: &str, phase: &str, status: &str, message: &str) {
+        let line = render(&Event {
+            event,
+            phase,
+            status,
+            message,
+            timestamp_ms: now_ms(),
+        });
+        if let Ok(mut writer) = self.writer.lock() {
+            let _ = writeln!(writer, "{line}");
+            let _ = writer.flush();
+        }
+    }
+}
+
+fn render(event: &Event<'_>) -> String {
+    serde_json::to_string(event).unwrap_or_default()
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_one_json_object_per_event() {
+        let line = render(&Event {
+            event: "task",
+            phase: "revdeps",
+            status: "failed",
+            message: "Listing \"ggsci\" revdeps\nfailed",
+            timestamp_ms: 1_700_000_000_000,
+        });
+        assert!(!line.contains('\n'));
+        let value: serde_json::Value = serde_json::from_str(&line).expect("valid JSON");
+        assert_eq!(value["event"], "task");
+        assert_eq!(value["phase"], "revdeps");
+        assert_eq!(value["status"], "failed");
+        assert_eq!(value["message"], "Listing \"ggsci\" revdeps\nfailed");
+        assert_eq!(value["timestamp_ms"], 1_700_000_000_000_u64);
+    }
+}
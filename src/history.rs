@@ -0,0 +1,120 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use rusqlite::{Connection, params};
+
+use crate::summary::CheckSummary;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS revdep_results (
+    run_id INTEGER NOT NULL,
+    run_at TEXT NOT NULL,
+    target TEXT NOT NULL,
+    target_version TEXT,
+    r_version TEXT NOT NULL,
+    package TEXT NOT NULL,
+    status TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS revdep_results_target_run
+    ON revdep_results (target, run_id);
+";
+
+/// Appends one row per checked revdep in `summary` to the SQLite database at
+/// `path`, creating it on first use. All rows of a run share a new `run_id`.
+pub fn record(path: &Path, summary: &CheckSummary, r_version: &str, run_at: &str) -> Result<i64> {
+    let mut connection = Connection::open(path)
+        .with_context(|| format!("failed to open results database {}", path.display()))?;
+    connection
+        .execute_batch(SCHEMA)
+        .context("failed to create the results schema")?;
+
+    let transaction = connection.transaction()?;
+    let run_id: i64 = transaction.query_row(
+        "SELECT COALESCE(MAX(run_id), 0) + 1 FROM revdep_results",
+        [],
+        |row| row.get(0),
+    )?;
+    {
+        let mut insert = transaction.prepare(
+            "INSERT INTO revdep_results
+                 (run_id, run_at, target, target_version, r_version, package, status)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )?;
+        for result in &summary.results {
+            insert.execute(params![
+                run_id,
+                run_at,
+                summary.package,
+                summary.version,
+                r_version,
+                result.package,
+                result.status.key(),
+            ])?;
+        }
+    }
+    transaction
+        .commit()
+        .with_context(|| format!("failed to write results to {}", path.display()))?;
+    Ok(run_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::summary::{CheckStatus, PackageResult};
+
+    #[test]
+    fn appends_runs_and_finds_new_failures() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("revdeps.sqlite");
+        let summary = |status_b| CheckSummary {
+            package: "ggsci".to_string(),
+            version: Some("3.2.0".to_string()),
+            results: vec![
+                PackageResult {
+                    package: "pkgA".to_string(),
+                    status: CheckStatus::Ok,
+                    excerpt: None,
+                    severity: None,
+                },
+                PackageResult {
+                    package: "pkgB".to_string(),
+                    status: status_b,
+                    excerpt: None,
+                    severity: None,
+                },
+            ],
+        };
+
+        let first = record(
+            &path,
+            &summary(CheckStatus::Ok),
+            "4.5.1",
+            "2025-06-01T00:00:00Z",
+        )
+        .expect("first run");
+        let second = record(
+            &path,
+            &summary(CheckStatus::Failed),
+            "4.5.1",
+            "2025-06-02T00:00:00Z",
+        )
+        .expect("second run");
+        assert_eq!((first, second), (1, 2));
+
+        let connection = Connection::open(&path).expect("open");
+        let newly_failing: Vec<String> = connection
+            .prepare(
+                "SELECT cur.package FROM revdep_results cur
+                 JOIN revdep_results prev
+                   ON prev.package = cur.package AND prev.run_id = cur.run_id - 1
+                 WHERE cur.run_id = 2 AND cur.status = 'failed' AND prev.status = 'ok'",
+            )
+            .expect("prepare")
+            .query_map([], |row| row.get(0))
+            .expect("query")
+            .collect::<Result<_, _>>()
+            .expect("rows");
+        assert_eq!(newly_failing, vec!["pkgB"]);
+    }
+}
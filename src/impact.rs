@@ -0,0 +1,98 @@
+use std::{collections::BTreeSet, fs, path::Path};
+
+use anyhow::{Context, Result, bail};
+use xshell::{Shell, cmd};
+
+/// Returns the exported functions defined in R files that changed since
+/// `git_ref` in the repository at `repo_path`.
+///
+/// This is a cheap heuristic: every exported function defined in a touched
+/// file counts as changed, whether or not its own lines were edited.
+pub fn touched_exports(shell: &Shell, repo_path: &Path, git_ref: &str) -> Result<Vec<String>> {
+    let _dir_guard = shell.push_dir(repo_path);
+    let output = cmd!(shell, "git diff --name-only {git_ref} -- R")
+        .quiet()
+        .ignore_status()
+        .output()
+        .context("failed to run git diff")?;
+    if !output.status.success() {
+        bail!(
+            "git diff against '{git_ref}' failed in {}: {}",
+            repo_path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let mut defined = BTreeSet::new();
+    for file in String::from_utf8_lossy(&output.stdout).lines() {
+        // Deleted files no longer define anything we could prioritise on.
+        if let Ok(source) = fs::read_to_string(repo_path.join(file.trim())) {
+            defined.extend(defined_functions(&source));
+        }
+    }
+
+    let namespace = fs::read_to_string(repo_path.join("NAMESPACE")).unwrap_or_default();
+    let exports = namespace_exports(&namespace);
+    Ok(defined
+        .into_iter()
+        .filter(|name| exports.contains(name))
+        .collect())
+}
+
+/// Names assigned a `function` at the start of a line, e.g. `foo <- function(`.
+fn defined_functions(source: &str) -> Vec<String> {
+    source
+        .lines()
+        .filter_map(|line| {
+            let (name, rest) = line.split_once("<-").or_else(|| line.split_once('='))?;
+            let name = name.trim().trim_matches('`');
+            let is_identifier = !name.is_empty()
+                && !line.starts_with([' ', '\t'])
+                && name
+                    .chars()
+                    .all(|ch| ch.is_ascii_alphanumeric() || ch == '.' || ch == '_');
+            (is_identifier && rest.trim_start().starts_with("function")).then(|| name.to_string())
+        })
+        .collect()
+}
+
+/// Names listed in `export(...)` directives of a NAMESPACE file.
+fn namespace_exports(namespace: &str) -> BTreeSet<String> {
+    let mut exports = BTreeSet::new();
+    let mut rest = namespace;
+    while let Some(start) = rest.find("export(") {
+        let after = &rest[start + "export(".len()..];
+        let Some(end) = after.find(')') else {
+            break;
+        };
+        exports.extend(
+            after[..end]
+                .split(',')
+                .map(|name| name.trim().trim_matches(['"', '`', '\'']).to_string())
+                .filter(|name| !name.is_empty()),
+        );
+        rest = &after[end..];
+    }
+    exports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_exported_function_definitions() {
+        let source = "#' Docs\nscale_color_npg <- function(palette = \"nrc\", ...) {\n  inner <- function(x) x\n}\n`%||%` <- function(x, y) x\npal_npg = function(palette) {}\nlimit <- 10\n";
+        assert_eq!(
+            defined_functions(source),
+            vec!["scale_color_npg", "pal_npg"]
+        );
+
+        let exports = namespace_exports(
+            "# Generated by roxygen2: do not edit by hand\n\nexport(pal_npg)\nexport(\"scale_color_npg\")\nimportFrom(grDevices,col2rgb)\n",
+        );
+        assert!(exports.contains("pal_npg"));
+        assert!(exports.contains("scale_color_npg"));
+        assert!(!exports.contains("col2rgb"));
+    }
+}
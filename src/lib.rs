@@ -4,20 +4,43 @@
 //! workflow for provisioning R, preparing the target package repository, and
 //! executing `xfun::rev_check()`.
 
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
 use anyhow::{Context, Result, bail};
-use clap::Parser;
 use progress::Progress;
 use xshell::Shell;
 
+mod auth;
+mod cache;
 pub mod cli;
+mod description;
+mod doctor;
+pub mod error;
+mod events;
+mod history;
+mod impact;
+mod preflight;
 mod progress;
 mod r_install;
 mod r_version;
+mod report;
 mod revdep;
+mod summary;
 mod sysreqs;
 pub mod util;
 mod workspace;
 
+/// Workspace directory holding one subdirectory per R version when
+/// `--r-version` lists several and no `--work-dir` is given.
+const MATRIX_WORKSPACE_NAME: &str = "revdeprun-matrix";
+
+/// Directory under the workspace's temporary directory for the complete
+/// output of commands whose output was truncated.
+const OUTPUT_LOG_DIR: &str = "logs";
+
 /// Executes the CLI workflow using the command-line arguments from [`std::env::args`].
 ///
 /// # Errors
@@ -25,28 +48,344 @@ mod workspace;
 /// Returns an error whenever preparing the workspace, installing R, cloning the
 /// repository, or launching `xfun::rev_check()` fails.
 pub fn run() -> Result<()> {
-    let args = cli::Args::parse();
+    let command = cli::Args::parse_command();
+
+    // These only inspect or tidy the machine, so they work on unsupported
+    // platforms too.
+    match &command {
+        cli::Command::Doctor => return doctor::run(),
+        cli::Command::CleanCache(args) => return cache::run(args),
+        cli::Command::Check(_) | cli::Command::Provision(_) => {}
+    }
 
     if std::env::consts::OS != "linux" {
         bail!("revdeprun currently supports Ubuntu Linux environments only.");
     }
 
-    let progress = Progress::new();
+    match command {
+        cli::Command::Check(args) => check(*args),
+        cli::Command::Provision(args) => provision(args),
+        cli::Command::Doctor | cli::Command::CleanCache(_) => {
+            unreachable!("handled before the OS check")
+        }
+    }
+}
+
+/// Resolves an `--r-version` spec, or describes the existing installation it
+/// names when it is a path.
+fn resolve_r_version(
+    shell: &Shell,
+    spec: &str,
+    http: &util::HttpOptions,
+    progress: &Progress,
+) -> Result<r_version::ResolvedRVersion> {
+    let version_label = format!("Resolving R version '{spec}'");
+    let task = progress.task(version_label.clone());
+    let resolved = if r_install::is_local_prefix(spec) {
+        r_install::resolve_local(shell, spec).context("failed to inspect the R installation")
+    } else {
+        r_version::resolve(spec, http).context("failed to resolve requested R version")
+    };
+    match resolved {
+        Ok(version) => {
+            task.finish_with_message(format!("Resolved R {}", version.version));
+            Ok(version)
+        }
+        Err(err) => {
+            task.fail(format!("{version_label} (failed)"));
+            Err(err)
+        }
+    }
+}
+
+/// Notes once per run that commands needing root run without `sudo`.
+fn note_root(progress: &Progress) {
+    if util::running_as_root() {
+        progress.println("Running as root; system commands run directly instead of through sudo.");
+    }
+}
+
+/// Installs R and its tools without touching any package.
+fn provision(args: cli::ProvisionArgs) -> Result<()> {
+    let progress = Progress::new().with_full_output(args.full_output);
+    progress.set_log_dir(workspace::temp_dir_path(None)?.join(OUTPUT_LOG_DIR));
+    let shell = Shell::new().context("failed to initialise shell environment")?;
+    note_root(&progress);
+
+    let http = util::HttpOptions {
+        user_agent: args.user_agent,
+        headers: args.http_header,
+    };
+    let resolved_version = resolve_r_version(&shell, &args.r_version, &http, &progress)?;
+
+    r_install::install_r(
+        &shell,
+        &resolved_version,
+        &r_install::InstallOptions {
+            strict_tools: args.strict_tools,
+            installer_mirror: args.installer_mirror,
+            verbose_apt: args.verbose_apt,
+            bin_dir: args.bin_dir,
+            vignette_tools: true,
+            latex_packages: args
+                .latex_packages
+                .into_iter()
+                .map(|package| package.0)
+                .collect(),
+            http,
+        },
+        &progress,
+    )
+    .context("failed to install the requested R toolchain")?;
+
+    progress.println(format!(
+        "Provisioning finished; R {} and its tools are ready.",
+        resolved_version.version
+    ));
+    Ok(())
+}
+
+/// Runs the full reverse dependency check workflow, once per R version when
+/// `--r-version` lists several.
+fn check(mut args: cli::CheckArgs) -> Result<()> {
+    if args.cran_mode {
+        args.only_changed_since_cran = true;
+        args.fail_on = vec![cli::FailOn::Warning];
+    }
+    if let Some(dir) = &args.check_dir {
+        args.repository = dir.display().to_string();
+    }
+    revdep::validate_repository_spec(&args.repository)?;
+    let specs = args
+        .r_version
+        .as_deref()
+        .map(split_r_versions)
+        .unwrap_or_default();
+    if specs.len() > 1 {
+        return check_matrix(args, specs);
+    }
+    if args.r_version.is_some() {
+        args.r_version = specs.into_iter().next();
+    }
+
+    let fail_on = args.fail_on.clone();
+    let outcomes = check_once(args)?.into_iter().collect::<Vec<_>>();
+    fail_on_problems(&outcomes, &fail_on, false)
+}
+
+/// Splits a comma-separated `--r-version` value into its specs.
+fn split_r_versions(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|spec| !spec.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Workspace subdirectory for one R version spec of a matrix, e.g. `r-oldrel-1`
+/// or `r-opt-R-4.4.1` for an installation prefix.
+fn matrix_dir_name(spec: &str) -> String {
+    let name = spec
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>();
+    format!("r-{}", name.trim_matches('-'))
+}
+
+/// Checks the revdeps under each R version in `specs`, each in its own
+/// workspace subdirectory, and compares the outcomes.
+fn check_matrix(args: cli::CheckArgs, specs: Vec<String>) -> Result<()> {
+    if Path::new(&args.repository).is_dir() {
+        bail!(
+            "checking several R versions needs a remote repository or tarball; a local package directory would share one revdep library between versions"
+        );
+    }
+    if args.skip_r_install {
+        bail!(
+            "--skip-r-install uses the R on PATH and cannot be combined with several --r-version values"
+        );
+    }
+    if args.cran_comments.is_some() {
+        bail!("--cran-comments cannot be combined with several --r-version values");
+    }
+
+    let base = match &args.work_dir {
+        Some(path) => path.clone(),
+        None if args.tmpfs_workspace => PathBuf::from(workspace::DEFAULT_TMPFS_WORKSPACE),
+        None => std::env::current_dir()
+            .context("failed to resolve current directory")?
+            .join(MATRIX_WORKSPACE_NAME),
+    };
+
+    let mut outcomes = Vec::new();
+    for spec in &specs {
+        let dir_name = matrix_dir_name(spec);
+        let mut run_args = args.clone();
+        run_args.r_version = Some(spec.clone());
+        run_args.work_dir = Some(base.join(&dir_name));
+        run_args.report = Vec::new();
+        run_args.dump_scripts = args.dump_scripts.as_ref().map(|dir| dir.join(&dir_name));
+        if let Some(outcome) =
+            check_once(run_args).with_context(|| format!("check with R '{spec}' failed"))?
+        {
+            outcomes.push(outcome);
+        }
+    }
+
+    if outcomes.is_empty() {
+        return Ok(());
+    }
+
+    let progress = Progress::new().with_quiet(args.quiet);
+    let reported = outcomes
+        .iter()
+        .map(|outcome| summary::CheckOutcome {
+            r_version: outcome.r_version.clone(),
+            summary: reported_summary(&args, &outcome.summary),
+        })
+        .collect::<Vec<_>>();
+    for target in &args.report {
+        report::write_matrix(target, &reported)?;
+        progress.println(format!("Report written to {}", target.path.display()));
+    }
+
+    let rows = summary::matrix_rows(&reported);
+    let differing = rows
+        .iter()
+        .filter(|(_, statuses)| statuses.windows(2).any(|pair| pair[0] != pair[1]))
+        .map(|(package, statuses)| {
+            let cells = outcomes
+                .iter()
+                .zip(statuses)
+                .map(|(outcome, status)| {
+                    format!(
+                        "R {} {}",
+                        outcome.r_version,
+                        status.map_or("-", summary::CheckStatus::label)
+                    )
+                })
+                .collect::<Vec<_>>();
+            format!("\n  • {package}: {}", cells.join(", "))
+        })
+        .collect::<String>();
+    let versions = outcomes
+        .iter()
+        .map(|outcome| outcome.r_version.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    if differing.is_empty() {
+        progress.outcome(format!(
+            "Checked {} revdeps under R {versions}; every status matched.",
+            rows.len()
+        ));
+    } else {
+        progress.outcome(format!(
+            "Checked {} revdeps under R {versions}; statuses differ for:{differing}",
+            rows.len()
+        ));
+    }
+
+    fail_on_problems(&outcomes, &args.fail_on, true)
+}
+
+/// Fails with [`error::RevdeprunError::RevdepProblems`] when any outcome has
+/// revdeps in a `--fail-on` category, labelling them with the R version when
+/// several were checked.
+fn fail_on_problems(
+    outcomes: &[summary::CheckOutcome],
+    fail_on: &[cli::FailOn],
+    label_versions: bool,
+) -> Result<()> {
+    let failures = outcomes
+        .iter()
+        .flat_map(|outcome| {
+            outcome.summary.failures(fail_on).map(move |result| {
+                if label_versions {
+                    format!(
+                        "{} ({}, R {})",
+                        result.package,
+                        result.status.label(),
+                        outcome.r_version
+                    )
+                } else {
+                    format!("{} ({})", result.package, result.status.label())
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    if !failures.is_empty() {
+        return Err(error::RevdeprunError::RevdepProblems(failures).into());
+    }
+    Ok(())
+}
+
+/// Runs the workflow for a single R version. Returns the results, or `None`
+/// when the run stopped before checking anything.
+fn check_once(args: cli::CheckArgs) -> Result<Option<summary::CheckOutcome>> {
+    let event_sink = match (&args.events, &args.events_file) {
+        (None, _) => None,
+        (Some(cli::EventFormat::Ndjson), None) => Some(events::EventSink::stdout()),
+        (Some(cli::EventFormat::Ndjson), Some(path)) => Some(events::EventSink::file(path)?),
+    };
+    let progress = Progress::new()
+        .with_full_output(args.full_output)
+        .with_events(event_sink)
+        .with_heartbeat(args.heartbeat.map(|interval| interval.0))
+        .with_quiet(args.quiet);
     let shell = Shell::new().context("failed to initialise shell environment")?;
+    note_root(&progress);
+
+    if args.preflight {
+        progress.phase("preflight");
+        preflight::check_network(&progress).context("network preflight failed")?;
+    }
 
+    progress.phase("workspace");
+    let work_dir = if args.tmpfs_workspace {
+        let path = args
+            .work_dir
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(workspace::DEFAULT_TMPFS_WORKSPACE));
+        if workspace::ensure_tmpfs(&shell, &path)? {
+            progress.println(format!("Mounted a tmpfs workspace on {}", path.display()));
+        } else {
+            progress.println(format!("Using tmpfs workspace {}", path.display()));
+        }
+        if Path::new(&args.repository).is_dir() {
+            progress.println(
+                "Warning: local package directories are checked in place; revdep/library stays on their file system.",
+            );
+        }
+        Some(path)
+    } else {
+        args.work_dir.clone()
+    };
     let workspace_label = args
-        .work_dir
+        .check_dir
         .as_ref()
+        .or(work_dir.as_ref())
         .map(|path| format!("Preparing workspace {}", path.display()))
         .unwrap_or_else(|| "Preparing workspace directory".to_string());
     let workspace = {
         let task = progress.task(workspace_label.clone());
-        match workspace::prepare(args.work_dir.clone()).context("failed to prepare workspace") {
+        let prepared = match &args.check_dir {
+            Some(dir) => workspace::in_place(dir, &revdep::revlib_dir(dir)),
+            None => workspace::prepare(work_dir, args.allow_dirty_workspace),
+        };
+        match prepared.context("failed to prepare workspace") {
             Ok(workspace) => {
                 task.finish_with_message(format!(
                     "Workspace ready (clone root: {})",
                     workspace.clone_root().display()
                 ));
+                progress.set_log_dir(workspace.temp_dir().join(OUTPUT_LOG_DIR));
                 workspace
             }
             Err(err) => {
@@ -56,55 +395,665 @@ pub fn run() -> Result<()> {
         }
     };
 
-    let version_label = format!("Resolving R version '{}'", args.r_version);
-    let resolved_version = {
-        let task = progress.task(version_label.clone());
-        match r_version::resolve(&args.r_version).context("failed to resolve requested R version") {
-            Ok(version) => {
-                task.finish_with_message(format!("Resolved R {}", version.version));
-                version
-            }
-            Err(err) => {
-                task.fail(format!("{version_label} (failed)"));
-                return Err(err);
-            }
+    let cran_mirror = match &args.cran_mirror {
+        Some(url) => revdep::validate_cran_mirror(url)?,
+        None if !args.auto_mirror.is_empty() => {
+            let candidates = args
+                .auto_mirror
+                .iter()
+                .map(|url| revdep::validate_cran_mirror(url))
+                .collect::<Result<Vec<_>>>()?;
+            preflight::fastest_mirror(&candidates, &progress)?
+        }
+        None => revdep::DEFAULT_CRAN_MIRROR.to_string(),
+    };
+    let cran_credentials = args
+        .cran_mirror
+        .as_ref()
+        .and_then(|_| auth::resolve(args.cran_auth.as_ref(), &cran_mirror));
+    if let Some(credentials) = cran_credentials {
+        // Passed through the environment so credentials never land in the
+        // generated scripts or progress output.
+        shell.set_var(auth::CRAN_USER_ENV, &credentials.user);
+        shell.set_var(auth::CRAN_PASSWORD_ENV, &credentials.password);
+    }
+
+    let available_cpus = num_cpus::get();
+    let num_workers = match args.num_workers.map(|value| value.get()) {
+        Some(requested) if requested > available_cpus && !args.oversubscribe => {
+            progress.println(format!(
+                "Warning: --num-workers {requested} exceeds the {available_cpus} available CPUs; using {available_cpus}. Pass --oversubscribe to keep {requested}."
+            ));
+            available_cpus
+        }
+        Some(requested) => requested,
+        None => available_cpus,
+    };
+    let connections = util::ConnectionTuning {
+        per_worker: args.connections_per_worker,
+        base: args.connections_base,
+    };
+
+    progress.phase("repository");
+    let prepared: revdep::PreparedRepository = match args.continue_from {
+        Some(phase) => {
+            let path = revdep::existing_repository(&workspace, &args.repository)?;
+            progress.println(format!(
+                "Continuing the earlier run in {} from the {} phase",
+                path.display(),
+                phase.label()
+            ));
+            path.into()
+        }
+        None if args.check_dir.is_some() => {
+            let path = workspace.clone_root().to_path_buf();
+            progress.println(format!("Checking {} in place", path.display()));
+            path.into()
+        }
+        None if args.summary_only => {
+            let path = revdep::existing_repository(&workspace, &args.repository)?;
+            progress.println(format!("Summarizing the earlier run in {}", path.display()));
+            path.into()
+        }
+        None => revdep::prepare_repository(
+            &shell,
+            &workspace,
+            &args.repository,
+            &revdep::PrepareOptions {
+                num_workers,
+                clone_retries: args.clone_retries,
+                clone_depth: args.clone_depth,
+            },
+            &progress,
+        )
+        .context("failed to prepare target repository")?,
+    };
+    let repository_path = prepared.path;
+    let _extraction_cleanup = match prepared.extraction {
+        Some(extraction) if args.keep_tarball_extraction => {
+            let path = extraction.keep();
+            progress.println(format!(
+                "Keeping extracted package sources at {}",
+                path.display()
+            ));
+            None
         }
+        extraction => extraction,
     };
+    // Fail before installing anything when the earlier run left too little
+    // to resume from.
+    let resumed_revdeps = args
+        .continue_from
+        .map(|phase| revdep::load_resume_state(&repository_path, phase == cli::ContinueFrom::Check))
+        .transpose()?;
+
+    if args.summary_only {
+        return summarize_earlier_run(&args, &repository_path, &progress).map(Some);
+    }
+
+    progress.phase("r");
+    let r_version_spec = match &args.r_version {
+        Some(spec) => spec.clone(),
+        None => match r_version::pinned_version(&repository_path, &progress)? {
+            Some(pinned) => {
+                progress.println(format!(
+                    "Using R version '{}' pinned in {}",
+                    pinned.spec, pinned.source
+                ));
+                pinned.spec
+            }
+            None => r_version::DEFAULT_SPEC.to_string(),
+        },
+    };
+
+    let http = util::HttpOptions {
+        user_agent: args.user_agent.clone(),
+        headers: args.http_header.clone(),
+    };
+    let resolved_version = resolve_r_version(&shell, &r_version_spec, &http, &progress)?;
 
     if args.skip_r_install {
         progress.println("Skipping R installation as requested.");
+        let banner = r_install::ensure_rscript(&shell)?;
+        progress.println(format!("Using the existing R on PATH: {banner}"));
+    } else {
+        r_install::install_r(
+            &shell,
+            &resolved_version,
+            &r_install::InstallOptions {
+                strict_tools: args.strict_tools,
+                installer_mirror: args.installer_mirror.clone(),
+                verbose_apt: args.verbose_apt,
+                bin_dir: args.bin_dir.clone(),
+                vignette_tools: !args.no_vignettes,
+                latex_packages: args
+                    .latex_packages
+                    .iter()
+                    .map(|package| package.0.clone())
+                    .collect(),
+                http,
+            },
+            &progress,
+        )
+        .context("failed to install the requested R toolchain")?;
+    }
+
+    if !args.env.is_empty() {
+        let names = args
+            .env
+            .iter()
+            .map(|var| var.key.as_str())
+            .collect::<Vec<_>>();
+        progress.println(format!(
+            "Passing environment variables to R (values redacted): {}",
+            names.join(", ")
+        ));
+    }
+
+    let package_name = description::read_package_name(&repository_path)?;
+    let since = match &args.since {
+        Some(cli::Since::Date(date)) => Some(date.clone()),
+        Some(cli::Since::LastRun) => {
+            let last_run = workspace.last_run(&package_name);
+            if last_run.is_none() {
+                progress.println(format!(
+                    "No previous run recorded for {package_name} in {}; checking all reverse dependencies.",
+                    workspace.temp_dir().display()
+                ));
+            }
+            last_run
+        }
+        None => None,
+    };
+    let run_date = util::today_utc();
+
+    progress.phase("revdeps");
+    let only = revdep::expand_package_specs(&args.only)?;
+    let exclude = revdep::expand_package_specs(&args.exclude)?;
+    let revdeps = match resumed_revdeps {
+        Some(revdeps) => {
+            progress.println(format!(
+                "Reusing the {} reverse dependencies listed by the earlier run",
+                revdeps.len()
+            ));
+            let revdeps = revdep::select_revdeps(revdeps, &only, &exclude, &progress)?;
+            revdep::filter_revdeps(revdeps, args.pkg_filter.as_ref(), &progress)?
+        }
+        None => {
+            let revdeps = revdep::resolve_revdeps(
+                &shell,
+                &workspace,
+                &repository_path,
+                &revdep::RevdepListOptions {
+                    num_workers,
+                    connections,
+                    source: args.revdeps_source,
+                    since: since.clone(),
+                    cran_mirror: cran_mirror.clone(),
+                    strong_only: args.cran_mode,
+                },
+                &progress,
+            )
+            .context("failed to compute reverse dependencies")?;
+            let revdeps = revdep::select_revdeps(revdeps, &only, &exclude, &progress)?;
+            let revdeps = revdep::filter_revdeps(revdeps, args.pkg_filter.as_ref(), &progress)?;
+
+            let revdeps = match args.max_revdeps {
+                Some(max) => revdep::cap_revdeps(revdeps, max, args.truncate, &progress)?,
+                None => revdeps,
+            };
+            revdep::save_revdeps(&repository_path, &revdeps)?;
+            revdeps
+        }
+    };
+    write_revdeps_out(&args, &revdeps, &progress)?;
+
+    if revdeps.is_empty() {
+        if let Some(date) = &since {
+            progress.outcome(format!(
+                "No reverse dependencies of {package_name} were published since {date}; nothing to check."
+            ));
+            workspace.record_last_run(&package_name, &run_date)?;
+            return Ok(None);
+        }
+        if args.fail_if_empty {
+            bail!(
+                "{package_name} has no CRAN reverse dependencies; nothing to check (--fail-if-empty)."
+            );
+        }
+        progress.outcome(format!(
+            "{package_name} has no CRAN reverse dependencies; nothing to check."
+        ));
+        return Ok(None);
+    }
+
+    if args.tmpfs_workspace {
+        let estimate = revdeps.len() as u64 * workspace::ESTIMATED_BYTES_PER_REVDEP;
+        match workspace::available_memory() {
+            Some(available) if available < estimate => progress.println(format!(
+                "Warning: checking {} revdeps on tmpfs may need about {} GiB of RAM, but only {} GiB is available.",
+                revdeps.len(),
+                estimate.div_ceil(1 << 30),
+                available >> 30
+            )),
+            Some(_) => {}
+            None => progress.println(
+                "Warning: could not read available memory from /proc/meminfo; the tmpfs workspace may run out of space.",
+            ),
+        }
+    }
+
+    progress.phase("sysreqs");
+    let sysreqs_platform = match &args.sysreqs_platform {
+        Some(platform) => {
+            sysreqs::validate_platform(platform)?;
+            platform.clone()
+        }
+        None => sysreqs::default_platform(),
+    };
+
+    if args.skip_sysreqs {
+        progress.println("Skipping system requirement installation as requested.");
+    } else if args.installer == cli::Installer::Pak && !args.only_sysreqs {
+        progress.println(
+            "Skipping the separate system requirement phase; pak installs system requirements with the packages.",
+        );
+    } else if let Some(phase) = args
+        .continue_from
+        .filter(|&phase| phase > cli::ContinueFrom::Sysreqs)
+    {
+        progress.println(format!(
+            "Skipping system requirement installation; continuing from the {} phase.",
+            phase.label()
+        ));
     } else {
-        r_install::install_r(&shell, &resolved_version, &progress)
-            .context("failed to install the requested R toolchain")?;
+        sysreqs::install_reverse_dep_sysreqs(
+            &shell,
+            &workspace,
+            &repository_path,
+            &revdeps,
+            num_workers,
+            sysreqs::SysreqsOptions {
+                print: args.print_sysreqs,
+                dry_run: args.dry_run,
+                env: args.env.clone(),
+                platform: sysreqs_platform,
+                file: args.sysreqs_file.clone(),
+                cran_mirror: cran_mirror.clone(),
+                verbose_apt: args.verbose_apt,
+                dump_scripts: args.dump_scripts.clone(),
+                bioc_version: args.bioc_version.clone(),
+                additional_repos: revdep::additional_repositories(&repository_path),
+                connections,
+            },
+            &progress,
+        )
+        .context("failed to install system requirements for reverse dependencies")?;
+    }
+
+    if args.only_sysreqs {
+        progress
+            .outcome("System requirements provisioned; skipping revdep installation and checks.");
+        return Ok(None);
     }
 
-    let repository_path =
-        revdep::prepare_repository(&shell, &workspace, &args.repository, &progress)
-            .context("failed to prepare target repository")?;
+    if args.dry_run {
+        progress.outcome("Dry run complete; skipping revdep installation and checks.");
+        return Ok(None);
+    }
+
+    if let Some(script) = &args.pre_check_script {
+        revdep::run_hook_script(
+            &shell,
+            &repository_path,
+            script,
+            "pre-check script",
+            None,
+            &progress,
+        )?;
+    }
+
+    progress.phase("check");
+    let cran_type = if args.cran_type == cli::CranType::Source {
+        progress.println("Installing revdep dependencies from source as requested.");
+        cli::CranType::Source
+    } else if resolved_version.is_development() {
+        progress.println(format!(
+            "R {} is a development build; Posit binaries target released R, so revdep dependencies will be installed from source.",
+            resolved_version.version
+        ));
+        cli::CranType::Source
+    } else if cran_mirror != revdep::DEFAULT_CRAN_MIRROR {
+        progress.println(format!(
+            "Using CRAN mirror {cran_mirror}; revdep dependencies will be installed from source."
+        ));
+        cli::CranType::Source
+    } else {
+        args.cran_type
+    };
 
-    let num_workers = args
-        .num_workers
-        .map(|value| value.get())
-        .unwrap_or_else(num_cpus::get);
+    let touched_exports = match &args.touched_only {
+        Some(git_ref) => {
+            let touched = impact::touched_exports(&shell, &repository_path, git_ref)
+                .context("failed to find exports changed by the diff")?;
+            if touched.is_empty() {
+                progress.println(format!(
+                    "No exported functions changed since {git_ref}; checking revdeps in the default order."
+                ));
+            } else {
+                progress.println(format!(
+                    "Exported functions changed since {git_ref}: {}",
+                    touched.join(", ")
+                ));
+            }
+            touched
+        }
+        None => Vec::new(),
+    };
 
-    sysreqs::install_reverse_dep_sysreqs(
+    revdep::run_revcheck(
         &shell,
         &workspace,
         &repository_path,
-        num_workers,
+        &revdeps,
+        &revdep::RevcheckOptions {
+            num_workers,
+            connections,
+            cran_type,
+            env: args.env.clone(),
+            reuse_library: args.reuse_library,
+            clean_library: args.clean_library,
+            install_suggests: !args.no_suggests_install,
+            target_suggests: match args.target_deps {
+                Some(cli::TargetDeps::Hard) => false,
+                Some(cli::TargetDeps::All) => true,
+                None => !args.no_suggests_install,
+            },
+            install_retries: args.install_retries,
+            cran_mirror: cran_mirror.clone(),
+            touched_exports,
+            fail_fast: args.fail_fast,
+            dump_scripts: args.dump_scripts.clone(),
+            build_vignettes: !args.no_vignettes,
+            isolate_target_deps: args.isolate_target_deps,
+            bioc_version: args.bioc_version.clone(),
+            binary_codename: args.binary_codename.clone(),
+            check_self: !args.no_self,
+            skip_install: args.continue_from == Some(cli::ContinueFrom::Check),
+            library_cache: args.library_cache.clone(),
+            require_cran_release: args.only_changed_since_cran,
+            additional_repos: revdep::additional_repositories(&repository_path),
+            installer: args.installer,
+        },
         &progress,
     )
-    .context("failed to install system requirements for reverse dependencies")?;
+    .context("reverse dependency check invocation failed")?;
 
-    revdep::run_revcheck(&shell, &workspace, &repository_path, num_workers, &progress)
-        .context("reverse dependency check invocation failed")?;
+    progress.phase("summary");
+    let summary = collect_summary(&args, &repository_path, &progress)?;
+    let disk_usage = publish_results(
+        &shell,
+        &args,
+        &summary,
+        &repository_path,
+        &resolved_version.version,
+        &progress,
+    )?;
 
-    progress.println(format!(
-        "Reverse dependency check finished successfully.\n  • R version: {}\n  • repository: {}\n  • library: {}",
+    let counts = status_counts(&reported_summary(&args, &summary));
+    progress.outcome(format!(
+        "Reverse dependency check finished.\n  • R version: {}\n  • repository: {}\n  • library: {}\n  • results: {}\n  • summary: {counts}\n  • disk usage: {disk_usage}",
         resolved_version.version,
         repository_path.display(),
-        revdep::revlib_dir(&repository_path).display()
+        revdep::revlib_dir(&repository_path).display(),
+        revdep::results_summary(&repository_path).display()
     ));
 
+    workspace.record_last_run(&package_name, &run_date)?;
+
+    Ok(Some(summary::CheckOutcome {
+        r_version: resolved_version.version,
+        summary,
+    }))
+}
+
+/// Writes the sorted revdeps this run considers to `--revdeps-out`, whether
+/// or not the run goes on to check them.
+fn write_revdeps_out(args: &cli::CheckArgs, revdeps: &[String], progress: &Progress) -> Result<()> {
+    let Some(path) = &args.revdeps_out else {
+        return Ok(());
+    };
+    let mut considered = revdeps
+        .iter()
+        .map(|name| format!("{name}\n"))
+        .collect::<Vec<_>>();
+    considered.sort();
+    let path = util::prepare_output_path(path)?;
+    fs::write(&path, considered.concat())
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    progress.println(format!(
+        "Reverse dependency list written to {}",
+        path.display()
+    ));
     Ok(())
 }
+
+/// Rebuilds the summary and outputs of an earlier run from its `revdep/`
+/// results, for `--summary-only`, without installing or checking anything.
+fn summarize_earlier_run(
+    args: &cli::CheckArgs,
+    repository_path: &Path,
+    progress: &Progress,
+) -> Result<summary::CheckOutcome> {
+    progress.phase("summary");
+    let summary = collect_summary(args, repository_path, progress)?;
+    let r_version = summary
+        .logged_r_version(&revdep::results_dir(repository_path))
+        .or_else(|| args.r_version.clone())
+        .unwrap_or_else(|| "unknown".to_string());
+    let disk_usage = write_outputs(args, &summary, repository_path, &r_version, progress)?;
+
+    progress.outcome(format!(
+        "Summary rebuilt from the earlier run.\n  • R version: {r_version}\n  • repository: {}\n  • results: {}\n  • summary: {}\n  • disk usage: {disk_usage}",
+        repository_path.display(),
+        revdep::results_summary(repository_path).display(),
+        status_counts(&reported_summary(args, &summary))
+    ));
+
+    Ok(summary::CheckOutcome { r_version, summary })
+}
+
+/// Reads the results of the check in `repository_path`, listing the
+/// regressions for `--only-changed-since-cran`.
+fn collect_summary(
+    args: &cli::CheckArgs,
+    repository_path: &Path,
+    progress: &Progress,
+) -> Result<summary::CheckSummary> {
+    let summary = summary::CheckSummary::collect(
+        repository_path,
+        &revdep::results_dir(repository_path),
+        progress,
+    )
+    .context("failed to summarise reverse dependency check results")?;
+    if args.only_changed_since_cran {
+        let regressions = reported_summary(args, &summary);
+        if regressions.results.is_empty() {
+            progress.println(format!(
+                "No revdeps newly break with your changes to {}.",
+                summary.package
+            ));
+        } else {
+            let names = regressions
+                .results
+                .iter()
+                .map(|result| result.package.as_str())
+                .collect::<Vec<_>>();
+            progress.println(format!(
+                "These revdeps newly break with your changes to {}: {}",
+                summary.package,
+                names.join(", ")
+            ));
+        }
+    }
+    Ok(summary)
+}
+
+/// The part of `summary` that is shown and reported: only the regressions for
+/// `--only-changed-since-cran`, everything otherwise. `--fail-on` always
+/// sees the full results.
+fn reported_summary(
+    args: &cli::CheckArgs,
+    summary: &summary::CheckSummary,
+) -> summary::CheckSummary {
+    let mut reported = summary.clone();
+    if args.only_changed_since_cran {
+        reported.retain_regressions();
+    }
+    reported
+}
+
+/// Writes the reports, cran-comments section, and history record requested
+/// for `summary`. Returns the disk usage of the run.
+fn write_outputs(
+    args: &cli::CheckArgs,
+    summary: &summary::CheckSummary,
+    repository_path: &Path,
+    r_version: &str,
+    progress: &Progress,
+) -> Result<revdep::DiskUsage> {
+    let disk_usage = revdep::DiskUsage::measure(repository_path);
+    let reported = reported_summary(args, summary);
+
+    for target in &args.report {
+        report::write(target, &reported, r_version, disk_usage)?;
+        progress.println(format!("Report written to {}", target.path.display()));
+    }
+
+    if let Some(comments_path) = &args.cran_comments {
+        report::append_cran_comments(comments_path, &reported)?;
+        progress.println(format!(
+            "Revdep results appended to {}",
+            comments_path.display()
+        ));
+    }
+
+    if let Some(db_path) = &args.db {
+        let run_id = history::record(db_path, &reported, r_version, &util::now_utc())?;
+        progress.println(format!(
+            "Recorded results as run {run_id} in {}",
+            db_path.display()
+        ));
+    }
+
+    Ok(disk_usage)
+}
+
+/// Writes the outputs of a finished check, then runs the
+/// `--post-check-script`, if any, so it can pick them up. The script also
+/// runs when `--fail-on` later fails the run, but not when the check itself
+/// could not run. Returns the disk usage of the run.
+fn publish_results(
+    shell: &Shell,
+    args: &cli::CheckArgs,
+    summary: &summary::CheckSummary,
+    repository_path: &Path,
+    r_version: &str,
+    progress: &Progress,
+) -> Result<revdep::DiskUsage> {
+    let disk_usage = write_outputs(args, summary, repository_path, r_version, progress)?;
+
+    if let Some(script) = &args.post_check_script {
+        let results = revdep::results_dir(repository_path);
+        if let Err(err) = revdep::run_hook_script(
+            shell,
+            repository_path,
+            script,
+            "post-check script",
+            Some(&results),
+            progress,
+        ) {
+            if args.post_check_fatal {
+                return Err(err);
+            }
+            progress.println(format!(
+                "Warning: {err:#}; continuing. Pass --post-check-fatal to make this fatal."
+            ));
+        }
+    }
+
+    Ok(disk_usage)
+}
+
+/// Counts per status, e.g. `3 OK, 1 Failed, 0 Timed out, 0 Not checked`.
+fn status_counts(summary: &summary::CheckSummary) -> String {
+    summary::CheckStatus::ALL
+        .iter()
+        .map(|&status| format!("{} {}", summary.count(status), status.label()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use clap::Parser;
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn post_check_script_runs_after_reports_are_written() {
+        let dir = tempdir().expect("tempdir");
+        let report = dir.path().join("report.md");
+        let script = dir.path().join("post-check.sh");
+        fs::write(&script, format!("test -f '{}'\n", report.display())).expect("script");
+
+        let args = cli::Args::try_parse_from([
+            OsStr::new("revdeprun"),
+            OsStr::new("ggsci"),
+            OsStr::new("--report"),
+            report.as_os_str(),
+            OsStr::new("--post-check-script"),
+            script.as_os_str(),
+            OsStr::new("--post-check-fatal"),
+        ])
+        .expect("args")
+        .check
+        .expect("check args");
+        let summary = summary::CheckSummary {
+            package: "ggsci".to_string(),
+            version: None,
+            results: Vec::new(),
+        };
+        let shell = Shell::new().expect("shell");
+        let progress = Progress::new().with_quiet(true);
+
+        publish_results(&shell, &args, &summary, dir.path(), "4.5.1", &progress)
+            .expect("the post-check script must see the report");
+    }
+
+    #[test]
+    fn dry_run_writes_revdeps_out() {
+        let dir = tempdir().expect("tempdir");
+        let out = dir.path().join("lists/revdeps.txt");
+        let args = cli::Args::try_parse_from([
+            OsStr::new("revdeprun"),
+            OsStr::new("ggsci"),
+            OsStr::new("--dry-run"),
+            OsStr::new("--revdeps-out"),
+            out.as_os_str(),
+        ])
+        .expect("args")
+        .check
+        .expect("check args");
+        let revdeps = ["ggplot2", "ggforce"].map(String::from);
+
+        write_revdeps_out(&args, &revdeps, &Progress::new().with_quiet(true)).expect("write");
+        assert_eq!(
+            fs::read_to_string(&out).expect("revdeps out"),
+            "ggforce\nggplot2\n"
+        );
+    }
+}
@@ -1,10 +1,11 @@
 use std::process::ExitCode;
 
 fn main() -> ExitCode {
-    if let Err(error) = revdeprun::run() {
-        eprintln!("revdeprun: {error:?}");
-        ExitCode::FAILURE
-    } else {
-        ExitCode::SUCCESS
+    match revdeprun::run() {
+        Ok(()) => ExitCode::from(revdeprun::error::EXIT_OK),
+        Err(error) => {
+            eprintln!("revdeprun: {error:?}");
+            ExitCode::from(revdeprun::error::exit_code(&error))
+        }
     }
 }
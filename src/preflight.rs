@@ -0,0 +1,216 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, bail};
+use reqwest::blocking::Client;
+
+use crate::progress::Progress;
+
+/// Hosts contacted during a normal run.
+pub const REQUIRED_HOSTS: &[&str] = &[
+    "api.r-hub.io",
+    "packagemanager.posit.co",
+    "github.com",
+    "cloud.r-project.org",
+];
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Reachability of a single host.
+#[derive(Debug)]
+pub struct HostStatus {
+    /// Host name that was probed.
+    pub host: &'static str,
+    /// `None` when the host answered, otherwise the connection error.
+    pub error: Option<String>,
+}
+
+/// Probes every host in [`REQUIRED_HOSTS`] concurrently with a short HEAD
+/// request. Any HTTP response, including error statuses, counts as reachable.
+pub fn probe_hosts() -> Result<Vec<HostStatus>> {
+    let client = probe_client()?;
+
+    let statuses = thread::scope(|scope| {
+        let handles = REQUIRED_HOSTS
+            .iter()
+            .map(|&host| {
+                let client = &client;
+                scope.spawn(move || HostStatus {
+                    host,
+                    error: client
+                        .head(format!("https://{host}/"))
+                        .send()
+                        .err()
+                        .map(|err| err.to_string()),
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("host probe thread panicked"))
+            .collect::<Vec<_>>()
+    });
+
+    Ok(statuses)
+}
+
+fn probe_client() -> Result<Client> {
+    Client::builder()
+        .user_agent(format!("revdeprun/{}", env!("CARGO_PKG_VERSION")))
+        .timeout(PROBE_TIMEOUT)
+        .connect_timeout(PROBE_TIMEOUT)
+        .build()
+        .context("failed to create HTTP client")
+}
+
+/// Times a HEAD request for the package index of every `--auto-mirror`
+/// candidate concurrently and returns the fastest one that answered.
+pub fn fastest_mirror(candidates: &[String], progress: &Progress) -> Result<String> {
+    let task = progress.task(format!(
+        "Timing {} candidate CRAN mirrors",
+        candidates.len()
+    ));
+    let client = probe_client()?;
+    let timings = thread::scope(|scope| {
+        let handles = candidates
+            .iter()
+            .map(|mirror| {
+                let client = &client;
+                scope.spawn(move || {
+                    let started = Instant::now();
+                    let response = client
+                        .head(format!("{mirror}/src/contrib/PACKAGES"))
+                        .send()
+                        .and_then(|response| response.error_for_status());
+                    (
+                        mirror.as_str(),
+                        response
+                            .map(|_| started.elapsed())
+                            .map_err(|err| err.to_string()),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("mirror probe thread panicked"))
+            .collect::<Vec<_>>()
+    });
+
+    for (mirror, timing) in &timings {
+        match timing {
+            Ok(elapsed) => progress.println(format!("  • {mirror}: {} ms", elapsed.as_millis())),
+            Err(error) => progress.println(format!("  • {mirror}: {error}")),
+        }
+    }
+    match pick_fastest(&timings) {
+        Some(mirror) => {
+            task.finish_with_message(format!("Using the fastest CRAN mirror {mirror}"));
+            Ok(mirror.to_string())
+        }
+        None => {
+            task.fail("No candidate CRAN mirror answered");
+            bail!(
+                "none of the {} --auto-mirror candidates answered",
+                candidates.len()
+            );
+        }
+    }
+}
+
+/// The mirror with the shortest response time, ignoring those that failed.
+fn pick_fastest<'a>(timings: &[(&'a str, Result<Duration, String>)]) -> Option<&'a str> {
+    timings
+        .iter()
+        .filter_map(|(mirror, timing)| timing.as_ref().ok().map(|elapsed| (*mirror, *elapsed)))
+        .min_by_key(|&(_, elapsed)| elapsed)
+        .map(|(mirror, _)| mirror)
+}
+
+/// Confirms that all required hosts are reachable before any real work starts.
+pub fn check_network(progress: &Progress) -> Result<()> {
+    let task = progress.task(format!(
+        "Checking network reachability of {} hosts",
+        REQUIRED_HOSTS.len()
+    ));
+    let statuses = match probe_hosts() {
+        Ok(statuses) => statuses,
+        Err(err) => {
+            task.fail("Network preflight failed to start");
+            return Err(err);
+        }
+    };
+
+    match unreachable_message(&statuses) {
+        None => {
+            task.finish_with_message(format!(
+                "All {} required hosts are reachable",
+                statuses.len()
+            ));
+            Ok(())
+        }
+        Some(message) => {
+            task.fail("Network preflight found unreachable hosts");
+            bail!("{message}");
+        }
+    }
+}
+
+fn unreachable_message(statuses: &[HostStatus]) -> Option<String> {
+    let unreachable = statuses
+        .iter()
+        .filter_map(|status| {
+            status
+                .error
+                .as_ref()
+                .map(|error| format!("  • {}: {error}", status.host))
+        })
+        .collect::<Vec<_>>();
+    if unreachable.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "{} of {} required hosts are unreachable; check your firewall or proxy settings:\n{}",
+        unreachable.len(),
+        statuses.len(),
+        unreachable.join("\n")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_only_unreachable_hosts() {
+        let statuses = vec![
+            HostStatus {
+                host: "github.com",
+                error: None,
+            },
+            HostStatus {
+                host: "cloud.r-project.org",
+                error: Some("connection refused".to_string()),
+            },
+        ];
+        let message = unreachable_message(&statuses).expect("one host unreachable");
+        assert!(message.starts_with("1 of 2 required hosts are unreachable"));
+        assert!(message.contains("cloud.r-project.org: connection refused"));
+        assert!(!message.contains("github.com"));
+
+        assert!(unreachable_message(&statuses[..1]).is_none());
+    }
+
+    #[test]
+    fn picks_the_fastest_mirror_that_answered() {
+        let timings = [
+            ("https://slow.example", Ok(Duration::from_millis(300))),
+            ("https://down.example", Err("timed out".to_string())),
+            ("https://fast.example", Ok(Duration::from_millis(40))),
+        ];
+        assert_eq!(pick_fastest(&timings), Some("https://fast.example"));
+        assert_eq!(pick_fastest(&timings[1..2]), None);
+    }
+}
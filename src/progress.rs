@@ -1,13 +1,29 @@
-use std::sync::Arc;
-use std::time::Duration;
+use std::path::PathBuf;
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicUsize, Ordering},
+    mpsc::{self, RecvTimeoutError},
+};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
 use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
 
+use crate::events::EventSink;
+
 /// Manages structured terminal output with spinner-style progress reporting.
 #[derive(Clone)]
 pub struct Progress {
     multi: Arc<MultiProgress>,
     spinner_style: ProgressStyle,
+    full_output: bool,
+    events: Option<Arc<EventSink>>,
+    phase: Arc<Mutex<String>>,
+    log_dir: Arc<Mutex<Option<PathBuf>>>,
+    heartbeat: Option<Duration>,
+    /// Number of [`Progress::suspend`] calls in progress on any clone.
+    suspended: Arc<AtomicUsize>,
+    quiet: bool,
 }
 
 impl Default for Progress {
@@ -27,9 +43,131 @@ impl Progress {
         Self {
             multi: Arc::new(multi),
             spinner_style,
+            full_output: false,
+            events: None,
+            phase: Arc::new(Mutex::new("setup".to_string())),
+            log_dir: Arc::new(Mutex::new(None)),
+            heartbeat: None,
+            suspended: Arc::new(AtomicUsize::new(0)),
+            quiet: false,
         }
     }
 
+    /// Hides spinners and intermediate messages, leaving only what
+    /// [`Progress::outcome`] prints.
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        if quiet {
+            self.multi.set_draw_target(ProgressDrawTarget::hidden());
+        }
+        self.quiet = quiet;
+        self
+    }
+
+    /// Whether `--quiet` suppresses intermediate output.
+    pub fn is_quiet(&self) -> bool {
+        self.quiet
+    }
+
+    /// Prints a "still working" line every `interval` while a [`Heartbeat`]
+    /// is alive, for CI systems that stop silent jobs.
+    pub fn with_heartbeat(mut self, interval: Option<Duration>) -> Self {
+        self.heartbeat = interval;
+        self
+    }
+
+    /// Starts printing timestamped "still working: `label`" lines until the
+    /// returned guard is dropped. Does nothing without `--heartbeat`.
+    pub fn heartbeat(&self, label: impl Into<String>) -> Heartbeat {
+        let Some(interval) = self.heartbeat else {
+            return Heartbeat {
+                stop: None,
+                handle: None,
+            };
+        };
+        let label = label.into();
+        let progress = self.clone();
+        let (stop, stopped) = mpsc::channel::<()>();
+        let handle = thread::spawn(move || {
+            let started = Instant::now();
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let line = heartbeat_line(&crate::util::now_utc(), &label, started.elapsed());
+                progress.emit("heartbeat", "info", &line);
+                // The progress bars, and with them `println`, are hidden when
+                // stderr is not a terminal, which is exactly the CI case.
+                // While they are suspended for streamed output, `println`
+                // would block until the command finishes.
+                if progress.quiet {
+                    continue;
+                }
+                if progress.multi.is_hidden() || progress.suspended.load(Ordering::SeqCst) > 0 {
+                    eprintln!("{line}");
+                } else {
+                    let _ = progress.multi.println(line);
+                }
+            }
+        });
+        Heartbeat {
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+
+    /// Forwards task, phase, and message events to `sink` in addition to the
+    /// terminal.
+    pub fn with_events(mut self, sink: Option<EventSink>) -> Self {
+        self.events = sink.map(Arc::new);
+        self
+    }
+
+    /// Whether machine-readable events are written to stdout, in which case
+    /// other stdout output should move to stderr.
+    pub fn events_on_stdout(&self) -> bool {
+        self.events.as_ref().is_some_and(|sink| sink.is_stdout())
+    }
+
+    /// Marks the start of a pipeline phase such as `revdeps` or `check`.
+    pub fn phase(&self, name: &str) {
+        if let Ok(mut phase) = self.phase.lock() {
+            *phase = name.to_string();
+        }
+        self.emit("phase", "started", name);
+    }
+
+    fn emit(&self, event: &str, status: &str, message: &str) {
+        if let Some(sink) = &self.events {
+            let phase = self
+                .phase
+                .lock()
+                .map(|phase| phase.clone())
+                .unwrap_or_default();
+            sink.emit(event, &phase, status, message);
+        }
+    }
+
+    /// Disables truncation of captured command output.
+    pub fn with_full_output(mut self, full_output: bool) -> Self {
+        self.full_output = full_output;
+        self
+    }
+
+    /// Whether captured command output is printed in full.
+    pub fn full_output(&self) -> bool {
+        self.full_output
+    }
+
+    /// Sets the directory the complete output of truncated commands is written
+    /// to, shared with every clone of this manager.
+    pub fn set_log_dir(&self, dir: PathBuf) {
+        if let Ok(mut log_dir) = self.log_dir.lock() {
+            *log_dir = Some(dir);
+        }
+    }
+
+    /// Directory for complete command output, if one was set.
+    pub fn log_dir(&self) -> Option<PathBuf> {
+        self.log_dir.lock().ok().and_then(|dir| dir.clone())
+    }
+
     /// Starts a new spinner task with the provided label.
     pub fn task(&self, label: impl Into<String>) -> Task {
         let label = label.into();
@@ -37,48 +175,129 @@ impl Progress {
         bar.set_style(self.spinner_style.clone());
         bar.set_message(label.clone());
         bar.enable_steady_tick(Duration::from_millis(80));
+        self.emit("task", "started", &label);
 
         Task {
             bar,
             label,
             finished: false,
+            progress: self.clone(),
         }
     }
 
     /// Prints a standalone message, respecting the progress draw target.
     pub fn println(&self, message: impl AsRef<str>) {
         let message = message.as_ref();
+        self.emit("message", "info", message);
+        if self.quiet {
+            return;
+        }
         // Ensure progress bars are temporarily suspended to avoid interleaving.
         let _ = self.multi.println(message);
     }
 
+    /// Prints the final result of a run. Unlike [`Progress::println`], this
+    /// is not silenced by `--quiet`; there it goes to stdout for scripts,
+    /// unless stdout carries the event stream.
+    pub fn outcome(&self, message: impl AsRef<str>) {
+        let message = message.as_ref();
+        if !self.quiet {
+            self.println(message);
+            return;
+        }
+        self.emit("message", "info", message);
+        if self.events_on_stdout() {
+            eprintln!("{message}");
+        } else {
+            println!("{message}");
+        }
+    }
+
     /// Executes a closure while temporarily suspending drawing.
     pub fn suspend<F, T>(&self, operation: F) -> T
     where
         F: FnOnce() -> T,
     {
+        let _suspended = Suspended::new(&self.suspended);
         self.multi.suspend(operation)
     }
 }
 
+/// Counts a [`Progress::suspend`] call as in progress until dropped.
+struct Suspended<'a>(&'a AtomicUsize);
+
+impl<'a> Suspended<'a> {
+    fn new(count: &'a AtomicUsize) -> Self {
+        count.fetch_add(1, Ordering::SeqCst);
+        Self(count)
+    }
+}
+
+impl Drop for Suspended<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Guard returned by [`Progress::heartbeat`]; stops the heartbeat when dropped.
+pub struct Heartbeat {
+    stop: Option<mpsc::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn heartbeat_line(timestamp: &str, label: &str, elapsed: Duration) -> String {
+    format!(
+        "[{timestamp}] still working: {label} (elapsed {}m)",
+        elapsed.as_secs() / 60
+    )
+}
+
 /// Spinner-style progress task returned by [`Progress::task`].
 pub struct Task {
     bar: ProgressBar,
     label: String,
     finished: bool,
+    progress: Progress,
 }
 
 impl Task {
     /// Marks the task as successfully completed with a custom trailing message.
     pub fn finish_with_message(mut self, message: impl Into<String>) {
         self.finished = true;
-        self.bar.finish_with_message(message.into());
+        let message = message.into();
+        self.progress.emit("task", "finished", &message);
+        self.bar.finish_with_message(message);
     }
 
     /// Marks the task as failed, preserving its last message.
     pub fn fail(mut self, message: impl Into<String>) {
         self.finished = true;
-        self.bar.abandon_with_message(message.into());
+        let message = message.into();
+        self.progress.emit("task", "failed", &message);
+        self.bar.abandon_with_message(message);
+    }
+
+    /// Switches the task to a determinate bar showing `done` of `total` steps.
+    pub fn set_progress(&self, done: u64, total: u64) {
+        if self.bar.length() != Some(total) {
+            let style =
+                ProgressStyle::with_template("{spinner:.green} {msg} [{bar:30}] {pos}/{len}")
+                    .unwrap_or_else(|_| ProgressStyle::default_bar())
+                    .tick_strings(&["-", "\\", "|", "/"])
+                    .progress_chars("=> ");
+            self.bar.set_style(style);
+            self.bar.set_length(total);
+        }
+        self.bar.set_position(done);
     }
 
     /// Returns a clone of the underlying progress bar for external updates.
@@ -90,8 +309,43 @@ impl Task {
 impl Drop for Task {
     fn drop(&mut self) {
         if !self.finished {
+            self.progress.emit("task", "cancelled", &self.label);
             self.bar
                 .abandon_with_message(format!("{} (cancelled)", self.label));
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quiet_mode_hides_progress() {
+        let progress = Progress::new().with_quiet(true);
+        assert!(progress.is_quiet());
+        assert!(progress.multi.is_hidden());
+        assert!(!Progress::new().is_quiet());
+    }
+
+    #[test]
+    fn tracks_suspension_across_clones() {
+        let progress = Progress::new();
+        let clone = progress.clone();
+        let during = progress.suspend(|| clone.suspended.load(Ordering::SeqCst));
+        assert_eq!(during, 1);
+        assert_eq!(clone.suspended.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn formats_heartbeat_lines() {
+        assert_eq!(
+            heartbeat_line(
+                "2025-06-01T12:00:00Z",
+                "checking revdeps",
+                Duration::from_secs(754)
+            ),
+            "[2025-06-01T12:00:00Z] still working: checking revdeps (elapsed 12m)"
+        );
+    }
+}
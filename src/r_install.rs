@@ -1,7 +1,9 @@
 use std::{
-    fs::File,
-    io::copy,
+    env,
+    fs::{self, File},
+    io::{Read, copy},
     path::{Path, PathBuf},
+    thread,
 };
 
 use anyhow::{Context, Result, bail};
@@ -9,55 +11,259 @@ use reqwest::blocking::Client;
 use tempfile::TempDir;
 use xshell::{Shell, cmd};
 
-use crate::{progress::Progress, r_version::ResolvedRVersion};
+use crate::{
+    progress::Progress,
+    r_version::ResolvedRVersion,
+    util::{self, HttpOptions},
+};
 
 const QUARTO_VERSION: &str = "1.8.25";
 
+/// Settings that control how R and supporting tools are provisioned.
+#[derive(Clone, Debug)]
+pub struct InstallOptions {
+    /// Fail when Quarto, pandoc, or TinyTeX cannot be provisioned instead of
+    /// warning and continuing.
+    pub strict_tools: bool,
+    /// Base URL to retry the R installer download from when the primary
+    /// download fails.
+    pub installer_mirror: Option<String>,
+    /// Print apt output live instead of only on failure.
+    pub verbose_apt: bool,
+    /// Directory for the `R`, `Rscript`, Quarto, and TinyTeX symlinks.
+    pub bin_dir: PathBuf,
+    /// Provision Quarto and TinyTeX, which are only needed to build vignettes.
+    pub vignette_tools: bool,
+    /// TeX Live packages to install with tlmgr once TinyTeX is available.
+    pub latex_packages: Vec<String>,
+    /// User agent and headers for the installer download.
+    pub http: HttpOptions,
+}
+
 /// Ensures the requested R toolchain is installed system-wide.
-pub fn install_r(shell: &Shell, version: &ResolvedRVersion, progress: &Progress) -> Result<()> {
+pub fn install_r(
+    shell: &Shell,
+    version: &ResolvedRVersion,
+    options: &InstallOptions,
+    progress: &Progress,
+) -> Result<()> {
+    let bin_dir = options.bin_dir.as_path();
+    prepare_bin_dir(shell, bin_dir, progress)?;
+
+    if let Some(prefix) = &version.prefix {
+        configure_symlinks(shell, version, bin_dir, progress)
+            .context("failed to configure R symlinks")?;
+        progress.println(format!(
+            "Using R {} from {}",
+            version.version,
+            prefix.display()
+        ));
+        return ensure_tools(shell, options, progress);
+    }
+
     let check_task = progress.task(format!(
         "Checking existing R {} installation",
         version.version
     ));
-    let r_already_installed = is_r_already_installed(shell, version)?;
+    // An installation under /opt/R counts even when PATH currently leads to
+    // another version, e.g. between the runs of an --r-version matrix.
+    let r_already_installed =
+        version.binary_path("R").is_file() || is_r_already_installed(shell, version)?;
     if r_already_installed {
         check_task.finish_with_message(format!("Using existing R {}", version.version));
+        let links = r_symlinks(version, bin_dir);
+        let dangling = links
+            .iter()
+            .filter(|(link, _)| is_dangling_symlink(Path::new(link)))
+            .map(|(link, _)| link.as_str())
+            .collect::<Vec<_>>();
+        let elsewhere = links
+            .iter()
+            .filter(|(link, target)| links_elsewhere(Path::new(link), Path::new(target)))
+            .map(|(link, _)| link.as_str())
+            .collect::<Vec<_>>();
+        if !dangling.is_empty() {
+            progress.println(format!(
+                "Found broken symlinks from an earlier install ({}); relinking",
+                dangling.join(", ")
+            ));
+            configure_symlinks(shell, version, bin_dir, progress)
+                .context("failed to repair R symlinks")?;
+        } else if !elsewhere.is_empty() {
+            progress.println(format!(
+                "{} point to another R installation; relinking to R {}",
+                elsewhere.join(", "),
+                version.version
+            ));
+            configure_symlinks(shell, version, bin_dir, progress)
+                .context("failed to relink R symlinks")?;
+        }
     } else {
         check_task.finish_with_message(format!("R {} not detected; installing", version.version));
 
-        let download_task = progress.task(format!("Downloading R {} installer", version.version));
-        let installer = match download_installer(version) {
-            Ok(installer) => {
-                let file_name = installer
-                    .path()
-                    .file_name()
-                    .and_then(|name| name.to_str())
-                    .unwrap_or("installer.deb");
-                download_task
-                    .finish_with_message(format!("Downloaded R {} ({file_name})", version.version));
-                installer
-            }
-            Err(err) => {
-                download_task.fail(format!("Download of R {} failed", version.version));
-                return Err(err);
-            }
-        };
-
-        install_prerequisites(shell, progress).context("failed to install R prerequisites")?;
+        // The prerequisites come from apt and do not need the installer, so
+        // they are installed while the installer downloads.
+        let (installer, prerequisites) = thread::scope(|scope| {
+            let download = scope.spawn(|| {
+                let download_task =
+                    progress.task(format!("Downloading R {} installer", version.version));
+                match download_installer(version, options, progress) {
+                    Ok(installer) => {
+                        let file_name = installer
+                            .path()
+                            .file_name()
+                            .and_then(|name| name.to_str())
+                            .unwrap_or("installer.deb");
+                        download_task.finish_with_message(format!(
+                            "Downloaded R {} ({file_name})",
+                            version.version
+                        ));
+                        Ok(installer)
+                    }
+                    Err(err) => {
+                        download_task.fail(format!("Download of R {} failed", version.version));
+                        Err(err)
+                    }
+                }
+            });
+            let prerequisites = install_prerequisites(shell, options.verbose_apt, progress);
+            (
+                download.join().expect("installer download thread panicked"),
+                prerequisites,
+            )
+        });
+        let installer = installer?;
+        prerequisites.context("failed to install R prerequisites")?;
         install_from_deb(shell, installer.path(), progress)
             .with_context(|| format!("failed to install {}", installer.path().display()))?;
-        configure_symlinks(shell, version, progress).context("failed to configure R symlinks")?;
+        configure_symlinks(shell, version, bin_dir, progress)
+            .context("failed to configure R symlinks")?;
 
         progress.println(format!("R {} installation completed", version.version));
     }
 
-    ensure_quarto(shell, progress).context("failed to provision Quarto")?;
-    ensure_pandoc(shell, progress).context("failed to provision pandoc")?;
-    ensure_tinytex(shell, progress).context("failed to provision TinyTeX")?;
+    ensure_tools(shell, options, progress)
+}
+
+/// Provisions Quarto, pandoc, and (unless vignettes are skipped) TinyTeX.
+fn ensure_tools(shell: &Shell, options: &InstallOptions, progress: &Progress) -> Result<()> {
+    let bin_dir = options.bin_dir.as_path();
+    let strict = options.strict_tools;
+    if options.vignette_tools {
+        tolerate_tool_failure(
+            "Quarto",
+            ensure_quarto(shell, bin_dir, progress),
+            strict,
+            progress,
+        )?;
+    }
+    tolerate_tool_failure("pandoc", ensure_pandoc(shell, progress), strict, progress)?;
+    if options.vignette_tools {
+        tolerate_tool_failure(
+            "TinyTeX",
+            ensure_tinytex(shell, bin_dir, progress),
+            strict,
+            progress,
+        )?;
+        if !options.latex_packages.is_empty() {
+            tolerate_tool_failure(
+                "LaTeX packages",
+                install_latex_packages(shell, &options.latex_packages, progress),
+                strict,
+                progress,
+            )?;
+        }
+    } else {
+        progress.println("Skipping Quarto and TinyTeX because vignettes are not built.");
+    }
+
+    Ok(())
+}
+
+/// Creates `bin_dir` if needed and puts it first on the `PATH` of every later
+/// command, so the tool checks and R invocations find the linked binaries.
+fn prepare_bin_dir(shell: &Shell, bin_dir: &Path, progress: &Progress) -> Result<()> {
+    let sudo = util::sudo();
+    if !bin_dir.is_dir() {
+        fs::create_dir_all(bin_dir)
+            .or_else(|_| cmd!(shell, "{sudo} mkdir -p {bin_dir}").quiet().run())
+            .with_context(|| format!("failed to create {}", bin_dir.display()))?;
+    }
 
+    let path = shell.var("PATH").unwrap_or_default();
+    if !env::split_paths(&path).any(|entry| entry == bin_dir) {
+        progress.println(format!(
+            "Warning: {} is not on PATH; it is added for revdeprun's own commands, but add it to your shell profile to use R afterwards.",
+            bin_dir.display()
+        ));
+        let joined =
+            env::join_paths(std::iter::once(bin_dir.to_path_buf()).chain(env::split_paths(&path)))
+                .context("failed to extend PATH")?;
+        shell.set_var("PATH", joined);
+    }
     Ok(())
 }
 
+/// Builds `ln -sf target link`, using `sudo` only when the current user
+/// cannot write to the directory holding `link`.
+fn link_command<'a>(shell: &'a Shell, target: &str, link: &Path) -> xshell::Cmd<'a> {
+    let sudo = util::sudo();
+    let writable = link
+        .parent()
+        .is_some_and(|dir| tempfile::tempfile_in(dir).is_ok());
+    if writable {
+        cmd!(shell, "ln -sf {target} {link}")
+    } else {
+        cmd!(shell, "{sudo} ln -sf {target} {link}")
+    }
+}
+
+/// Turns a tool provisioning failure into a warning unless `strict` is set.
+fn tolerate_tool_failure(
+    tool: &str,
+    result: Result<()>,
+    strict: bool,
+    progress: &Progress,
+) -> Result<()> {
+    match result {
+        Ok(()) => Ok(()),
+        Err(err) if strict => Err(err).with_context(|| format!("failed to provision {tool}")),
+        Err(err) => {
+            progress.println(format!(
+                "Warning: failed to provision {tool} ({err:#}); continuing without it. \
+                 Vignette builds that need {tool} may fail. Pass --strict-tools to make this fatal."
+            ));
+            Ok(())
+        }
+    }
+}
+
+/// Confirms that `Rscript` runs when provisioning was skipped, so a missing R
+/// fails here rather than as "command not found" in the middle of a check.
+pub fn ensure_rscript(shell: &Shell) -> Result<String> {
+    let output = cmd!(shell, "Rscript --version")
+        .quiet()
+        .ignore_status()
+        .output();
+    match output {
+        Ok(output) if output.status.success() => {
+            // Older R versions print the version banner to stderr.
+            let banner = [output.stdout, output.stderr]
+                .iter()
+                .map(|stream| {
+                    let text = String::from_utf8_lossy(stream);
+                    text.lines().next().unwrap_or_default().trim().to_string()
+                })
+                .find(|text| !text.is_empty())
+                .unwrap_or_default();
+            Ok(banner)
+        }
+        _ => bail!(
+            "Rscript was not found on PATH; install R first or drop --skip-r-install to let revdeprun provision it"
+        ),
+    }
+}
+
 fn is_r_already_installed(shell: &Shell, version: &ResolvedRVersion) -> Result<bool> {
     let output = cmd!(shell, "R --version").ignore_status().read();
     Ok(match output {
@@ -66,34 +272,41 @@ fn is_r_already_installed(shell: &Shell, version: &ResolvedRVersion) -> Result<b
     })
 }
 
-fn install_prerequisites(shell: &Shell, progress: &Progress) -> Result<()> {
-    run_command(
-        progress,
+fn install_prerequisites(shell: &Shell, verbose_apt: bool, progress: &Progress) -> Result<()> {
+    let sudo = util::sudo();
+    let run_apt = |start: &str, success: &str, command: xshell::Cmd<'_>| {
+        if verbose_apt {
+            util::stream_to_progress(command, progress, start, success)
+        } else {
+            run_command(progress, start, success, command)
+        }
+    };
+    let update_quiet: &[&str] = if verbose_apt { &[] } else { &["-qq"] };
+
+    run_apt(
         "Updating apt package metadata",
         "apt package metadata updated",
         cmd!(
             shell,
-            "sudo env DEBIAN_FRONTEND=noninteractive apt-get update -y -qq"
+            "{sudo} env DEBIAN_FRONTEND=noninteractive apt-get update -y {update_quiet...}"
         ),
     )?;
 
-    run_command(
-        progress,
+    run_apt(
         "Installing base R prerequisites",
         "base R prerequisites installed",
         cmd!(
             shell,
-            "sudo env DEBIAN_FRONTEND=noninteractive apt-get install -y gdebi-core qpdf devscripts ghostscript"
+            "{sudo} env DEBIAN_FRONTEND=noninteractive apt-get install -y gdebi-core qpdf devscripts ghostscript"
         ),
     )?;
 
-    run_command(
-        progress,
+    run_apt(
         "Installing pak system requirements",
         "pak system requirements installed",
         cmd!(
             shell,
-            "sudo env DEBIAN_FRONTEND=noninteractive apt-get install -y libcurl4-openssl-dev libssl-dev"
+            "{sudo} env DEBIAN_FRONTEND=noninteractive apt-get install -y libcurl4-openssl-dev libssl-dev"
         ),
     )?;
 
@@ -101,37 +314,143 @@ fn install_prerequisites(shell: &Shell, progress: &Progress) -> Result<()> {
 }
 
 fn install_from_deb(shell: &Shell, package_path: &Path, progress: &Progress) -> Result<()> {
+    let sudo = util::sudo();
     let label = format!("Installing {}", package_path.display());
     run_command(
         progress,
         label.clone(),
         format!("Installed {}", package_path.display()),
-        cmd!(shell, "sudo gdebi --non-interactive {package_path}"),
+        cmd!(shell, "{sudo} gdebi --non-interactive {package_path}"),
     )
 }
 
+/// Returns the `bin_dir` symlinks for `version` with their targets.
+fn r_symlinks(version: &ResolvedRVersion, bin_dir: &Path) -> [(String, String); 2] {
+    ["R", "Rscript"].map(|binary| {
+        (
+            bin_dir.join(binary).to_string_lossy().into_owned(),
+            version.binary_path(binary).to_string_lossy().into_owned(),
+        )
+    })
+}
+
+/// Whether an `--r-version` value names an existing installation prefix
+/// rather than a version; version specs never contain a slash.
+pub fn is_local_prefix(spec: &str) -> bool {
+    spec.contains('/')
+}
+
+/// Describes the R installation under `spec` (e.g. `/custom/R/4.4.1`) from
+/// the output of its `bin/R --version`. When the last path component looks
+/// like a version, it must match the reported one.
+pub fn resolve_local(shell: &Shell, spec: &str) -> Result<ResolvedRVersion> {
+    let prefix =
+        fs::canonicalize(spec).with_context(|| format!("R installation {spec} does not exist"))?;
+    let r_binary = prefix.join("bin").join("R");
+    if !r_binary.is_file() {
+        bail!(
+            "{} does not exist; --r-version PATH must name an R installation prefix containing bin/R",
+            r_binary.display()
+        );
+    }
+    let banner = cmd!(shell, "{r_binary} --version")
+        .quiet()
+        .read()
+        .with_context(|| format!("failed to run {} --version", r_binary.display()))?;
+    let (version, kind) = parse_r_banner(&banner)
+        .with_context(|| format!("unrecognised output from {} --version", r_binary.display()))?;
+
+    let named = Path::new(spec)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .filter(|name| name.split('.').all(|part| part.parse::<u32>().is_ok()));
+    if let Some(named) = named {
+        if named != version {
+            bail!("{spec} looks like R {named}, but its R --version reports {version}");
+        }
+    }
+
+    Ok(ResolvedRVersion {
+        version,
+        url: String::new(),
+        kind,
+        prefix: Some(prefix),
+    })
+}
+
+/// Extracts the version and, for development builds, the `devel` channel
+/// from the first line of `R --version`.
+fn parse_r_banner(banner: &str) -> Option<(String, Option<String>)> {
+    let first = banner.lines().next()?.trim();
+    if first.starts_with("R Under development") {
+        return Some(("devel".to_string(), Some("devel".to_string())));
+    }
+    let version = first
+        .strip_prefix("R version ")?
+        .split_whitespace()
+        .next()?;
+    Some((version.to_string(), None))
+}
+
+/// Returns whether `path` is a symlink whose target does not exist.
+/// Whether `link` is a symlink to something other than the existing `target`.
+fn links_elsewhere(link: &Path, target: &Path) -> bool {
+    target.is_file() && fs::read_link(link).is_ok_and(|current| current != target)
+}
+
+fn is_dangling_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_symlink())
+        && fs::metadata(path).is_err()
+}
+
 fn configure_symlinks(
     shell: &Shell,
     version: &ResolvedRVersion,
+    bin_dir: &Path,
     progress: &Progress,
 ) -> Result<()> {
-    let install_dir = version.install_dir_name();
-    let r_path = format!("/opt/R/{install_dir}/bin/R");
-    let rscript_path = format!("/opt/R/{install_dir}/bin/Rscript");
-
-    run_command(
-        progress,
-        "Linking R binary",
-        format!("Linked /usr/local/bin/R -> {r_path}"),
-        cmd!(shell, "sudo ln -sf {r_path} /usr/local/bin/R"),
-    )?;
+    let links = r_symlinks(version, bin_dir);
+
+    for (_, target) in &links {
+        if !Path::new(target).is_file() {
+            if let Some(prefix) = &version.prefix {
+                bail!(
+                    "{target} does not exist in the R installation at {}",
+                    prefix.display()
+                );
+            }
+            bail!(
+                "{target} does not exist, so the R {} installation under /opt/R/{} looks incomplete; \
+                 remove that directory and rerun revdeprun to reinstall it",
+                version.version,
+                version.install_dir_name()
+            );
+        }
+    }
 
-    run_command(
-        progress,
-        "Linking Rscript binary",
-        format!("Linked /usr/local/bin/Rscript -> {rscript_path}"),
-        cmd!(shell, "sudo ln -sf {rscript_path} /usr/local/bin/Rscript"),
-    )?;
+    for (link, target) in &links {
+        let binary = Path::new(target)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("R");
+        run_command(
+            progress,
+            format!("Linking {binary} binary"),
+            format!("Linked {link} -> {target}"),
+            link_command(shell, target, Path::new(link)),
+        )?;
+
+        let resolved = fs::canonicalize(link)
+            .with_context(|| format!("{link} does not resolve after linking it to {target}"))?;
+        let expected =
+            fs::canonicalize(target).with_context(|| format!("failed to resolve {target}"))?;
+        if resolved != expected {
+            bail!(
+                "{link} resolves to {} instead of {target}",
+                resolved.display()
+            );
+        }
+    }
     Ok(())
 }
 
@@ -188,24 +507,56 @@ impl DownloadedInstaller {
     }
 }
 
-fn download_installer(version: &ResolvedRVersion) -> Result<DownloadedInstaller> {
-    let client = http_client()?;
+fn download_installer(
+    version: &ResolvedRVersion,
+    options: &InstallOptions,
+    progress: &Progress,
+) -> Result<DownloadedInstaller> {
+    let primary_err = match download_from(&version.url, &options.http) {
+        Ok(installer) => return Ok(installer),
+        Err(err) => err,
+    };
+    let Some(mirror) = options.installer_mirror.as_deref() else {
+        return Err(primary_err);
+    };
+
+    let mirrored = mirror_url(&version.url, mirror)?;
+    progress.println(format!(
+        "Download from {} failed ({primary_err:#}); retrying from {mirrored}",
+        version.url
+    ));
+    download_from(&mirrored, &options.http).with_context(|| {
+        format!("installer mirror also failed after primary download error: {primary_err:#}")
+    })
+}
+
+fn download_from(url: &str, http: &HttpOptions) -> Result<DownloadedInstaller> {
+    let client = http_client(http)?;
     let response = client
-        .get(version.url.clone())
+        .get(url)
         .send()
-        .with_context(|| format!("failed to download {}", version.url))?
+        .with_context(|| format!("failed to download {url}"))?
         .error_for_status()
-        .with_context(|| format!("download returned error status for {}", version.url))?;
+        .with_context(|| format!("download returned error status for {url}"))?;
 
     let temp_dir = TempDir::new().context("failed to allocate temporary directory")?;
-    let file_name = file_name_from_url(&version.url)?;
+    let file_name = file_name_from_url(url)?;
     let installer_path = temp_dir.path().join(file_name);
 
+    let expected_len = response.content_length();
     let mut file = File::create(&installer_path)
         .with_context(|| format!("failed to create {}", installer_path.display()))?;
     let mut reader = response;
-    copy(&mut reader, &mut file)
+    let written = copy(&mut reader, &mut file)
         .with_context(|| format!("failed to write {}", installer_path.display()))?;
+    drop(file);
+
+    let mut header = Vec::with_capacity(DEB_MAGIC.len());
+    File::open(&installer_path)
+        .and_then(|file| file.take(DEB_MAGIC.len() as u64).read_to_end(&mut header))
+        .with_context(|| format!("failed to read {}", installer_path.display()))?;
+    verify_installer(written, expected_len, &header)
+        .with_context(|| format!("corrupt installer download from {url}"))?;
 
     Ok(DownloadedInstaller {
         temp_dir,
@@ -213,7 +564,41 @@ fn download_installer(version: &ResolvedRVersion) -> Result<DownloadedInstaller>
     })
 }
 
-fn ensure_quarto(shell: &Shell, progress: &Progress) -> Result<()> {
+/// Every `.deb` is an `ar` archive starting with this signature.
+const DEB_MAGIC: &[u8] = b"!<arch>\n";
+
+/// Rejects empty, truncated, or non-`.deb` downloads before they reach
+/// `gdebi`, so the caller can fall back to the mirror.
+fn verify_installer(written: u64, expected_len: Option<u64>, header: &[u8]) -> Result<()> {
+    if written == 0 {
+        bail!("the downloaded installer is empty");
+    }
+    if let Some(expected) = expected_len.filter(|&expected| expected != written) {
+        bail!("the downloaded installer is {written} bytes but the server announced {expected}");
+    }
+    if !header.starts_with(DEB_MAGIC) {
+        bail!("the downloaded installer is not a Debian package");
+    }
+    Ok(())
+}
+
+/// Rewrites `url` to be served from `mirror`, keeping its path and query.
+///
+/// A path on the mirror base (e.g. `https://mirror.example.com/r-builds`) is
+/// prepended to the original path.
+fn mirror_url(url: &str, mirror: &str) -> Result<String> {
+    let original =
+        reqwest::Url::parse(url).with_context(|| format!("failed to parse download URL {url}"))?;
+    let mut mirrored = reqwest::Url::parse(mirror)
+        .with_context(|| format!("failed to parse installer mirror {mirror}"))?;
+    let prefix = mirrored.path().trim_end_matches('/').to_string();
+    mirrored.set_path(&format!("{prefix}{}", original.path()));
+    mirrored.set_query(original.query());
+    Ok(mirrored.to_string())
+}
+
+fn ensure_quarto(shell: &Shell, bin_dir: &Path, progress: &Progress) -> Result<()> {
+    let sudo = util::sudo();
     ensure_curl(shell, progress)?;
 
     let check_task = progress.task(format!("Checking existing Quarto {QUARTO_VERSION}"));
@@ -236,14 +621,20 @@ fn ensure_quarto(shell: &Shell, progress: &Progress) -> Result<()> {
         progress,
         format!("Creating /opt/quarto/{QUARTO_VERSION}"),
         format!("Prepared /opt/quarto/{QUARTO_VERSION}"),
-        cmd!(shell, "sudo mkdir -p /opt/quarto/{QUARTO_VERSION}"),
+        cmd!(shell, "{sudo} mkdir -p /opt/quarto/{QUARTO_VERSION}"),
     )?;
 
-    let tarball_path = format!("/tmp/quarto-{QUARTO_VERSION}.tar.gz");
-    let download_url = format!(
-        "https://github.com/quarto-dev/quarto-cli/releases/download/v{}/quarto-{}-linux-amd64.tar.gz",
-        QUARTO_VERSION, QUARTO_VERSION
-    );
+    // Removed on every exit path, so an interrupted download leaves nothing
+    // behind.
+    let download_dir =
+        TempDir::new().context("failed to allocate temporary directory for Quarto")?;
+    let tarball_path = download_dir
+        .path()
+        .join(format!("quarto-{QUARTO_VERSION}.tar.gz"));
+    let release_url =
+        format!("https://github.com/quarto-dev/quarto-cli/releases/download/v{QUARTO_VERSION}");
+    let asset_name = format!("quarto-{QUARTO_VERSION}-linux-amd64.tar.gz");
+    let download_url = format!("{release_url}/{asset_name}");
 
     run_command(
         progress,
@@ -252,30 +643,30 @@ fn ensure_quarto(shell: &Shell, progress: &Progress) -> Result<()> {
         cmd!(shell, "curl -fsSL -o {tarball_path} -L {download_url}"),
     )?;
 
+    verify_quarto_bundle(shell, &tarball_path, &release_url, &asset_name, progress)?;
+
     run_command(
         progress,
         format!("Extracting Quarto {QUARTO_VERSION} bundle"),
         format!("Installed Quarto {QUARTO_VERSION} to /opt/quarto/{QUARTO_VERSION}"),
         cmd!(
             shell,
-            "sudo tar -xzf {tarball_path} -C /opt/quarto/{QUARTO_VERSION} --strip-components=1"
+            "{sudo} tar -xzf {tarball_path} -C /opt/quarto/{QUARTO_VERSION} --strip-components=1"
         ),
     )?;
 
-    run_command(
-        progress,
-        "Cleaning temporary Quarto archive",
-        "Removed temporary Quarto archive",
-        cmd!(shell, "rm -f {tarball_path}"),
-    )?;
-
+    let link = bin_dir.join("quarto");
     run_command(
         progress,
         "Linking Quarto binary",
-        format!("Linked /usr/local/bin/quarto -> /opt/quarto/{QUARTO_VERSION}/bin/quarto"),
-        cmd!(
+        format!(
+            "Linked {} -> /opt/quarto/{QUARTO_VERSION}/bin/quarto",
+            link.display()
+        ),
+        link_command(
             shell,
-            "sudo ln -sf /opt/quarto/{QUARTO_VERSION}/bin/quarto /usr/local/bin/quarto"
+            &format!("/opt/quarto/{QUARTO_VERSION}/bin/quarto"),
+            &link,
         ),
     )?;
 
@@ -284,7 +675,74 @@ fn ensure_quarto(shell: &Shell, progress: &Progress) -> Result<()> {
     Ok(())
 }
 
+/// Checks the downloaded Quarto bundle against the SHA-256 checksum published
+/// with the release, or, when the checksum list cannot be fetched, that it is
+/// at least a complete gzip archive.
+fn verify_quarto_bundle(
+    shell: &Shell,
+    tarball_path: &Path,
+    release_url: &str,
+    asset_name: &str,
+    progress: &Progress,
+) -> Result<()> {
+    let task = progress.task(format!("Verifying Quarto {QUARTO_VERSION} bundle"));
+    let checksums_url = format!("{release_url}/quarto-{QUARTO_VERSION}-checksums.txt");
+    let expected = cmd!(shell, "curl -fsSL {checksums_url}")
+        .quiet()
+        .read()
+        .ok()
+        .and_then(|listing| published_checksum(&listing, asset_name));
+
+    let Some(expected) = expected else {
+        let valid_gzip = cmd!(shell, "gzip -t {tarball_path}")
+            .quiet()
+            .ignore_status()
+            .output()
+            .is_ok_and(|output| output.status.success());
+        if !valid_gzip {
+            task.fail(format!("Quarto {QUARTO_VERSION} bundle is corrupt"));
+            bail!(
+                "the downloaded Quarto bundle {} is not a valid gzip archive; rerun to download it again",
+                tarball_path.display()
+            );
+        }
+        task.finish_with_message(format!(
+            "No published checksum found; Quarto {QUARTO_VERSION} bundle is a valid gzip archive"
+        ));
+        return Ok(());
+    };
+
+    let actual = cmd!(shell, "sha256sum {tarball_path}")
+        .quiet()
+        .read()
+        .context("failed to compute the Quarto bundle checksum")?
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if actual != expected {
+        task.fail(format!("Quarto {QUARTO_VERSION} checksum mismatch"));
+        bail!(
+            "checksum mismatch for {asset_name}: expected SHA-256 {expected}, got {actual}; the download is corrupt, rerun to download it again"
+        );
+    }
+    task.finish_with_message(format!("Verified Quarto {QUARTO_VERSION} checksum"));
+    Ok(())
+}
+
+/// Finds the SHA-256 of `asset_name` in a `sha256sum`-style listing.
+fn published_checksum(listing: &str, asset_name: &str) -> Option<String> {
+    listing.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let hash = fields.next()?;
+        let name = fields.next()?.trim_start_matches('*');
+        (name == asset_name && hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit()))
+            .then(|| hash.to_ascii_lowercase())
+    })
+}
+
 fn ensure_pandoc(shell: &Shell, progress: &Progress) -> Result<()> {
+    let sudo = util::sudo();
     let check_task = progress.task("Checking existing pandoc");
     let already_installed = cmd!(shell, "pandoc --version")
         .quiet()
@@ -304,7 +762,7 @@ fn ensure_pandoc(shell: &Shell, progress: &Progress) -> Result<()> {
         "apt metadata updated for pandoc",
         cmd!(
             shell,
-            "sudo env DEBIAN_FRONTEND=noninteractive apt-get update -y -qq"
+            "{sudo} env DEBIAN_FRONTEND=noninteractive apt-get update -y -qq"
         ),
     )?;
 
@@ -314,7 +772,7 @@ fn ensure_pandoc(shell: &Shell, progress: &Progress) -> Result<()> {
         "pandoc installed",
         cmd!(
             shell,
-            "sudo env DEBIAN_FRONTEND=noninteractive apt-get install -y pandoc"
+            "{sudo} env DEBIAN_FRONTEND=noninteractive apt-get install -y pandoc"
         ),
     )?;
 
@@ -323,12 +781,12 @@ fn ensure_pandoc(shell: &Shell, progress: &Progress) -> Result<()> {
     Ok(())
 }
 
-fn ensure_tinytex(shell: &Shell, progress: &Progress) -> Result<()> {
+fn ensure_tinytex(shell: &Shell, bin_dir: &Path, progress: &Progress) -> Result<()> {
     let check_task = progress.task("Checking existing TinyTeX");
     let already_installed = cmd!(shell, "tlmgr --version")
         .quiet()
         .ignore_status()
-        .run()
+        .output()
         .is_ok();
     if already_installed {
         check_task.finish_with_message("Using existing TinyTeX");
@@ -352,11 +810,12 @@ fn ensure_tinytex(shell: &Shell, progress: &Progress) -> Result<()> {
             for binary in ["tlmgr", "pdflatex", "xelatex", "lualatex"] {
                 let source = format!("{tinytex_bin}/{binary}");
                 if Path::new(&source).exists() {
+                    let link = bin_dir.join(binary);
                     run_command(
                         progress,
                         format!("Linking TinyTeX {binary}"),
-                        format!("Linked /usr/local/bin/{binary}"),
-                        cmd!(shell, "sudo ln -sf {source} /usr/local/bin/{binary}"),
+                        format!("Linked {}", link.display()),
+                        link_command(shell, &source, &link),
                     )?;
                 }
             }
@@ -368,7 +827,19 @@ fn ensure_tinytex(shell: &Shell, progress: &Progress) -> Result<()> {
     Ok(())
 }
 
+/// Installs `packages` into TinyTeX up front, instead of letting vignette
+/// builds install them one at a time during the check.
+fn install_latex_packages(shell: &Shell, packages: &[String], progress: &Progress) -> Result<()> {
+    run_command(
+        progress,
+        format!("Installing LaTeX packages: {}", packages.join(", ")),
+        format!("Installed {} LaTeX packages", packages.len()),
+        cmd!(shell, "tlmgr install {packages...}"),
+    )
+}
+
 fn ensure_curl(shell: &Shell, progress: &Progress) -> Result<()> {
+    let sudo = util::sudo();
     if cmd!(shell, "curl --version")
         .quiet()
         .ignore_status()
@@ -385,7 +856,7 @@ fn ensure_curl(shell: &Shell, progress: &Progress) -> Result<()> {
         "apt metadata updated for curl",
         cmd!(
             shell,
-            "sudo env DEBIAN_FRONTEND=noninteractive apt-get update -y -qq"
+            "{sudo} env DEBIAN_FRONTEND=noninteractive apt-get update -y -qq"
         ),
     )?;
 
@@ -395,14 +866,13 @@ fn ensure_curl(shell: &Shell, progress: &Progress) -> Result<()> {
         "curl installed",
         cmd!(
             shell,
-            "sudo env DEBIAN_FRONTEND=noninteractive apt-get install -y curl"
+            "{sudo} env DEBIAN_FRONTEND=noninteractive apt-get install -y curl"
         ),
     )
 }
 
-fn http_client() -> Result<Client> {
-    Client::builder()
-        .user_agent(format!("revdeprun/{}", env!("CARGO_PKG_VERSION")))
+fn http_client(http: &HttpOptions) -> Result<Client> {
+    http.client_builder()?
         .build()
         .context("failed to construct HTTP client")
 }
@@ -417,3 +887,129 @@ fn file_name_from_url(url: &str) -> Result<String> {
         .map(|segment| segment.to_string())
         .ok_or_else(|| anyhow::anyhow!("failed to extract file name from {url}"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_empty_truncated_or_foreign_installers() {
+        let header = b"!<arch>\ndebian-binary";
+        assert!(verify_installer(1024, Some(1024), header).is_ok());
+        assert!(verify_installer(1024, None, header).is_ok());
+
+        let err = verify_installer(0, Some(0), b"").expect_err("empty");
+        assert!(err.to_string().contains("empty"));
+        let err = verify_installer(512, Some(1024), header).expect_err("truncated");
+        assert!(err.to_string().contains("512 bytes"));
+        let err = verify_installer(9, None, b"<html>404").expect_err("not a deb");
+        assert!(err.to_string().contains("not a Debian package"));
+    }
+
+    #[test]
+    fn finds_published_quarto_checksums() {
+        let hash = "a".repeat(64);
+        let listing = format!(
+            "{}  quarto-1.8.25-linux-arm64.tar.gz\n{}  quarto-1.8.25-linux-amd64.tar.gz\n",
+            "b".repeat(64),
+            hash.to_ascii_uppercase()
+        );
+        assert_eq!(
+            published_checksum(&listing, "quarto-1.8.25-linux-amd64.tar.gz"),
+            Some(hash)
+        );
+        assert_eq!(published_checksum(&listing, "quarto-1.8.25-win.zip"), None);
+        assert_eq!(
+            published_checksum("<html>Not Found</html>", "quarto-1.8.25-linux-amd64.tar.gz"),
+            None
+        );
+    }
+
+    #[test]
+    fn rewrites_installer_url_host() {
+        let url = "https://cdn.posit.co/r/ubuntu-2204/pkgs/r-4.5.1_1_amd64.deb";
+        assert_eq!(
+            mirror_url(url, "https://mirror.example.com").expect("mirror url"),
+            "https://mirror.example.com/r/ubuntu-2204/pkgs/r-4.5.1_1_amd64.deb"
+        );
+        assert_eq!(
+            mirror_url(url, "http://mirror.example.com:8080/r-builds/").expect("mirror url"),
+            "http://mirror.example.com:8080/r-builds/r/ubuntu-2204/pkgs/r-4.5.1_1_amd64.deb"
+        );
+        assert!(mirror_url(url, "not a url").is_err());
+    }
+
+    #[test]
+    fn links_r_binaries_into_bin_dir() {
+        let version = ResolvedRVersion {
+            version: "4.5.1".to_string(),
+            url: "https://example.com/R.deb".to_string(),
+            kind: None,
+            prefix: None,
+        };
+        let links = r_symlinks(&version, Path::new("/home/ci/.local/bin"));
+        assert_eq!(
+            links[0],
+            (
+                "/home/ci/.local/bin/R".to_string(),
+                "/opt/R/4.5.1/bin/R".to_string()
+            )
+        );
+        assert_eq!(links[1].0, "/home/ci/.local/bin/Rscript");
+    }
+
+    #[test]
+    fn detects_links_to_another_installation() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let [current, other] = ["4.5.1", "4.4.3"].map(|version| {
+            let binary = dir.path().join(version).join("R");
+            fs::create_dir_all(binary.parent().expect("parent")).expect("prefix");
+            fs::write(&binary, "").expect("binary");
+            binary
+        });
+        let link = dir.path().join("R");
+        std::os::unix::fs::symlink(&other, &link).expect("symlink");
+
+        assert!(links_elsewhere(&link, &current));
+        assert!(!links_elsewhere(&link, &other));
+        assert!(!links_elsewhere(&link, &dir.path().join("missing")));
+    }
+
+    #[test]
+    fn reports_missing_rscript() {
+        let shell = Shell::new().expect("shell");
+        let empty = tempfile::tempdir().expect("tempdir");
+        shell.set_var("PATH", empty.path());
+        let err = ensure_rscript(&shell).expect_err("no Rscript on PATH");
+        assert!(err.to_string().contains("--skip-r-install"));
+    }
+
+    #[test]
+    fn parses_r_version_banners() {
+        assert_eq!(
+            parse_r_banner("R version 4.4.1 (2024-06-14) -- \"Race for Your Life\"\nCopyright"),
+            Some(("4.4.1".to_string(), None))
+        );
+        assert_eq!(
+            parse_r_banner(
+                "R Under development (unstable) (2025-06-01 r88000) -- \"Unsuffered Consequences\""
+            ),
+            Some(("devel".to_string(), Some("devel".to_string())))
+        );
+        assert_eq!(parse_r_banner("bash: R: command not found"), None);
+        assert!(is_local_prefix("/custom/R/4.4.1"));
+        assert!(!is_local_prefix("oldrel-1"));
+    }
+
+    #[test]
+    fn links_r_binaries_from_a_custom_prefix() {
+        let version = ResolvedRVersion {
+            version: "4.4.1".to_string(),
+            url: String::new(),
+            kind: None,
+            prefix: Some(PathBuf::from("/custom/R/4.4.1")),
+        };
+        let links = r_symlinks(&version, Path::new("/usr/local/bin"));
+        assert_eq!(links[1].1, "/custom/R/4.4.1/bin/Rscript");
+    }
+}
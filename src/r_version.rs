@@ -1,11 +1,20 @@
-use std::{collections::HashMap, env, fs};
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{Context, Result};
 use reqwest::blocking::Client;
 use serde::Deserialize;
 
+use crate::{progress::Progress, util::HttpOptions};
+
 const API_ENDPOINT: &str = "https://api.r-hub.io/rversions/resolve";
 
+/// Version specifier used when neither `--r-version` nor a pinned version is given.
+pub const DEFAULT_SPEC: &str = "release";
+
 /// Metadata describing a resolved R toolchain download.
 #[derive(Debug, Clone, Deserialize)]
 pub struct ResolvedRVersion {
@@ -16,20 +25,125 @@ pub struct ResolvedRVersion {
     /// Build type, used to detect special channels like `next` or `devel`.
     #[serde(rename = "type")]
     pub kind: Option<String>,
+    /// Prefix of an existing installation named by `--r-version PATH`, used
+    /// instead of `/opt/R/<version>`.
+    #[serde(skip)]
+    pub prefix: Option<PathBuf>,
 }
 
 impl ResolvedRVersion {
     /// Returns the directory name used under `/opt/R/` by the upstream installer.
+    ///
+    /// The Posit `.deb` packages are named `r-<dir>_<build>_<arch>.deb` after
+    /// the directory they install into, so that name wins; otherwise
+    /// development channels use their channel name and releases their version.
     pub fn install_dir_name(&self) -> &str {
+        if let Some(name) = deb_install_dir(&self.url) {
+            return name;
+        }
         match self.kind.as_deref() {
             Some(kind @ ("next" | "devel")) => kind,
             _ => self.version.as_str(),
         }
     }
+
+    /// Returns the path of `binary` (e.g. `Rscript`) in this installation.
+    pub fn binary_path(&self, binary: &str) -> PathBuf {
+        match &self.prefix {
+            Some(prefix) => prefix.join("bin").join(binary),
+            None => Path::new("/opt/R")
+                .join(self.install_dir_name())
+                .join("bin")
+                .join(binary),
+        }
+    }
+
+    /// Returns `true` for development channels (`next`, `devel`) that Posit
+    /// binary packages are not built against.
+    pub fn is_development(&self) -> bool {
+        matches!(self.kind.as_deref(), Some("next" | "devel"))
+            || matches!(deb_install_dir(&self.url), Some("next" | "devel"))
+    }
+}
+
+/// Extracts `<dir>` from an installer URL ending in `r-<dir>_<build>_<arch>.deb`.
+fn deb_install_dir(url: &str) -> Option<&str> {
+    let file = url.rsplit('/').next()?.strip_suffix(".deb")?;
+    let (name, _) = file.strip_prefix("r-")?.split_once('_')?;
+    (!name.is_empty()).then_some(name)
+}
+
+/// R version pinned by a file in the target repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinnedVersion {
+    /// Version specifier read from the file.
+    pub spec: String,
+    /// Name of the file the version was read from.
+    pub source: &'static str,
+}
+
+/// Reads the R version pinned in `.Rversion` or, failing that, the `R.Version`
+/// field of `renv.lock` at the root of `repo_path`. An `renv.lock` that cannot
+/// be read or parsed is ignored with a warning.
+pub fn pinned_version(repo_path: &Path, progress: &Progress) -> Result<Option<PinnedVersion>> {
+    let rversion_path = repo_path.join(".Rversion");
+    if rversion_path.is_file() {
+        let contents = fs::read_to_string(&rversion_path)
+            .with_context(|| format!("failed to read {}", rversion_path.display()))?;
+        if let Some(spec) = parse_rversion_file(&contents) {
+            return Ok(Some(PinnedVersion {
+                spec,
+                source: ".Rversion",
+            }));
+        }
+    }
+
+    let lockfile_path = repo_path.join("renv.lock");
+    if lockfile_path.is_file() {
+        let spec = fs::read_to_string(&lockfile_path)
+            .with_context(|| format!("failed to read {}", lockfile_path.display()))
+            .and_then(|contents| {
+                parse_renv_lock(&contents)
+                    .with_context(|| format!("failed to parse {}", lockfile_path.display()))
+            });
+        let spec = spec.unwrap_or_else(|err| {
+            progress.println(format!(
+                "Warning: ignoring the R version in renv.lock: {err:#}"
+            ));
+            None
+        });
+        if let Some(spec) = spec {
+            return Ok(Some(PinnedVersion {
+                spec,
+                source: "renv.lock",
+            }));
+        }
+    }
+
+    Ok(None)
+}
+
+fn parse_rversion_file(contents: &str) -> Option<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+}
+
+fn parse_renv_lock(contents: &str) -> Result<Option<String>> {
+    let lockfile: serde_json::Value = serde_json::from_str(contents)?;
+    Ok(lockfile
+        .get("R")
+        .and_then(|r| r.get("Version"))
+        .and_then(|version| version.as_str())
+        .map(str::trim)
+        .filter(|version| !version.is_empty())
+        .map(str::to_string))
 }
 
 /// Resolves the user provided version specifier to a concrete installer download.
-pub fn resolve(spec: &str) -> Result<ResolvedRVersion> {
+pub fn resolve(spec: &str, http: &HttpOptions) -> Result<ResolvedRVersion> {
     let normalized = normalize_spec(spec);
     let platform = linux_platform().context("failed to determine Linux distribution")?;
     let mut url = format!("{API_ENDPOINT}/{normalized}/{platform}");
@@ -39,7 +153,7 @@ pub fn resolve(spec: &str) -> Result<ResolvedRVersion> {
         url.push_str(arch);
     }
 
-    let client = http_client()?;
+    let client = http_client(http)?;
     let response = client
         .get(url.clone())
         .send()
@@ -52,17 +166,22 @@ pub fn resolve(spec: &str) -> Result<ResolvedRVersion> {
         .with_context(|| format!("failed to decode version metadata from {url}"))
 }
 
-fn http_client() -> Result<Client> {
-    Client::builder()
-        .user_agent(format!("revdeprun/{}", env!("CARGO_PKG_VERSION")))
+fn http_client(http: &HttpOptions) -> Result<Client> {
+    http.client_builder()?
         .build()
         .context("failed to create HTTP client")
 }
 
 /// Normalises the version specification following the behaviour of setup-r.
+///
+/// Development channels map to the r-hub names: `devel` for R-devel (also
+/// `r-devel`, `development`) and `next` for the upcoming or patched release
+/// (also `patched`, `r-patched`, `prerelease`).
 pub fn normalize_spec(spec: &str) -> String {
-    match spec.trim() {
-        "latest" | "4" | "4.x" | "4.x.x" => "release".to_string(),
+    match spec.trim().to_ascii_lowercase().as_str() {
+        "latest" | "4" | "4.x" | "4.x.x" | "r-release" => "release".to_string(),
+        "devel" | "r-devel" | "development" => "devel".to_string(),
+        "next" | "patched" | "r-patched" | "prerelease" | "pre-release" => "next".to_string(),
         "3" | "3.x" | "3.x.x" => "3.6.3".to_string(),
         value if value.ends_with(".x") => value.trim_end_matches(".x").to_string(),
         value if value.starts_with("oldrel-") => value.replacen("oldrel-", "oldrel/", 1),
@@ -83,7 +202,9 @@ fn detect_arch() -> Option<&'static str> {
     }
 }
 
-fn linux_platform() -> Result<String> {
+/// Returns the `linux-<distro>-<version>` platform string for this machine,
+/// honouring the `REVDEPRUN_LINUX_PLATFORM` override.
+pub fn linux_platform() -> Result<String> {
     if let Ok(override_value) = env::var("REVDEPRUN_LINUX_PLATFORM") {
         if !override_value.trim().is_empty() {
             return Ok(override_value);
@@ -136,6 +257,108 @@ mod tests {
         assert_eq!(normalize_spec("4.2.x"), "4.2");
         assert_eq!(normalize_spec("oldrel-1"), "oldrel/1");
         assert_eq!(normalize_spec(" 4.3.2 "), "4.3.2");
+        assert_eq!(normalize_spec("devel"), "devel");
+        assert_eq!(normalize_spec("R-devel"), "devel");
+        assert_eq!(normalize_spec("next"), "next");
+        assert_eq!(normalize_spec("patched"), "next");
+        assert_eq!(normalize_spec("r-patched"), "next");
+        assert_eq!(normalize_spec("prerelease"), "next");
+        assert_eq!(normalize_spec("Release"), "release");
+    }
+
+    #[test]
+    fn install_dir_follows_the_deb_name() {
+        let version = |kind: Option<&str>, url: &str| ResolvedRVersion {
+            version: "4.6.0".to_string(),
+            url: url.to_string(),
+            kind: kind.map(str::to_string),
+            prefix: None,
+        };
+        let cdn = "https://cdn.posit.co/r/ubuntu-2404/pkgs";
+
+        let devel = version(Some("devel"), &format!("{cdn}/r-devel_1_amd64.deb"));
+        assert_eq!(devel.install_dir_name(), "devel");
+        assert_eq!(devel.binary_path("R"), Path::new("/opt/R/devel/bin/R"));
+
+        let next = version(None, &format!("{cdn}/r-next_1_arm64.deb"));
+        assert_eq!(next.install_dir_name(), "next");
+        assert!(next.is_development());
+
+        let release = version(Some("release"), &format!("{cdn}/r-4.5.1_1_amd64.deb"));
+        assert_eq!(release.install_dir_name(), "4.5.1");
+        assert!(!release.is_development());
+
+        let other = version(Some("devel"), "https://example.com/R.deb");
+        assert_eq!(other.install_dir_name(), "devel");
+    }
+
+    #[test]
+    fn detects_development_channels() {
+        let version = |kind: Option<&str>| ResolvedRVersion {
+            version: "4.6.0".to_string(),
+            url: "https://example.com/R.deb".to_string(),
+            kind: kind.map(str::to_string),
+            prefix: None,
+        };
+
+        assert!(version(Some("devel")).is_development());
+        assert!(version(Some("next")).is_development());
+        assert!(!version(Some("release")).is_development());
+        assert!(!version(None).is_development());
+    }
+
+    #[test]
+    fn reads_pinned_versions() {
+        assert_eq!(
+            parse_rversion_file("# pinned\n\n 4.3.1 \n").as_deref(),
+            Some("4.3.1")
+        );
+        assert_eq!(parse_rversion_file("\n"), None);
+
+        let lockfile = r#"{"R": {"Version": "4.4.1", "Repositories": []}, "Packages": {}}"#;
+        assert_eq!(
+            parse_renv_lock(lockfile)
+                .expect("valid lockfile")
+                .as_deref(),
+            Some("4.4.1")
+        );
+        assert_eq!(parse_renv_lock(r#"{"Packages": {}}"#).expect("valid"), None);
+        assert!(parse_renv_lock("not json").is_err());
+    }
+
+    #[test]
+    fn ignores_malformed_renv_lock() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        fs::write(tmp.path().join("renv.lock"), "{ not json").expect("renv.lock");
+        assert_eq!(
+            pinned_version(tmp.path(), &Progress::new()).expect("pinned"),
+            None
+        );
+    }
+
+    #[test]
+    fn rversion_file_takes_precedence_over_renv_lock() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        fs::write(
+            tmp.path().join("renv.lock"),
+            r#"{"R": {"Version": "4.4.1"}}"#,
+        )
+        .expect("renv.lock");
+        assert_eq!(
+            pinned_version(tmp.path(), &Progress::new()).expect("pinned"),
+            Some(PinnedVersion {
+                spec: "4.4.1".to_string(),
+                source: "renv.lock",
+            })
+        );
+
+        fs::write(tmp.path().join(".Rversion"), "4.3.3\n").expect(".Rversion");
+        assert_eq!(
+            pinned_version(tmp.path(), &Progress::new())
+                .expect("pinned")
+                .map(|pinned| pinned.spec),
+            Some("4.3.3".to_string())
+        );
     }
 
     #[test]
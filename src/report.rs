@@ -0,0 +1,499 @@
+use std::{
+    fmt::Write as _,
+    fs::{self, OpenOptions},
+    io::Write as _,
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::{
+    cli::{ReportFormat, ReportTarget},
+    revdep::DiskUsage,
+    summary::{self, CheckOutcome, CheckStatus, CheckSummary, PackageResult},
+};
+
+/// JSON report of one run, or of one R version of a matrix.
+#[derive(Serialize)]
+struct JsonRun<'a> {
+    r_version: &'a str,
+    counts: JsonCounts,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disk_usage: Option<JsonDiskUsage>,
+    results: Vec<JsonResult<'a>>,
+}
+
+/// Number of revdeps per status, keyed like [`CheckStatus::key`].
+#[derive(Serialize)]
+struct JsonCounts {
+    ok: usize,
+    failed: usize,
+    timed_out: usize,
+    not_checked: usize,
+}
+
+#[derive(Serialize)]
+struct JsonDiskUsage {
+    library_bytes: u64,
+    repository_bytes: u64,
+}
+
+#[derive(Serialize)]
+struct JsonResult<'a> {
+    package: &'a str,
+    status: &'static str,
+    excerpt: Option<&'a str>,
+}
+
+/// JSON report document: the target package and its runs.
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    package: &'a str,
+    version: Option<&'a str>,
+    runs: Vec<JsonRun<'a>>,
+}
+
+impl<'a> JsonRun<'a> {
+    fn new(summary: &'a CheckSummary, r_version: &'a str, disk_usage: Option<DiskUsage>) -> Self {
+        Self {
+            r_version,
+            counts: JsonCounts {
+                ok: summary.count(CheckStatus::Ok),
+                failed: summary.count(CheckStatus::Failed),
+                timed_out: summary.count(CheckStatus::TimedOut),
+                not_checked: summary.count(CheckStatus::NotChecked),
+            },
+            disk_usage: disk_usage.map(|usage| JsonDiskUsage {
+                library_bytes: usage.library,
+                repository_bytes: usage.repository,
+            }),
+            results: summary.results.iter().map(JsonResult::from).collect(),
+        }
+    }
+}
+
+impl<'a> From<&'a PackageResult> for JsonResult<'a> {
+    fn from(result: &'a PackageResult) -> Self {
+        Self {
+            package: &result.package,
+            status: result.status.key(),
+            excerpt: result.excerpt.as_deref(),
+        }
+    }
+}
+
+/// Writes the report for `summary` to `target`, in its format.
+pub fn write(
+    target: &ReportTarget,
+    summary: &CheckSummary,
+    r_version: &str,
+    disk_usage: DiskUsage,
+) -> Result<()> {
+    let contents = match target.format {
+        ReportFormat::Markdown => render_markdown(summary, r_version, disk_usage),
+        ReportFormat::Json => render_json(
+            summary,
+            vec![JsonRun::new(summary, r_version, Some(disk_usage))],
+        )?,
+    };
+    write_file(&target.path, &contents)
+}
+
+/// Writes a report comparing the runs of an `--r-version` matrix to `target`:
+/// one status column per R version in Markdown, one run per R version in JSON.
+pub fn write_matrix(target: &ReportTarget, outcomes: &[CheckOutcome]) -> Result<()> {
+    let contents = match (target.format, outcomes.first()) {
+        (ReportFormat::Json, Some(first)) => render_json(
+            &first.summary,
+            outcomes
+                .iter()
+                .map(|outcome| JsonRun::new(&outcome.summary, &outcome.r_version, None))
+                .collect(),
+        )?,
+        _ => render_matrix_markdown(outcomes),
+    };
+    write_file(&target.path, &contents)
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<()> {
+    create_parent_dir(path)?;
+    fs::write(path, contents)
+        .with_context(|| format!("failed to write report to {}", path.display()))
+}
+
+fn render_json(summary: &CheckSummary, runs: Vec<JsonRun<'_>>) -> Result<String> {
+    let report = JsonReport {
+        package: &summary.package,
+        version: summary.version.as_deref(),
+        runs,
+    };
+    let mut json =
+        serde_json::to_string_pretty(&report).context("failed to serialise the JSON report")?;
+    json.push('\n');
+    Ok(json)
+}
+
+fn render_matrix_markdown(outcomes: &[CheckOutcome]) -> String {
+    let mut out = String::new();
+    let target = outcomes
+        .first()
+        .map(|outcome| match &outcome.summary.version {
+            Some(version) => format!("{} {version}", outcome.summary.package),
+            None => outcome.summary.package.clone(),
+        })
+        .unwrap_or_default();
+
+    let _ = writeln!(
+        out,
+        "# Reverse dependency check across R versions: {target}\n"
+    );
+    let labels = CheckStatus::ALL.map(CheckStatus::label);
+    let _ = writeln!(out, "| R version | {} |", labels.join(" | "));
+    let _ = writeln!(out, "| --- |{}", " --- |".repeat(labels.len()));
+    for outcome in outcomes {
+        let counts = CheckStatus::ALL.map(|status| outcome.summary.count(status).to_string());
+        let _ = writeln!(out, "| {} | {} |", outcome.r_version, counts.join(" | "));
+    }
+
+    let rows = summary::matrix_rows(outcomes);
+    if rows.is_empty() {
+        let _ = writeln!(out, "\nNo reverse dependencies were checked.");
+        return out;
+    }
+
+    let _ = writeln!(out, "\n## Results\n");
+    let versions = outcomes
+        .iter()
+        .map(|outcome| format!("R {}", outcome.r_version))
+        .collect::<Vec<_>>();
+    let _ = writeln!(out, "| Package | {} |", versions.join(" | "));
+    let _ = writeln!(out, "| --- |{}", " --- |".repeat(versions.len()));
+    let mut differing = Vec::new();
+    for (package, statuses) in &rows {
+        let cells = statuses
+            .iter()
+            .map(|status| status.map_or("-", CheckStatus::label))
+            .collect::<Vec<_>>();
+        let _ = writeln!(out, "| {package} | {} |", cells.join(" | "));
+        if statuses.windows(2).any(|pair| pair[0] != pair[1]) {
+            differing.push(package.as_str());
+        }
+    }
+
+    if !differing.is_empty() {
+        let _ = writeln!(out, "\n## Differences\n");
+        let _ = writeln!(
+            out,
+            "Status differs across R versions for: {}",
+            differing.join(", ")
+        );
+    }
+    out
+}
+
+/// Appends a `## revdepcheck results` section for `summary` to the
+/// `cran-comments.md` at `path`, creating the file if needed.
+pub fn append_cran_comments(path: &Path, summary: &CheckSummary) -> Result<()> {
+    create_parent_dir(path)?;
+    let existing = fs::read_to_string(path).unwrap_or_default();
+    let separator = match existing.as_str() {
+        "" => "",
+        text if text.ends_with("\n\n") => "",
+        text if text.ends_with('\n') => "\n",
+        _ => "\n\n",
+    };
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    write!(file, "{separator}{}", render_cran_comments(summary))
+        .with_context(|| format!("failed to append revdep results to {}", path.display()))
+}
+
+fn create_parent_dir(path: &Path) -> Result<()> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create report directory {}", parent.display()))?;
+    }
+    Ok(())
+}
+
+/// Renders the section in the layout of `revdepcheck::revdep_report_cran()`,
+/// which CRAN reviewers are used to.
+fn render_cran_comments(summary: &CheckSummary) -> String {
+    let new_problems = summary
+        .results
+        .iter()
+        .filter(|result| result.status == CheckStatus::Failed)
+        .collect::<Vec<_>>();
+    let not_checked = summary
+        .results
+        .iter()
+        .filter(|result| {
+            matches!(
+                result.status,
+                CheckStatus::TimedOut | CheckStatus::NotChecked
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut out = String::new();
+    let _ = writeln!(out, "## revdepcheck results\n");
+    let _ = writeln!(
+        out,
+        "We checked {} reverse dependencies, comparing R CMD check results across CRAN and dev versions of this package.\n",
+        summary.results.len()
+    );
+    let _ = writeln!(out, " * We saw {} new problems", new_problems.len());
+    let _ = writeln!(out, " * We failed to check {} packages", not_checked.len());
+
+    if new_problems.is_empty() && not_checked.is_empty() {
+        return out;
+    }
+    let _ = writeln!(out, "\nIssues with CRAN packages are summarised below.");
+    if !new_problems.is_empty() {
+        let _ = writeln!(out, "\n### New problems");
+        for result in new_problems {
+            let _ = writeln!(out, "\n* {}", result.package);
+            let checks = result
+                .excerpt
+                .iter()
+                .flat_map(|excerpt| excerpt.lines())
+                .filter_map(|line| line.strip_prefix("* "));
+            for check in checks {
+                let _ = writeln!(out, "  {check}");
+            }
+        }
+    }
+    if !not_checked.is_empty() {
+        let _ = writeln!(out, "\n### Failed to check\n");
+        for result in not_checked {
+            let _ = writeln!(out, "* {} ({})", result.package, result.status.label());
+        }
+    }
+    out
+}
+
+fn render_markdown(summary: &CheckSummary, r_version: &str, disk_usage: DiskUsage) -> String {
+    let mut out = String::new();
+    let target = match &summary.version {
+        Some(version) => format!("{} {version}", summary.package),
+        None => summary.package.clone(),
+    };
+
+    let _ = writeln!(out, "# Reverse dependency check: {target}\n");
+    let _ = writeln!(out, "- Target package: {target}");
+    let _ = writeln!(out, "- R version: {r_version}");
+    let _ = writeln!(
+        out,
+        "- Reverse dependencies checked: {}",
+        summary.results.len()
+    );
+    for status in CheckStatus::ALL {
+        let _ = writeln!(out, "- {}: {}", status.label(), summary.count(status));
+    }
+    let _ = writeln!(out, "- Disk usage: {disk_usage}");
+
+    if summary.results.is_empty() {
+        let _ = writeln!(out, "\nNo reverse dependencies were checked.");
+        return out;
+    }
+
+    let _ = writeln!(out, "\n## Results\n");
+    let _ = writeln!(out, "| Package | Status |");
+    let _ = writeln!(out, "| --- | --- |");
+    for result in &summary.results {
+        let _ = writeln!(out, "| {} | {} |", result.package, result.status.label());
+    }
+
+    let problems = summary.problems().collect::<Vec<_>>();
+    if !problems.is_empty() {
+        let _ = writeln!(out, "\n## Problems");
+        for result in problems {
+            let _ = writeln!(
+                out,
+                "\n<details>\n<summary>{} ({})</summary>\n",
+                result.package,
+                result.status.label()
+            );
+            match &result.excerpt {
+                Some(excerpt) => {
+                    let _ = writeln!(out, "```\n{excerpt}\n```");
+                }
+                None => {
+                    let _ = writeln!(out, "No check log excerpt available.");
+                }
+            }
+            let _ = writeln!(out, "\n</details>");
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_counts_table_and_problem_details() {
+        let summary = CheckSummary {
+            package: "ggsci".to_string(),
+            version: Some("3.2.0".to_string()),
+            results: vec![
+                PackageResult {
+                    package: "pkgA".to_string(),
+                    status: CheckStatus::Ok,
+                    excerpt: None,
+                    severity: None,
+                },
+                PackageResult {
+                    package: "pkgB".to_string(),
+                    status: CheckStatus::Failed,
+                    excerpt: Some("* checking tests ... ERROR".to_string()),
+                    severity: None,
+                },
+            ],
+        };
+
+        let disk_usage = DiskUsage {
+            library: 3 * 1024 * 1024 * 1024,
+            repository: 4 * 1024 * 1024 * 1024,
+        };
+        let markdown = render_markdown(&summary, "4.5.1", disk_usage);
+        assert!(markdown.contains("# Reverse dependency check: ggsci 3.2.0"));
+        assert!(markdown.contains("- R version: 4.5.1"));
+        assert!(markdown.contains("- Failed: 1"));
+        assert!(markdown.contains("- Disk usage: 4.0 GiB in total, 3.0 GiB in revdep/library"));
+        assert!(markdown.contains("| pkgB | Failed |"));
+        assert!(markdown.contains("<summary>pkgB (Failed)</summary>"));
+        assert!(markdown.contains("* checking tests ... ERROR"));
+        assert!(!markdown.contains("<summary>pkgA"));
+    }
+
+    #[test]
+    fn writes_every_requested_format() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let summary = CheckSummary {
+            package: "ggsci".to_string(),
+            version: Some("3.2.0".to_string()),
+            results: vec![PackageResult {
+                package: "pkgB".to_string(),
+                status: CheckStatus::Failed,
+                excerpt: Some("* checking tests ... ERROR".to_string()),
+                severity: None,
+            }],
+        };
+        let disk_usage = DiskUsage {
+            library: 1024,
+            repository: 2048,
+        };
+        for (format, name) in [("json", "report.json"), ("md", "report.md")] {
+            let spec = format!("{format}={}", dir.path().join("out").join(name).display());
+            let target = spec.parse::<ReportTarget>().expect("target");
+            write(&target, &summary, "4.5.1", disk_usage).expect("report");
+        }
+
+        let json: serde_json::Value = serde_json::from_str(
+            &fs::read_to_string(dir.path().join("out/report.json")).expect("json"),
+        )
+        .expect("valid JSON");
+        assert_eq!(json["package"], "ggsci");
+        assert_eq!(json["runs"][0]["r_version"], "4.5.1");
+        assert_eq!(json["runs"][0]["counts"]["failed"], 1);
+        assert_eq!(json["runs"][0]["disk_usage"]["library_bytes"], 1024);
+        assert_eq!(json["runs"][0]["results"][0]["status"], "failed");
+        assert!(
+            fs::read_to_string(dir.path().join("out/report.md"))
+                .expect("markdown")
+                .contains("| pkgB | Failed |")
+        );
+    }
+
+    #[test]
+    fn renders_r_version_matrix() {
+        let outcome = |r_version: &str, status| CheckOutcome {
+            r_version: r_version.to_string(),
+            summary: CheckSummary {
+                package: "ggsci".to_string(),
+                version: Some("3.2.0".to_string()),
+                results: vec![
+                    PackageResult {
+                        package: "pkgA".to_string(),
+                        status: CheckStatus::Ok,
+                        excerpt: None,
+                        severity: None,
+                    },
+                    PackageResult {
+                        package: "pkgB".to_string(),
+                        status,
+                        excerpt: None,
+                        severity: None,
+                    },
+                ],
+            },
+        };
+        let markdown = render_matrix_markdown(&[
+            outcome("4.5.1", CheckStatus::Ok),
+            outcome("4.4.3", CheckStatus::Failed),
+        ]);
+        assert!(markdown.contains("# Reverse dependency check across R versions: ggsci 3.2.0"));
+        assert!(markdown.contains("| R version | OK | Failed | Timed out | Not checked |"));
+        assert!(markdown.contains("| 4.4.3 | 1 | 1 | 0 | 0 |"));
+        assert!(markdown.contains("| Package | R 4.5.1 | R 4.4.3 |"));
+        assert!(markdown.contains("| pkgB | OK | Failed |"));
+        assert!(markdown.contains("Status differs across R versions for: pkgB"));
+    }
+
+    #[test]
+    fn appends_cran_comments_section() {
+        let result = |package: &str, status, excerpt: Option<&str>| PackageResult {
+            package: package.to_string(),
+            status,
+            excerpt: excerpt.map(str::to_string),
+            severity: None,
+        };
+        let summary = CheckSummary {
+            package: "ggsci".to_string(),
+            version: Some("3.2.0".to_string()),
+            results: vec![
+                result("pkgA", CheckStatus::Ok, None),
+                result(
+                    "pkgB",
+                    CheckStatus::Failed,
+                    Some("* checking tests ... ERROR\n  Error: boom"),
+                ),
+                result("pkgC", CheckStatus::TimedOut, None),
+            ],
+        };
+
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("cran-comments.md");
+        fs::write(
+            &path,
+            "## R CMD check results\n\n0 errors | 0 warnings | 0 notes\n",
+        )
+        .expect("cran-comments.md");
+        append_cran_comments(&path, &summary).expect("append");
+
+        assert_eq!(
+            fs::read_to_string(&path).expect("read back"),
+            "## R CMD check results\n\n0 errors | 0 warnings | 0 notes\n\n\
+             ## revdepcheck results\n\n\
+             We checked 3 reverse dependencies, comparing R CMD check results across CRAN and dev versions of this package.\n\n\
+             \x20* We saw 1 new problems\n\
+             \x20* We failed to check 1 packages\n\n\
+             Issues with CRAN packages are summarised below.\n\n\
+             ### New problems\n\n\
+             * pkgB\n\
+             \x20 checking tests ... ERROR\n\n\
+             ### Failed to check\n\n\
+             * pkgC (Timed out)\n"
+        );
+    }
+}
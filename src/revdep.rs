@@ -1,99 +1,502 @@
 use std::{
-    env, fs,
-    io::Write,
+    borrow::Cow,
+    collections::HashMap,
+    env, fmt, fs,
+    io::{self, IsTerminal, Write},
+    ops::ControlFlow,
     path::{Path, PathBuf},
+    thread,
+    time::Duration,
 };
 
 use anyhow::{Context, Result, anyhow, bail};
-use tempfile::{NamedTempFile, tempdir_in};
+use tempfile::{NamedTempFile, TempDir, tempdir_in};
 use xshell::{Shell, cmd};
 
 use crate::{
-    progress::Progress,
-    util,
+    auth,
+    cli::{
+        BiocVersion, CloneDepth, Codename, CranType, EnvVar, Installer, PackageSpec, PkgFilter,
+        RevdepsSource,
+    },
+    description, error,
+    progress::{Progress, Task},
+    summary, util,
     workspace::{self, Workspace},
 };
 
+/// File in the revdep directory recording the target package version the
+/// revdep library was built against.
+const LIBRARY_STAMP_FILE: &str = "revdeprun-library-target";
+
+/// File in the revdep directory listing the revdeps of the last run, one per
+/// line, for `--continue-from`.
+const REVDEPS_FILE: &str = "revdeprun-revdeps.txt";
+
+/// File in the revdep directory listing packages that could not be installed.
+const INSTALL_FAILURES_FILE: &str = "revdeprun-install-failures.txt";
+
+/// File in the revdep directory listing the target dependencies installed
+/// before the target was built, so the revdep install does not repeat them.
+const TARGET_DEPS_FILE: &str = "revdeprun-target-deps.txt";
+
+/// Prefix of the `done/total` lines the install script prints after each batch.
+const INSTALL_PROGRESS_MARKER: &str = "revdeprun-install-progress";
+
+/// Summaries of check log differences that `xfun::rev_check()` writes when
+/// `xfun.rev_check.summary` is set, in order of preference.
+const CHECK_SUMMARY_FILES: [&str; 2] = ["00check_diffs.html", "00check_diffs.md"];
+
+/// CRAN-like repository used for package metadata and source installs unless
+/// `--cran-mirror` is given.
+pub const DEFAULT_CRAN_MIRROR: &str = "https://packagemanager.posit.co/cran/latest";
+
+/// Settings that control how the target repository is prepared.
+#[derive(Clone, Copy, Debug)]
+pub struct PrepareOptions {
+    /// Parallelism for submodule clones and `pigz` tarball decompression.
+    pub num_workers: usize,
+    /// Extra `git clone` attempts after transient network failures.
+    pub clone_retries: u32,
+    /// History fetched by `git clone`.
+    pub clone_depth: CloneDepth,
+}
+
+/// Package sources ready for checking.
+pub struct PreparedRepository {
+    /// Canonical path of the package sources.
+    pub path: PathBuf,
+    /// Guard over sources extracted from a tarball, `None` for checkouts and
+    /// local directories.
+    pub extraction: Option<ExtractionCleanup>,
+}
+
+impl From<PathBuf> for PreparedRepository {
+    fn from(path: PathBuf) -> Self {
+        Self {
+            path,
+            extraction: None,
+        }
+    }
+}
+
 /// Ensures a checkout of the target repository exists within the configured
 /// workspace clone root.
 ///
-/// Local paths are used as-is, while remote Git URLs are cloned.
+/// Local paths are used as-is, while remote Git URLs are cloned. The worker
+/// count in `options` controls how many submodules are fetched in parallel and
+/// how many threads `pigz` may use when decompressing tarballs.
 pub fn prepare_repository(
     shell: &Shell,
     workspace: &Workspace,
     spec: &str,
+    options: &PrepareOptions,
     progress: &Progress,
-) -> Result<PathBuf> {
+) -> Result<PreparedRepository> {
     let candidate = Path::new(spec);
     if candidate.exists() {
         if candidate.is_dir() {
-            return prepare_local_directory(candidate, progress);
+            workspace.check_local_package(candidate)?;
+            return prepare_local_directory(candidate, progress).map(PreparedRepository::from);
         } else if candidate.is_file() && is_tarball(candidate) {
-            return prepare_tarball(shell, workspace, candidate, progress);
+            let extraction =
+                prepare_tarball(shell, workspace, candidate, options.num_workers, progress)?;
+            return Ok(PreparedRepository {
+                path: extraction.path().to_path_buf(),
+                extraction: Some(extraction),
+            });
         } else if candidate.is_file() {
             bail!(
-                "unsupported local package input {}; expected a directory or .tar.gz archive",
-                candidate.display()
+                "unsupported local package input {}; expected a directory or {} archive",
+                candidate.display(),
+                ArchiveKind::supported_suffixes()
             );
         } else {
             bail!(
-                "unsupported package input {}; expected a directory or .tar.gz archive",
-                candidate.display()
+                "unsupported package input {}; expected a directory or {} archive",
+                candidate.display(),
+                ArchiveKind::supported_suffixes()
             );
         }
     }
 
-    fs::create_dir_all(workspace.clone_root()).with_context(|| {
-        format!(
-            "failed to create clone root directory {}",
-            workspace.clone_root().display()
-        )
-    })?;
+    workspace.ensure_clone_root_ready()?;
 
-    let repo_name = util::guess_repo_name(spec)
-        .ok_or_else(|| anyhow!("unable to infer repository name from {spec}"))?;
-    let destination = workspace.clone_root().join(repo_name);
+    let expanded = expand_forge_spec(spec);
+    let spec = expanded.as_deref().unwrap_or(spec);
+    let pull_request = github_pull_request(spec);
+    let clone_url = pull_request
+        .as_ref()
+        .map_or(spec, |(repo_url, _)| repo_url.as_str());
+    let destination = clone_destination(workspace, clone_url)?;
     if destination.exists() {
-        anyhow::bail!(
-            "refusing to clone into {} because the directory already exists",
+        match description::read_package_name(&destination) {
+            Ok(package) => bail!(
+                "refusing to clone into {} because it already holds a checkout of {package}; \
+                 remove it or pass --continue-from to reuse it",
+                destination.display()
+            ),
+            Err(_) => bail!(
+                "refusing to clone into {} because the directory already exists",
+                destination.display()
+            ),
+        }
+    }
+
+    clone_repository(shell, clone_url, &destination, options, progress)?;
+    if let Some((_, number)) = pull_request {
+        checkout_pull_request(shell, &destination, number, options, progress)?;
+    }
+
+    workspace::canonicalized(&destination).map(PreparedRepository::from)
+}
+
+/// Rejects a `repository` argument that cannot name a package source, such
+/// as the value of a misspelled flag that clap took for the positional
+/// argument (`--r-versio 4.4.1`), before any work starts.
+pub fn validate_repository_spec(spec: &str) -> Result<()> {
+    let path = Path::new(spec);
+    let looks_valid = path.exists()
+        || spec.contains('/')
+        || spec.starts_with(['.', '~'])
+        || spec.starts_with("git@")
+        || is_tarball(path)
+        || is_package_name(spec);
+    if !looks_valid {
+        bail!("'{spec}' doesn't look like a repository, path, or tarball; did you mean a flag?");
+    }
+    Ok(())
+}
+
+/// Whether `name` is a valid R package name: ASCII letters, digits, and
+/// dots, starting with a letter and not ending in a dot.
+fn is_package_name(name: &str) -> bool {
+    name.len() >= 2
+        && name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && !name.ends_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '.')
+}
+
+/// Returns the package directory an earlier run prepared for `spec`, for
+/// `--continue-from` and `--summary-only`: a local directory as is, or the existing clone of a
+/// remote repository. Extracted tarballs are not kept between runs, so they
+/// cannot be resumed.
+pub fn existing_repository(workspace: &Workspace, spec: &str) -> Result<PathBuf> {
+    let candidate = Path::new(spec);
+    if candidate.is_dir() {
+        workspace.check_local_package(candidate)?;
+        return workspace::canonicalized(candidate);
+    }
+    if candidate.is_file() {
+        bail!(
+            "reusing an earlier run needs a repository or local directory; extracted tarballs are not kept between runs"
+        );
+    }
+
+    let expanded = expand_forge_spec(spec);
+    let spec = expanded.as_deref().unwrap_or(spec);
+    let clone_url = github_pull_request(spec).map_or_else(|| spec.to_string(), |(url, _)| url);
+    let destination = clone_destination(workspace, &clone_url)?;
+    if !destination.join("DESCRIPTION").is_file() {
+        bail!(
+            "found no clone from an earlier run at {}; run a full check first",
             destination.display()
         );
     }
+    workspace::canonicalized(&destination)
+}
+
+/// Directory under the clone root that `clone_url` is cloned into.
+fn clone_destination(workspace: &Workspace, clone_url: &str) -> Result<PathBuf> {
+    let repo_name = util::guess_repo_name(clone_url)
+        .ok_or_else(|| anyhow!("unable to infer repository name from {clone_url}"))?;
+    workspace.check_clone_destination(&repo_name)?;
+    Ok(workspace.clone_root().join(repo_name))
+}
+
+/// Forge prefixes accepted in short specs such as `gitlab:org/pkg`, as in
+/// `pak` and `remotes`.
+const FORGE_PREFIXES: &[(&str, &str)] = &[
+    ("gh", "https://github.com"),
+    ("github", "https://github.com"),
+    ("gl", "https://gitlab.com"),
+    ("gitlab", "https://gitlab.com"),
+    ("bb", "https://bitbucket.org"),
+    ("bitbucket", "https://bitbucket.org"),
+];
+
+/// Expands a short spec such as `gl:org/pkg` into its HTTPS clone URL.
+/// GitLab specs may name nested groups (`gitlab:group/subgroup/pkg`).
+fn expand_forge_spec(spec: &str) -> Option<String> {
+    let (prefix, path) = spec.trim().split_once(':')?;
+    let (_, host) = FORGE_PREFIXES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(prefix))?;
+    let path = path.trim_end_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    let segments = path.split('/').collect::<Vec<_>>();
+    let nested_ok = host.contains("gitlab") || segments.len() == 2;
+    if segments.len() < 2 || !nested_ok || segments.iter().any(|segment| segment.is_empty()) {
+        return None;
+    }
+    Some(format!("{host}/{path}.git"))
+}
+
+/// Splits a GitHub pull request URL such as
+/// `https://github.com/org/pkg/pull/123` into the repository clone URL and
+/// the pull request number.
+fn github_pull_request(spec: &str) -> Option<(String, u32)> {
+    let rest = spec
+        .trim()
+        .trim_end_matches('/')
+        .strip_prefix("https://github.com/")?;
+    let segments = rest.split('/').collect::<Vec<_>>();
+    // Allow trailing views such as `/files` or `/commits`.
+    let [owner, repo, "pull", number, ..] = segments.as_slice() else {
+        return None;
+    };
+    let number = number.parse().ok()?;
+    Some((format!("https://github.com/{owner}/{repo}.git"), number))
+}
+
+/// Fetches `pull/<number>/head` into the fresh clone at `repo_path` and
+/// checks it out, including its submodules.
+fn checkout_pull_request(
+    shell: &Shell,
+    repo_path: &Path,
+    number: u32,
+    options: &PrepareOptions,
+    progress: &Progress,
+) -> Result<()> {
+    let task = progress.task(format!("Fetching pull request #{number}"));
+    let _dir_guard = shell.push_dir(repo_path);
+    let refspec = format!("pull/{number}/head:revdeprun-pr-{number}");
+    let branch = format!("revdeprun-pr-{number}");
+    let depth_args = clone_depth_args(options.clone_depth)
+        .into_iter()
+        .filter(|arg| arg != "--shallow-submodules")
+        .collect::<Vec<_>>();
+    let run_git = |command: xshell::Cmd<'_>| -> Result<()> {
+        let output = command.quiet().ignore_status().output()?;
+        if !output.status.success() {
+            bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(())
+    };
+    let result = run_git(cmd!(shell, "git fetch {depth_args...} origin {refspec}"))
+        .and_then(|()| run_git(cmd!(shell, "git checkout --quiet {branch}")))
+        .and_then(|()| {
+            run_git(cmd!(
+                shell,
+                "git submodule update --init --recursive --quiet"
+            ))
+        });
+    match result {
+        Ok(()) => {
+            task.finish_with_message(format!("Checked out pull request #{number}"));
+            Ok(())
+        }
+        Err(err) => {
+            task.fail(format!("Fetching pull request #{number} failed"));
+            Err(err).with_context(|| format!("failed to check out pull request #{number}"))
+        }
+    }
+}
+
+fn clone_repository(
+    shell: &Shell,
+    spec: &str,
+    destination: &Path,
+    options: &PrepareOptions,
+    progress: &Progress,
+) -> Result<()> {
+    let jobs = options.num_workers.max(1).to_string();
+    let attempts = options.clone_retries.saturating_add(1);
+    let depth_args = clone_depth_args(options.clone_depth);
 
-    let clone_task = progress.task(format!("Cloning {spec} into {}", destination.display()));
-    let output = cmd!(shell, "git clone --depth 1 {spec} {destination}")
+    for attempt in 1..=attempts {
+        let label = if attempt == 1 {
+            format!("Cloning {spec} into {}", destination.display())
+        } else {
+            format!("Cloning {spec} (attempt {attempt} of {attempts})")
+        };
+        let clone_task = progress.task(label);
+        let depth_args = &depth_args;
+        let output = cmd!(
+            shell,
+            "git clone {depth_args...} --recurse-submodules --jobs {jobs} {spec} {destination}"
+        )
         .quiet()
         .ignore_status()
         .output();
 
-    match output {
-        Ok(output) if output.status.success() => {
-            clone_task.finish_with_message(format!("Cloned into {}", destination.display()));
-        }
-        Ok(output) => {
-            clone_task.fail(format!("Cloning {spec} failed"));
+        let output = match output {
+            Ok(output) if output.status.success() => {
+                clone_task.finish_with_message(format!("Cloned into {}", destination.display()));
+                return Ok(());
+            }
+            Ok(output) => output,
+            Err(err) => {
+                clone_task.fail(format!("Cloning {spec} failed to start"));
+                return Err(err).with_context(|| format!("failed to clone repository {spec}"));
+            }
+        };
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if is_rate_limited(&stderr) {
+            let wait = retry_after_hint(&stderr).filter(|wait| *wait <= MAX_RATE_LIMIT_WAIT);
+            if let Some(wait) = wait.filter(|_| attempt < attempts) {
+                clone_task.fail(format!(
+                    "Cloning {spec} was rate limited; retrying in {}s (attempt {attempt} of {attempts})",
+                    wait.as_secs()
+                ));
+                remove_partial_clone(destination)?;
+                thread::sleep(wait);
+                continue;
+            }
+            clone_task.fail(format!("Cloning {spec} was rate limited"));
             util::emit_command_output(
                 progress,
                 &format!("git clone {spec}"),
                 &output.stdout,
                 &output.stderr,
             );
-            bail!("failed to clone repository {spec}");
+            bail!(
+                "failed to clone repository {spec}: the host is rate limiting anonymous clones; \
+                 authenticate git (e.g. `gh auth setup-git`, or a credential helper with a \
+                 personal access token) or clone over SSH, then try again"
+            );
         }
-        Err(err) => {
-            clone_task.fail(format!("Cloning {spec} failed to start"));
-            return Err(err).with_context(|| format!("failed to clone repository {spec}"));
+        if attempt < attempts && is_transient_clone_error(&stderr) {
+            clone_task.fail(format!(
+                "Cloning {spec} hit a network error; retrying (attempt {attempt} of {attempts})"
+            ));
+            remove_partial_clone(destination)?;
+            thread::sleep(Duration::from_secs(2u64.pow(attempt.min(5))));
+            continue;
         }
+
+        clone_task.fail(format!("Cloning {spec} failed"));
+        util::emit_command_output(
+            progress,
+            &format!("git clone {spec}"),
+            &output.stdout,
+            &output.stderr,
+        );
+        bail!("failed to clone repository {spec}");
     }
 
-    workspace::canonicalized(&destination)
+    unreachable!("clone loop always returns")
+}
+
+fn remove_partial_clone(destination: &Path) -> Result<()> {
+    if destination.exists() {
+        fs::remove_dir_all(destination).with_context(|| {
+            format!(
+                "failed to remove partial clone at {}",
+                destination.display()
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// `git clone` flags limiting history to `depth`; empty for a full clone.
+fn clone_depth_args(depth: CloneDepth) -> Vec<String> {
+    match depth {
+        CloneDepth::Shallow(commits) => vec![
+            "--depth".to_string(),
+            commits.to_string(),
+            "--shallow-submodules".to_string(),
+        ],
+        CloneDepth::Full => Vec::new(),
+    }
+}
+
+/// Longest `Retry-After` hint honoured before a rate-limited clone gives up.
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(300);
+
+/// Returns `true` when `git clone` stderr points at a transient network or
+/// transport failure rather than an authentication or missing-repository error.
+/// Permanent markers, including HTTP 4xx statuses, win over transient ones.
+fn is_transient_clone_error(stderr: &str) -> bool {
+    const PERMANENT: &[&str] = &[
+        "authentication failed",
+        "permission denied",
+        "could not read username",
+        "repository not found",
+        "does not appear to be a git repository",
+    ];
+    const TRANSIENT: &[&str] = &[
+        "could not resolve host",
+        "rpc failed",
+        "early eof",
+        "connection reset",
+        "connection timed out",
+        "operation timed out",
+        "failed to connect",
+        "the remote end hung up unexpectedly",
+        "unexpected disconnect",
+        "gnutls_handshake",
+        "ssl_read",
+        "http/2 stream",
+    ];
+
+    let stderr = stderr.to_ascii_lowercase();
+    if PERMANENT.iter().any(|marker| stderr.contains(marker)) || has_permanent_http_status(&stderr)
+    {
+        return false;
+    }
+    TRANSIENT.iter().any(|marker| stderr.contains(marker))
+}
+
+/// Returns `true` when lowercased `git clone` stderr reports an HTTP 4xx
+/// status, as `HTTP 404` or `returned error: 404`. 408 and 429 are left out
+/// since retrying them can succeed.
+fn has_permanent_http_status(stderr: &str) -> bool {
+    ["http ", "returned error: "].iter().any(|prefix| {
+        stderr.match_indices(prefix).any(|(index, _)| {
+            let code = &stderr[index + prefix.len()..];
+            let code = code.get(..3).unwrap_or_default();
+            code.starts_with('4')
+                && code.bytes().all(|byte| byte.is_ascii_digit())
+                && !["408", "429"].contains(&code)
+        })
+    })
+}
+
+/// Returns `true` when `git clone` stderr shows the host (typically GitHub)
+/// refusing the request because of its rate limit.
+fn is_rate_limited(stderr: &str) -> bool {
+    const MARKERS: &[&str] = &["rate limit", "returned error: 429", "too many requests"];
+
+    let stderr = stderr.to_ascii_lowercase();
+    MARKERS.iter().any(|marker| stderr.contains(marker))
+}
+
+/// Wait requested by a `Retry-After: N` or "retry after N seconds" hint in
+/// `git clone` stderr.
+fn retry_after_hint(stderr: &str) -> Option<Duration> {
+    let stderr = stderr.to_ascii_lowercase();
+    ["retry-after", "retry after"].iter().find_map(|marker| {
+        let (_, rest) = stderr.split_once(marker)?;
+        let rest = rest.trim_start_matches([':', ' ']);
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .map_or(rest, |end| &rest[..end]);
+        digits.parse().ok().map(Duration::from_secs)
+    })
 }
 
+/// Resolves a local package directory. The canonical path, with symlinks
+/// resolved, is what later steps operate on; the path as given is kept for
+/// display, noting where it resolves to when that differs.
 fn prepare_local_directory(candidate: &Path, progress: &Progress) -> Result<PathBuf> {
     let task = progress.task(format!("Using local repository at {}", candidate.display()));
+    let logical = std::path::absolute(candidate).unwrap_or_else(|_| candidate.to_path_buf());
     match workspace::canonicalized(candidate) {
         Ok(path) => {
-            task.finish_with_message(format!("Using {}", path.display()));
+            task.finish_with_message(describe_local_directory(&logical, &path));
             Ok(path)
         }
         Err(err) => {
@@ -106,19 +509,34 @@ fn prepare_local_directory(candidate: &Path, progress: &Progress) -> Result<Path
     }
 }
 
+fn describe_local_directory(logical: &Path, canonical: &Path) -> String {
+    if logical == canonical {
+        format!("Using {}", logical.display())
+    } else {
+        format!(
+            "Using {} (resolved to {})",
+            logical.display(),
+            canonical.display()
+        )
+    }
+}
+
 fn prepare_tarball(
     shell: &Shell,
     workspace: &Workspace,
     tarball: &Path,
+    num_workers: usize,
     progress: &Progress,
-) -> Result<PathBuf> {
+) -> Result<ExtractionCleanup> {
     let tarball_path = workspace::canonicalized(tarball)
         .with_context(|| format!("failed to resolve tarball path {}", tarball.display()))?;
-
-    let task = progress.task(format!(
-        "Preparing package from tarball {}",
-        tarball_path.display()
-    ));
+    let kind = ArchiveKind::from_path(&tarball_path).ok_or_else(|| {
+        anyhow!(
+            "unsupported package archive {}; expected {}",
+            tarball_path.display(),
+            ArchiveKind::supported_suffixes()
+        )
+    })?;
 
     let extraction_dir = tempdir_in(workspace.temp_dir()).with_context(|| {
         format!(
@@ -128,11 +546,22 @@ fn prepare_tarball(
     })?;
     let extraction_path = extraction_dir.path().to_path_buf();
 
+    let task = progress.task(format!(
+        "Extracting {} into {}",
+        tarball_path.display(),
+        extraction_path.display()
+    ));
+
+    let decompress = decompression_args(shell, kind, num_workers);
     let extraction_output = progress.suspend(|| {
-        cmd!(shell, "tar -xzf {tarball_path} -C {extraction_path}")
-            .quiet()
-            .ignore_status()
-            .output()
+        let decompress = &decompress;
+        cmd!(
+            shell,
+            "tar {decompress...} -xf {tarball_path} -C {extraction_path}"
+        )
+        .quiet()
+        .ignore_status()
+        .output()
     });
 
     let output = match extraction_output {
@@ -148,13 +577,22 @@ fn prepare_tarball(
         util::emit_command_output(
             progress,
             &format!(
-                "tar -xzf {} -C {}",
+                "tar {} -xf {} -C {}",
+                decompress.join(" "),
                 tarball_path.display(),
                 extraction_path.display()
             ),
             &output.stdout,
             &output.stderr,
         );
+        if kind == ArchiveKind::Zstd && lacks_zstd_support(&String::from_utf8_lossy(&output.stderr))
+        {
+            bail!(
+                "failed to extract {}: the system tar cannot decompress zstd archives; \
+                 install zstd (e.g. `sudo apt-get install zstd`) or provide a .tar.gz archive",
+                tarball_path.display()
+            );
+        }
         bail!(
             "failed to extract package tarball {}",
             tarball_path.display()
@@ -169,11 +607,17 @@ fn prepare_tarball(
         }
     };
 
-    let package_name = package_dir
-        .file_name()
-        .and_then(|value| value.to_str())
-        .map(|value| value.to_string())
-        .or_else(|| infer_package_name(&tarball_path));
+    // The Package field is authoritative; the top-level directory of a
+    // GitHub archive, e.g. `my-pkg-main`, need not match it.
+    let package_name = description::read_package_name(&package_dir)
+        .ok()
+        .or_else(|| {
+            package_dir
+                .file_name()
+                .and_then(|value| value.to_str())
+                .map(|value| value.to_string())
+        })
+        .or_else(|| infer_package_name(&tarball_path, kind));
     let package_name = match package_name {
         Some(name) => name,
         None => {
@@ -188,6 +632,10 @@ fn prepare_tarball(
         }
     };
 
+    if let Err(err) = workspace.check_extract_destination(&package_name) {
+        task.fail(format!("Cannot extract {package_name} into the workspace"));
+        return Err(err);
+    }
     let destination = workspace.temp_dir().join(&package_name);
     if destination.exists() {
         task.fail(format!(
@@ -200,6 +648,14 @@ fn prepare_tarball(
         );
     }
 
+    // A directory named exactly after the package, owned by a guard from the
+    // moment it exists so that no error path leaves the sources behind. The
+    // rename replaces the empty directory with the package root.
+    let sources = tempfile::Builder::new()
+        .prefix(&package_name)
+        .rand_bytes(0)
+        .tempdir_in(workspace.temp_dir())
+        .with_context(|| format!("failed to create {}", destination.display()))?;
     fs::rename(&package_dir, &destination).with_context(|| {
         format!(
             "failed to move extracted package into {}",
@@ -218,8 +674,178 @@ fn prepare_tarball(
         }
     };
 
-    task.finish_with_message(format!("Using {}", canonical_dir.display()));
-    Ok(canonical_dir)
+    task.finish_with_message(format!(
+        "Using sources extracted to {}",
+        canonical_dir.display()
+    ));
+    Ok(ExtractionCleanup {
+        repo_path: canonical_dir,
+        sources: Some(sources),
+        progress: progress.clone(),
+    })
+}
+
+/// Validates a `--cran-mirror` URL and returns it without trailing slashes.
+///
+/// `file://` mirrors must contain a `src/contrib/PACKAGES` index.
+pub fn validate_cran_mirror(url: &str) -> Result<String> {
+    let url = url.trim().trim_end_matches('/');
+    if let Some(path) = url.strip_prefix("file://") {
+        let index = Path::new(path).join("src/contrib/PACKAGES");
+        if !index.is_file() {
+            bail!(
+                "local CRAN mirror {url} has no package index at {}",
+                index.display()
+            );
+        }
+    } else if !url.starts_with("https://") && !url.starts_with("http://") {
+        bail!("unsupported CRAN mirror '{url}'; expected an http(s):// or file:// URL");
+    }
+    Ok(url.to_string())
+}
+
+/// Removes package sources extracted from a tarball when dropped, keeping the
+/// `revdep/` results directory if one was created.
+pub struct ExtractionCleanup {
+    repo_path: PathBuf,
+    sources: Option<TempDir>,
+    progress: Progress,
+}
+
+impl ExtractionCleanup {
+    /// Canonical path of the extracted package sources.
+    pub fn path(&self) -> &Path {
+        &self.repo_path
+    }
+
+    /// Leaves the extracted sources in place and returns their path.
+    pub fn keep(mut self) -> PathBuf {
+        self.sources
+            .take()
+            .map_or_else(|| self.repo_path.clone(), TempDir::keep)
+    }
+}
+
+impl Drop for ExtractionCleanup {
+    fn drop(&mut self) {
+        let Some(sources) = self.sources.take() else {
+            return;
+        };
+        let removed = remove_extracted_sources(&self.repo_path).and_then(|kept_results| {
+            if kept_results {
+                let _ = sources.keep();
+            } else {
+                sources
+                    .close()
+                    .with_context(|| format!("failed to remove {}", self.repo_path.display()))?;
+            }
+            Ok(kept_results)
+        });
+        match removed {
+            Ok(true) => self.progress.println(format!(
+                "Removed extracted sources from {}; library and check results kept",
+                self.repo_path.display()
+            )),
+            Ok(false) => self.progress.println(format!(
+                "Removed extracted sources at {}",
+                self.repo_path.display()
+            )),
+            Err(err) => self.progress.println(format!(
+                "Warning: failed to clean up extracted sources: {err:#}"
+            )),
+        }
+    }
+}
+
+/// Deletes everything under `repo_path` except `revdep/` and the check output.
+/// Returns whether anything was kept.
+fn remove_extracted_sources(repo_path: &Path) -> Result<bool> {
+    let library = revlib_dir(repo_path);
+    let mut kept_results = false;
+    let entries = fs::read_dir(repo_path)
+        .with_context(|| format!("failed to read {}", repo_path.display()))?;
+    for entry in entries {
+        let entry = entry.with_context(|| format!("failed to read {}", repo_path.display()))?;
+        let path = entry.path();
+        if path == library || entry.file_name().to_str().is_some_and(is_check_output) {
+            kept_results = true;
+        } else if path.is_dir() {
+            fs::remove_dir_all(&path)
+                .with_context(|| format!("failed to remove {}", path.display()))?;
+        } else {
+            fs::remove_file(&path)
+                .with_context(|| format!("failed to remove {}", path.display()))?;
+        }
+    }
+    Ok(kept_results)
+}
+
+/// Compression formats accepted for local source package archives.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ArchiveKind {
+    Gzip,
+    Zstd,
+    /// Uncompressed `.tar`, as produced by some build pipelines.
+    Plain,
+}
+
+impl ArchiveKind {
+    const ALL: [Self; 3] = [Self::Gzip, Self::Zstd, Self::Plain];
+
+    fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|kind| name.ends_with(kind.suffix()))
+    }
+
+    fn suffix(self) -> &'static str {
+        match self {
+            Self::Gzip => ".tar.gz",
+            Self::Zstd => ".tar.zst",
+            Self::Plain => ".tar",
+        }
+    }
+
+    fn supported_suffixes() -> String {
+        let suffixes = Self::ALL.map(Self::suffix);
+        let (last, rest) = suffixes.split_last().expect("at least one archive kind");
+        format!("{}, or {last}", rest.join(", "))
+    }
+}
+
+/// Returns the `tar` flags used to decompress `kind`, preferring a parallel
+/// `pigz` for gzip archives when it is installed.
+fn decompression_args(shell: &Shell, kind: ArchiveKind, num_workers: usize) -> Vec<String> {
+    match kind {
+        ArchiveKind::Zstd => return vec!["--zstd".to_string()],
+        ArchiveKind::Plain => return Vec::new(),
+        ArchiveKind::Gzip => {}
+    }
+
+    let has_pigz = cmd!(shell, "pigz --version")
+        .quiet()
+        .ignore_status()
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false);
+
+    if has_pigz {
+        vec![pigz_program_arg(num_workers)]
+    } else {
+        vec!["-z".to_string()]
+    }
+}
+
+fn pigz_program_arg(num_workers: usize) -> String {
+    format!("--use-compress-program=pigz -p {}", num_workers.max(1))
+}
+
+fn lacks_zstd_support(stderr: &str) -> bool {
+    let stderr = stderr.to_ascii_lowercase();
+    stderr.contains("unrecognized option '--zstd'")
+        || (stderr.contains("zstd")
+            && (stderr.contains("not found") || stderr.contains("cannot exec")))
 }
 
 fn locate_package_root(extraction_root: &Path, tarball: &Path) -> Result<PathBuf> {
@@ -269,15 +895,12 @@ fn locate_package_root(extraction_root: &Path, tarball: &Path) -> Result<PathBuf
 }
 
 fn is_tarball(path: &Path) -> bool {
-    let Some(name) = path.file_name().and_then(|value| value.to_str()) else {
-        return false;
-    };
-    name.to_ascii_lowercase().ends_with(".tar.gz")
+    ArchiveKind::from_path(path).is_some()
 }
 
-fn infer_package_name(tarball: &Path) -> Option<String> {
+fn infer_package_name(tarball: &Path, kind: ArchiveKind) -> Option<String> {
     let file_name = tarball.file_name()?.to_str()?;
-    let stem = file_name.strip_suffix(".tar.gz")?;
+    let stem = file_name.get(..file_name.len().checked_sub(kind.suffix().len())?)?;
     let package = stem.split_once('_').map(|(head, _)| head).unwrap_or(stem);
     if package.is_empty() {
         None
@@ -286,25 +909,162 @@ fn infer_package_name(tarball: &Path) -> Option<String> {
     }
 }
 
-/// Runs reverse dependency checks for the repository under `repo_path`.
+/// Settings that shape the generated revdep install and check scripts.
+#[derive(Clone, Debug)]
+pub struct RevcheckOptions {
+    /// Number of parallel workers for installation and checking.
+    pub num_workers: usize,
+    /// Tunables for R's `--max-connections`.
+    pub connections: util::ConnectionTuning,
+    /// Where revdep dependencies are installed from. Anything but
+    /// [`CranType::Source`] uses the Posit `__linux__` binary repository, which
+    /// is only built against released R versions.
+    pub cran_type: CranType,
+    /// Extra environment variables for the `Rscript` processes.
+    pub env: Vec<EnvVar>,
+    /// Keep an existing revdep library even when it was built against a
+    /// different version of the target package.
+    pub reuse_library: bool,
+    /// Delete the revdep libraries before installing, whatever they were
+    /// built against.
+    pub clean_library: bool,
+    /// Whether to install the `Suggests` dependencies of the revdeps.
+    /// Skipping them speeds up installation at the cost of checks that need
+    /// them.
+    pub install_suggests: bool,
+    /// Whether to install the `Suggests` dependencies of the target itself.
+    pub target_suggests: bool,
+    /// Extra attempts at installing packages that are still missing after the
+    /// first pass.
+    pub install_retries: u32,
+    /// CRAN-like repository for metadata and source installs.
+    pub cran_mirror: String,
+    /// Exported functions of the target that changed; revdeps importing them
+    /// are checked first.
+    pub touched_exports: Vec<String>,
+    /// Stop the whole check at the first `ERROR` result line.
+    pub fail_fast: bool,
+    /// Directory to save copies of the generated R scripts in.
+    pub dump_scripts: Option<PathBuf>,
+    /// Re-build revdep vignettes during `R CMD check`.
+    pub build_vignettes: bool,
+    /// Install the target's own dependencies into a separate library ahead of
+    /// the shared one.
+    pub isolate_target_deps: bool,
+    /// Bioconductor release to install Bioconductor packages from, instead of
+    /// the one paired with the running R version.
+    pub bioc_version: Option<BiocVersion>,
+    /// Codename of the Posit binary repository to use instead of the detected
+    /// Ubuntu release.
+    pub binary_codename: Option<Codename>,
+    /// Build the target package and compare each revdep against its CRAN
+    /// release with `xfun::rev_check()`. When `false`, revdeps are checked
+    /// against the target already installed.
+    pub check_self: bool,
+    /// Reuse the revdep library an earlier run installed, for
+    /// `--continue-from check`, instead of running the install script.
+    pub skip_install: bool,
+    /// Archive of a prebuilt revdep library to unpack before installing.
+    pub library_cache: Option<PathBuf>,
+    /// Require a CRAN release of the target to compare against, for
+    /// `--only-changed-since-cran`.
+    pub require_cran_release: bool,
+    /// `Additional_repositories` from the target's DESCRIPTION.
+    pub additional_repos: Vec<String>,
+    /// Tool the install script installs packages with.
+    pub installer: Installer,
+}
+
+/// Environment variable holding the results directory in the
+/// `--post-check-script` hook.
+pub const RESULTS_DIR_ENV: &str = "REVDEPRUN_RESULTS_DIR";
+
+/// Runs a user-supplied shell script with `sh`, from the repository under
+/// `repo_path`, e.g. the `--pre-check-script` hook. `hook` names it in
+/// progress output. A `results_dir` is passed as the first argument and in
+/// [`RESULTS_DIR_ENV`]. Its output is shown when it fails.
+pub fn run_hook_script(
+    shell: &Shell,
+    repo_path: &Path,
+    script: &Path,
+    hook: &str,
+    results_dir: Option<&Path>,
+    progress: &Progress,
+) -> Result<()> {
+    let script = fs::canonicalize(script)
+        .with_context(|| format!("failed to find the {hook} {}", script.display()))?;
+    let label = format!("{hook} {}", script.display());
+    let task = progress.task(format!("Running {label}"));
+    let _dir_guard = shell.push_dir(repo_path);
+    let mut command = cmd!(shell, "sh {script} {results_dir...}");
+    if let Some(dir) = results_dir {
+        command = command.env(RESULTS_DIR_ENV, dir);
+    }
+    let output = command.quiet().ignore_status().output();
+
+    match output {
+        Ok(output) if output.status.success() => {
+            task.finish_with_message(format!("{label} succeeded"));
+            Ok(())
+        }
+        Ok(output) => {
+            task.fail(format!("{label} failed"));
+            util::emit_command_output(progress, &label, &output.stdout, &output.stderr);
+            bail!("{label} {}", util::describe_exit(output.status));
+        }
+        Err(err) => {
+            task.fail(format!("{label} failed to start"));
+            Err(err).with_context(|| format!("failed to execute the {hook}"))
+        }
+    }
+}
+
+/// Runs reverse dependency checks of `revdeps` for the repository under
+/// `repo_path`.
 pub fn run_revcheck(
     shell: &Shell,
     workspace: &Workspace,
     repo_path: &Path,
-    num_workers: usize,
+    revdeps: &[String],
+    options: &RevcheckOptions,
     progress: &Progress,
 ) -> Result<()> {
-    let max_connections = util::optimal_max_connections(num_workers);
-    let codename = detect_ubuntu_codename().context("failed to detect Ubuntu release codename")?;
+    let max_connections = util::optimal_max_connections(options.num_workers, options.connections);
+    let codename = match (&options.binary_codename, options.cran_type) {
+        (_, CranType::Source) => None,
+        (Some(Codename(codename)), _) => {
+            progress.println(format!(
+                "Installing revdep dependencies from Posit binaries built for {codename}."
+            ));
+            Some(codename.clone())
+        }
+        (None, _) => {
+            Some(detect_ubuntu_codename().context("failed to detect Ubuntu release codename")?)
+        }
+    };
 
-    let install_contents = build_revdep_install_script(repo_path, num_workers, &codename)?;
-    let run_contents = build_revdep_run_script(repo_path, num_workers)?;
+    let target_deps_contents =
+        build_target_deps_install_script(repo_path, options, codename.as_deref());
+    let install_contents =
+        build_revdep_install_script(repo_path, revdeps, options, codename.as_deref())?;
+    let run_contents = build_revdep_run_script(repo_path, revdeps, options)?;
+    let dump_dir = options.dump_scripts.as_deref();
+    if options.check_self {
+        util::dump_script(dump_dir, "target-deps.R", &target_deps_contents, progress)?;
+    }
+    util::dump_script(dump_dir, "install.R", &install_contents, progress)?;
+    util::dump_script(dump_dir, "run.R", &run_contents, progress)?;
 
+    let mut target_deps_script = NamedTempFile::new_in(workspace.temp_dir())
+        .context("failed to create temporary R script file")?;
     let mut install_script = NamedTempFile::new_in(workspace.temp_dir())
         .context("failed to create temporary R script file")?;
     let mut run_script = NamedTempFile::new_in(workspace.temp_dir())
         .context("failed to create temporary R script file")?;
 
+    target_deps_script
+        .write_all(target_deps_contents.as_bytes())
+        .context("failed to write target dependencies install script")?;
     install_script
         .write_all(install_contents.as_bytes())
         .context("failed to write revdep dependencies install script")?;
@@ -312,71 +1072,965 @@ pub fn run_revcheck(
         .write_all(run_contents.as_bytes())
         .context("failed to write reverse dependency check script")?;
 
+    let target_deps_path = target_deps_script.path().to_owned();
     let install_path = install_script.path().to_owned();
     let run_path = run_script.path().to_owned();
 
     fs::create_dir_all(repo_path.join("revdep"))
         .with_context(|| format!("failed to create {}", repo_path.join("revdep").display()))?;
 
-    let _dir_guard = shell.push_dir(repo_path);
+    if options.skip_install {
+        progress.println(format!(
+            "Reusing the revdep library installed by an earlier run in {}",
+            library_dir(repo_path).display()
+        ));
+    } else if options.clean_library {
+        clean_libraries(repo_path, progress)?;
+    } else {
+        refresh_stale_library(repo_path, options.reuse_library, progress)?;
+    }
+    if let Some(cache) = options
+        .library_cache
+        .as_deref()
+        .filter(|_| !options.skip_install)
+    {
+        extract_library_cache(shell, cache, repo_path, options.num_workers, progress)?;
+    }
 
-    let install_task = progress.task("Installing revdep dependencies");
-    let install_result = progress.suspend(|| {
-        let install_max_connections = max_connections.to_string();
-        cmd!(
-            shell,
-            "Rscript --vanilla --max-connections={install_max_connections} {install_path}"
-        )
-        .quiet()
-        .run()
-    });
+    let _dir_guard = shell.push_dir(repo_path);
 
-    match install_result {
-        Ok(_) => {
-            install_task.finish_with_message("Reverse dependencies installed".to_string());
-        }
-        Err(err) => {
-            install_task.fail("Failed to install revdep dependencies".to_string());
-            return Err(err).context("failed to install revdep dependencies");
+    // Build the target against its own dependencies before spending time on
+    // the revdeps, so a broken target fails fast.
+    if options.check_self {
+        if !options.skip_install {
+            install_target_deps(shell, &target_deps_path, max_connections, options, progress)?;
         }
+        verify_target_builds(shell, workspace, repo_path, options, progress)?;
     }
 
-    progress.println("Launching xfun::rev_check()...");
-    progress.suspend(|| {
-        let run_max_connections = max_connections.to_string();
-        cmd!(
+    if !options.skip_install {
+        install_revdeps(
             shell,
-            "Rscript --vanilla --max-connections={run_max_connections} {run_path}"
-        )
-        .quiet()
-        .run()
-        .context("xfun::rev_check() reported an error")
-    })?;
+            repo_path,
+            &install_path,
+            max_connections,
+            options,
+            progress,
+        )?;
+    }
+
+    progress.println("Launching xfun::rev_check()...");
+    let _heartbeat = progress.heartbeat("checking revdeps with xfun::rev_check()");
+    let run_max_connections = max_connections.to_string();
+    let run_cmd = cmd!(
+        shell,
+        "Rscript --vanilla --max-connections={run_max_connections} {run_path}"
+    )
+    .envs(options.env.iter().map(|var| (&var.key, &var.value)));
+    let color = check_output_color_enabled();
+    // Keep stdout parseable when it carries the event stream.
+    let to_stderr = progress.events_on_stdout();
+    let fail_fast = options.fail_fast;
+    let quiet = progress.is_quiet();
+    let end = progress
+        .suspend(|| {
+            util::stream_lines(
+                run_cmd,
+                fail_fast,
+                |line| {
+                    let (line, is_error) = prepare_check_line(&line, color);
+                    if !quiet {
+                        if to_stderr {
+                            eprintln!("{line}");
+                        } else {
+                            println!("{line}");
+                        }
+                    }
+                    if fail_fast && is_error {
+                        ControlFlow::Break(())
+                    } else {
+                        ControlFlow::Continue(())
+                    }
+                },
+                |line| {
+                    if !quiet {
+                        eprintln!("{}", util::redact_url_credentials(&line));
+                    }
+                },
+            )
+        })
+        .context("xfun::rev_check() reported an error")?;
+
+    if end == util::StreamEnd::Stopped {
+        let mut failed = failed_check_packages(&results_dir(repo_path));
+        if failed.is_empty() {
+            failed.push("unknown package".to_string());
+        }
+        progress.println(format!(
+            "Stopped xfun::rev_check() after the first ERROR (--fail-fast): {}",
+            failed.join(", ")
+        ));
+        return Err(error::RevdeprunError::RevdepProblems(failed).into());
+    }
 
     Ok(())
 }
 
-/// Returns the default library directory created for xfun::rev_check().
-pub fn revlib_dir(repo_path: &Path) -> PathBuf {
-    repo_path.join("revdep")
+/// Runs the script installing the target's own dependencies.
+fn install_target_deps(
+    shell: &Shell,
+    script_path: &Path,
+    max_connections: usize,
+    options: &RevcheckOptions,
+    progress: &Progress,
+) -> Result<()> {
+    let task = progress.task("Installing target package dependencies");
+    let max_connections = max_connections.to_string();
+    let install_cmd = cmd!(
+        shell,
+        "Rscript --vanilla --max-connections={max_connections} {script_path}"
+    )
+    .envs(options.env.iter().map(|var| (&var.key, &var.value)));
+
+    match run_with_install_progress(install_cmd, &task, progress) {
+        Ok(()) => {
+            task.finish_with_message("Target package dependencies installed".to_string());
+            Ok(())
+        }
+        Err(err) => {
+            task.fail("Failed to install target package dependencies".to_string());
+            Err(err).context("failed to install target package dependencies")
+        }
+    }
 }
 
-fn build_revdep_install_script(
+/// Builds and installs the target package on its own, against its
+/// dependencies and before any revdep is installed. A broken target then
+/// fails early with its build log and
+/// [`error::RevdeprunError::TargetBuildFailed`] instead of surfacing as a
+/// failed check invocation. The built tarball is unpacked into
+/// [`target_source_dir`] for `xfun::rev_check()` to check against.
+fn verify_target_builds(
+    shell: &Shell,
+    workspace: &Workspace,
+    repo_path: &Path,
+    options: &RevcheckOptions,
+    progress: &Progress,
+) -> Result<()> {
+    let package = description::read_package_name(repo_path)?;
+    let build_dir = tempdir_in(workspace.temp_dir())
+        .context("failed to create a directory to build the target package in")?;
+    let build_path = build_dir.path();
+    let install_path = build_path.join("library");
+    fs::create_dir_all(&install_path)
+        .with_context(|| format!("failed to create {}", install_path.display()))?;
+
+    let mut libraries = vec![library_dir(repo_path)];
+    if options.isolate_target_deps {
+        libraries.insert(0, target_library_dir(repo_path));
+    }
+    let r_libs = env::join_paths(&libraries).context("failed to join library paths")?;
+
+    let task = progress.task(format!("Building target package {package}"));
+    let _dir_guard = shell.push_dir(build_path);
+    let build = cmd!(
+        shell,
+        "R CMD build --no-build-vignettes --no-manual {repo_path}"
+    );
+    let output = build
+        .env("R_LIBS", &r_libs)
+        .envs(options.env.iter().map(|var| (&var.key, &var.value)))
+        .quiet()
+        .ignore_status()
+        .output()
+        .context("failed to launch R CMD build")?;
+    if !output.status.success() {
+        task.fail(format!("Target package {package} failed to build"));
+        util::emit_command_output(progress, "R CMD build", &output.stdout, &output.stderr);
+        return Err(error::RevdeprunError::TargetBuildFailed(package).into());
+    }
+
+    let tarball = fs::read_dir(build_path)
+        .with_context(|| format!("failed to read {}", build_path.display()))?
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| path.to_string_lossy().ends_with(".tar.gz"))
+        .with_context(|| format!("R CMD build left no tarball for {package}"))?;
+    let output = cmd!(shell, "R CMD INSTALL --library={install_path} {tarball}")
+        .env("R_LIBS", &r_libs)
+        .envs(options.env.iter().map(|var| (&var.key, &var.value)))
+        .quiet()
+        .ignore_status()
+        .output()
+        .context("failed to launch R CMD INSTALL")?;
+    if !output.status.success() {
+        task.fail(format!("Target package {package} failed to install"));
+        util::emit_command_output(progress, "R CMD INSTALL", &output.stdout, &output.stderr);
+        return Err(error::RevdeprunError::TargetBuildFailed(package).into());
+    }
+
+    let source_dir = target_source_dir(repo_path);
+    if source_dir.exists() {
+        fs::remove_dir_all(&source_dir)
+            .with_context(|| format!("failed to remove {}", source_dir.display()))?;
+    }
+    fs::create_dir_all(&source_dir)
+        .with_context(|| format!("failed to create {}", source_dir.display()))?;
+    cmd!(shell, "tar -xzf {tarball} -C {source_dir}")
+        .quiet()
+        .run()
+        .with_context(|| format!("failed to unpack {}", tarball.display()))?;
+
+    task.finish_with_message(format!("Target package {package} builds and installs"));
+    Ok(())
+}
+
+/// Unpacks a `--library-cache` archive of revdep library contents into
+/// `revdep/library`, keeping packages already installed there, so the
+/// install script only fills the gaps.
+fn extract_library_cache(
+    shell: &Shell,
+    cache: &Path,
     repo_path: &Path,
     num_workers: usize,
-    codename: &str,
-) -> Result<String> {
-    let prelude = script_prelude(repo_path, num_workers);
-    let codename_literal = util::r_string_literal(&codename.to_lowercase());
+    progress: &Progress,
+) -> Result<()> {
+    let cache = workspace::canonicalized(cache)
+        .with_context(|| format!("failed to resolve library cache {}", cache.display()))?;
+    let kind = ArchiveKind::from_path(&cache).ok_or_else(|| {
+        anyhow!(
+            "unsupported library cache {}; expected {}",
+            cache.display(),
+            ArchiveKind::supported_suffixes()
+        )
+    })?;
+    let library = library_dir(repo_path);
+    fs::create_dir_all(&library)
+        .with_context(|| format!("failed to create {}", library.display()))?;
+
+    let task = progress.task(format!("Unpacking library cache {}", cache.display()));
+    let decompress = decompression_args(shell, kind, num_workers);
+    let output = cmd!(
+        shell,
+        "tar {decompress...} --skip-old-files -xf {cache} -C {library}"
+    )
+    .quiet()
+    .ignore_status()
+    .output();
+    match output {
+        Ok(output) if output.status.success() => {
+            task.finish_with_message(format!("Unpacked library cache into {}", library.display()));
+            Ok(())
+        }
+        Ok(output) => {
+            task.fail(format!("Failed to unpack {}", cache.display()));
+            util::emit_command_output(
+                progress,
+                &format!("tar -xf {}", cache.display()),
+                &output.stdout,
+                &output.stderr,
+            );
+            bail!("failed to unpack library cache {}", cache.display());
+        }
+        Err(err) => {
+            task.fail(format!("Failed to unpack {}", cache.display()));
+            Err(err).context("failed to launch tar for the library cache")
+        }
+    }
+}
 
-    let script = format!(
-        r#"{prelude}
+/// Runs the install script at `install_path` from the repository, reporting
+/// batch progress and packages that could not be installed.
+fn install_revdeps(
+    shell: &Shell,
+    repo_path: &Path,
+    install_path: &Path,
+    max_connections: usize,
+    options: &RevcheckOptions,
+    progress: &Progress,
+) -> Result<()> {
+    let install_task = progress.task("Installing revdep dependencies");
+    let _heartbeat = progress.heartbeat("installing revdep dependencies");
+    let install_max_connections = max_connections.to_string();
+    let install_cmd = cmd!(
+        shell,
+        "Rscript --vanilla --max-connections={install_max_connections} {install_path}"
+    )
+    .envs(options.env.iter().map(|var| (&var.key, &var.value)));
+
+    match run_with_install_progress(install_cmd, &install_task, progress) {
+        Ok(()) => {
+            install_task.finish_with_message("Reverse dependencies installed".to_string());
+            write_library_stamp(repo_path)?;
+            let failed = read_install_failures(repo_path);
+            if !failed.is_empty() {
+                progress.println(format!(
+                    "Warning: {} packages could not be installed; revdeps among them will not be checked: {}",
+                    failed.len(),
+                    failed.join(", ")
+                ));
+            }
+            Ok(())
+        }
+        Err(err) => {
+            install_task.fail("Failed to install revdep dependencies".to_string());
+            Err(err).context("failed to install revdep dependencies")
+        }
+    }
+}
+
+/// Whether `line` is an `R CMD check` result line ending in `ERROR`.
+fn is_check_error_line(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.starts_with("* ") && trimmed.ends_with("ERROR")
+}
+
+/// Revdeps under `check_dir` whose `00check.log` already records an `ERROR`.
+fn failed_check_packages(check_dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(check_dir) else {
+        return Vec::new();
+    };
+    let mut failed = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let package = name.strip_suffix(".Rcheck")?.to_string();
+            let log = fs::read_to_string(entry.path().join("00check.log")).ok()?;
+            log.lines().any(is_check_error_line).then_some(package)
+        })
+        .collect::<Vec<_>>();
+    failed.sort();
+    failed
+}
+
+/// Runs the install script, turning its progress markers into a determinate
+/// bar on `task` and forwarding every other output line.
+fn run_with_install_progress(
+    install_cmd: xshell::Cmd<'_>,
+    task: &Task,
+    progress: &Progress,
+) -> Result<()> {
+    util::stream_lines(
+        install_cmd,
+        false,
+        |line| {
+            match parse_install_progress(&line) {
+                Some((done, total)) => task.set_progress(done, total),
+                None => progress.println(line),
+            }
+            ControlFlow::Continue(())
+        },
+        |line| progress.println(line),
+    )
+    .map(|_| ())
+}
+
+/// Whether check output written to stdout should be colorized, following the
+/// `NO_COLOR` convention and only when stdout is a terminal.
+fn check_output_color_enabled() -> bool {
+    env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()) && io::stdout().is_terminal()
+}
+
+/// Prepares a line of check output for display: credentials are redacted
+/// and, with `color`, result lines highlighted. Also returns whether it
+/// reports an ERROR, judged before highlighting adds escape codes.
+fn prepare_check_line(line: &str, color: bool) -> (String, bool) {
+    let line = util::redact_url_credentials(line);
+    let is_error = is_check_error_line(&line);
+    let shown = if color {
+        highlight_check_line(&line).into_owned()
+    } else {
+        line.into_owned()
+    };
+    (shown, is_error)
+}
+
+/// Wraps `* checking ... ERROR` and `... WARNING` result lines in ANSI colors.
+fn highlight_check_line(line: &str) -> Cow<'_, str> {
+    let trimmed = line.trim_end();
+    if !trimmed.trim_start().starts_with("* ") {
+        return Cow::Borrowed(line);
+    }
+    let color = if trimmed.ends_with("ERROR") {
+        "\x1b[1;31m"
+    } else if trimmed.ends_with("WARNING") {
+        "\x1b[1;33m"
+    } else {
+        return Cow::Borrowed(line);
+    };
+    Cow::Owned(format!("{color}{line}\x1b[0m"))
+}
+
+/// Parses a `revdeprun-install-progress done/total` line.
+fn parse_install_progress(line: &str) -> Option<(u64, u64)> {
+    let counts = line.trim().strip_prefix(INSTALL_PROGRESS_MARKER)?.trim();
+    let (done, total) = counts.split_once('/')?;
+    let done = done.parse().ok()?;
+    let total = total.parse().ok()?;
+    (total > 0 && done <= total).then_some((done, total))
+}
+
+/// Settings that control how the reverse dependency list is computed.
+#[derive(Clone, Debug)]
+pub struct RevdepListOptions {
+    /// Parallelism for helper package installation.
+    pub num_workers: usize,
+    /// Tunables for R's `--max-connections`.
+    pub connections: util::ConnectionTuning,
+    /// Method used to compute the reverse dependencies.
+    pub source: RevdepsSource,
+    /// Only keep revdeps published on CRAN on or after this `YYYY-MM-DD` date.
+    pub since: Option<String>,
+    /// CRAN-like repository used to look up reverse dependencies.
+    pub cran_mirror: String,
+    /// Only list packages that depend on the target through Depends,
+    /// Imports, or LinkingTo, as CRAN's incoming checks do.
+    pub strong_only: bool,
+}
+
+/// Computes the sorted CRAN reverse dependencies of the package at
+/// `repo_path`.
+///
+/// This is the single source of truth for the revdep set: the sysreqs,
+/// install, and check phases all operate on the returned list.
+pub fn resolve_revdeps(
+    shell: &Shell,
+    workspace: &Workspace,
+    repo_path: &Path,
+    options: &RevdepListOptions,
+    progress: &Progress,
+) -> Result<Vec<String>> {
+    let RevdepListOptions {
+        num_workers,
+        connections,
+        source,
+        ref since,
+        ..
+    } = *options;
+    let package_name = description::read_package_name(repo_path)?;
+    let output_file = NamedTempFile::new_in(workspace.temp_dir())
+        .context("failed to create temporary revdep list file")?;
+    let output_path = output_file.path().to_owned();
+
+    let script_contents = build_revdep_list_script(&package_name, options, &output_path);
+    let mut script = NamedTempFile::new_in(workspace.temp_dir())
+        .context("failed to create temporary revdep list R script")?;
+    script
+        .write_all(script_contents.as_bytes())
+        .context("failed to write revdep list R script")?;
+    let script_path = script.path().to_owned();
+
+    let task = progress.task(format!(
+        "Listing reverse dependencies of {package_name} ({})",
+        source.label()
+    ));
+    let max_connections = util::optimal_max_connections(num_workers, connections).to_string();
+    let output = cmd!(
+        shell,
+        "Rscript --vanilla --max-connections={max_connections} {script_path}"
+    )
+    .quiet()
+    .ignore_status()
+    .output();
+
+    match output {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => {
+            task.fail(format!(
+                "Failed to list reverse dependencies of {package_name}"
+            ));
+            util::emit_command_output(
+                progress,
+                "reverse dependency listing",
+                &output.stdout,
+                &output.stderr,
+            );
+            bail!(
+                "revdep listing script {}",
+                util::describe_exit(output.status)
+            );
+        }
+        Err(err) => {
+            task.fail(format!(
+                "Launching revdep listing for {package_name} failed"
+            ));
+            return Err(err).context("failed to list reverse dependencies");
+        }
+    }
+
+    let contents = fs::read_to_string(&output_path)
+        .with_context(|| format!("failed to read revdep list {}", output_path.display()))?;
+    let revdeps = parse_revdep_list(&contents);
+    task.finish_with_message(match since {
+        Some(date) => format!(
+            "Found {} reverse dependencies of {package_name} published since {date}",
+            revdeps.len()
+        ),
+        None => format!(
+            "Found {} reverse dependencies of {package_name}",
+            revdeps.len()
+        ),
+    });
+
+    Ok(revdeps)
+}
+
+fn parse_revdep_list(contents: &str) -> Vec<String> {
+    let mut revdeps = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    revdeps.sort();
+    revdeps.dedup();
+    revdeps
+}
+
+fn build_revdep_list_script(
+    package_name: &str,
+    options: &RevdepListOptions,
+    output_path: &Path,
+) -> String {
+    let package_literal = util::r_string_literal(package_name);
+    let output_literal = util::r_string_literal(&output_path.to_string_lossy());
+    let kinds_vector = util::r_character_vector(dependency_kinds(!options.strong_only));
+    let workers = options.num_workers.max(1);
+    let source_repo_setup = auth::source_repo_setup(&options.cran_mirror);
+    let since_filter = match &options.since {
+        Some(date) => format!(
+            r#"
+# Keep only revdeps published on CRAN on or after the cutoff.
+since <- as.Date({})
+cran_db <- tools::CRAN_package_db()
+published <- as.Date(cran_db$Published[match(revdeps, cran_db$Package)])
+revdeps <- revdeps[!is.na(published) & published >= since]
+"#,
+            util::r_string_literal(date)
+        ),
+        None => String::new(),
+    };
+    let computation = match options.source {
+        RevdepsSource::Tools => format!(
+            r#"db <- available.packages(repos = source_repo, type = "source")
+{REQUIRE_PACKAGE_DB}
+revdeps <- tools::package_dependencies(
+  packages = pkg_name,
+  db = db,
+  which = dependency_kinds,
+  reverse = TRUE
+)[[pkg_name]]"#
+        ),
+        RevdepsSource::Revdepcheck => r#"if (!requireNamespace("revdepcheck", quietly = TRUE)) {
+  ensure_installed("pak")
+  pak::pkg_install("r-lib/revdepcheck", lib = user_lib, ask = FALSE)
+}
+revdeps <- revdepcheck::cran_revdeps(pkg_name, dependencies = dependency_kinds, bioc = FALSE)"#
+            .to_string(),
+    };
+
+    format!(
+        r#"
+{source_repo_setup}
 
-binary_repo <- sprintf("https://packagemanager.posit.co/cran/__linux__/%s/latest", {codename_literal})
-source_repo <- "https://packagemanager.posit.co/cran/latest"
+options(
+  repos = c(CRAN = source_repo),
+  Ncpus = {workers}
+)
+
+user_lib <- Sys.getenv("R_LIBS_USER")
+if (!nzchar(user_lib)) {{
+  stop('R_LIBS_USER is empty; cannot install packages into user library')
+}}
+dir.create(user_lib, recursive = TRUE, showWarnings = FALSE)
+.libPaths(c(user_lib, .libPaths()))
+
+ensure_installed <- function(pkg) {{
+  if (!requireNamespace(pkg, quietly = TRUE)) {{
+    install.packages(pkg, lib = user_lib, quiet = TRUE, Ncpus = {workers})
+  }}
+}}
+
+pkg_name <- {package_literal}
+dependency_kinds <- {kinds_vector}
+
+{computation}
+
+if (is.null(revdeps)) {{
+  revdeps <- character()
+}}
+revdeps <- sort(unique(stats::na.omit(revdeps)))
+base_pkgs <- unique(c(.BaseNamespaceEnv$basePackage, rownames(installed.packages(priority = "base"))))
+revdeps <- setdiff(revdeps, c(base_pkgs, pkg_name))
+{since_filter}
+writeLines(revdeps, {output_literal})
+"#
+    )
+}
+
+/// Keeps the revdeps whose names match `--pkg-filter`, if given. The
+/// filtered list is what every later phase installs and checks, so an
+/// empty result is an error rather than a run with nothing to do.
+pub fn filter_revdeps(
+    revdeps: Vec<String>,
+    filter: Option<&PkgFilter>,
+    progress: &Progress,
+) -> Result<Vec<String>> {
+    let Some(filter) = filter.filter(|_| !revdeps.is_empty()) else {
+        return Ok(revdeps);
+    };
+    let matching = revdeps
+        .into_iter()
+        .filter(|name| filter.is_match(name))
+        .collect::<Vec<_>>();
+    if matching.is_empty() {
+        bail!(
+            "no reverse dependencies match --pkg-filter {}",
+            filter.as_str()
+        );
+    }
+    progress.println(format!(
+        "Reverse dependencies matching --pkg-filter: {}",
+        matching.len()
+    ));
+    Ok(matching)
+}
+
+/// Enforces `--max-revdeps` on the revdeps left after [`filter_revdeps`]:
+/// fails when more than `max` would be checked, or keeps the first `max` of
+/// them when `truncate` is set.
+pub fn cap_revdeps(
+    revdeps: Vec<String>,
+    max: usize,
+    truncate: bool,
+    progress: &Progress,
+) -> Result<Vec<String>> {
+    if revdeps.len() <= max {
+        return Ok(revdeps);
+    }
+    if !truncate {
+        bail!(
+            "{} reverse dependencies exceed --max-revdeps {max}; raise the limit, narrow the run with --pkg-filter, or pass --truncate to check only the first {max}",
+            revdeps.len()
+        );
+    }
+    progress.println(format!(
+        "Warning: checking only the first {max} of {} reverse dependencies (--max-revdeps with --truncate).",
+        revdeps.len()
+    ));
+    Ok(revdeps.into_iter().take(max).collect())
+}
+
+/// Reads `--only` or `--exclude` entries into package names, expanding
+/// `@FILE` entries to the names listed in the file.
+pub fn expand_package_specs(specs: &[PackageSpec]) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    for spec in specs {
+        match spec {
+            PackageSpec::Name(name) => names.push(name.clone()),
+            PackageSpec::File(path) => {
+                let contents = fs::read_to_string(path)
+                    .with_context(|| format!("failed to read package list {}", path.display()))?;
+                names.extend(
+                    contents
+                        .lines()
+                        .map(|line| line.split('#').next().unwrap_or_default().trim())
+                        .filter(|line| !line.is_empty())
+                        .map(str::to_string),
+                );
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// Narrows `revdeps` to `only` (when not empty) and drops `exclude`.
+pub fn select_revdeps(
+    revdeps: Vec<String>,
+    only: &[String],
+    exclude: &[String],
+    progress: &Progress,
+) -> Result<Vec<String>> {
+    if !only.is_empty() {
+        let unknown = only
+            .iter()
+            .filter(|name| !revdeps.contains(name))
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        if !unknown.is_empty() {
+            progress.println(format!(
+                "Warning: ignoring --only packages that are not reverse dependencies: {}",
+                unknown.join(", ")
+            ));
+        }
+    }
+    let total = revdeps.len();
+    let selected = revdeps
+        .into_iter()
+        .filter(|name| only.is_empty() || only.contains(name))
+        .filter(|name| !exclude.contains(name))
+        .collect::<Vec<_>>();
+    if selected.is_empty() && total > 0 {
+        bail!("no reverse dependencies are left to check after applying --only and --exclude");
+    }
+    if selected.len() < total {
+        progress.println(format!(
+            "Checking {} of {total} reverse dependencies after --only and --exclude.",
+            selected.len()
+        ));
+    }
+    Ok(selected)
+}
+
+/// Returns the default library directory created for xfun::rev_check().
+pub fn revlib_dir(repo_path: &Path) -> PathBuf {
+    repo_path.join("revdep")
+}
+
+/// Returns the directory `xfun::rev_check()` writes the `*.Rcheck` folders
+/// and its `00check_diffs` summary to. The run script calls it from the
+/// repository root, so this is the repository itself rather than `revdep/`.
+pub fn results_dir(repo_path: &Path) -> PathBuf {
+    repo_path.to_path_buf()
+}
+
+/// Returns the summary `xfun::rev_check()` wrote for the last run, or
+/// [`results_dir`] when there is none, e.g. because no check log differed.
+pub fn results_summary(repo_path: &Path) -> PathBuf {
+    let dir = results_dir(repo_path);
+    CHECK_SUMMARY_FILES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+        .unwrap_or(dir)
+}
+
+/// Whether a top-level repository entry is check output of `xfun::rev_check()`.
+fn is_check_output(name: &str) -> bool {
+    name.ends_with(".Rcheck") || name.ends_with(".Rcheck2") || name.starts_with("00check_diffs")
+}
+
+/// Returns the library that revdeps and their dependencies are installed into.
+pub fn library_dir(repo_path: &Path) -> PathBuf {
+    revlib_dir(repo_path).join("library")
+}
+
+/// Disk space taken by a finished run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DiskUsage {
+    /// Bytes under `revdep/library`.
+    pub library: u64,
+    /// Bytes under the whole repository, including the library and check output.
+    pub repository: u64,
+}
+
+impl DiskUsage {
+    /// Walks the repository at `repo_path` to measure its size.
+    pub fn measure(repo_path: &Path) -> Self {
+        Self {
+            library: util::dir_size(&library_dir(repo_path)),
+            repository: util::dir_size(repo_path),
+        }
+    }
+}
+
+impl fmt::Display for DiskUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} in total, {} in revdep/library",
+            util::format_bytes(self.repository),
+            util::format_bytes(self.library)
+        )
+    }
+}
+
+/// Returns the directory holding the target package source built by
+/// [`verify_target_builds`], unpacked, which `xfun::rev_check()` checks
+/// against.
+fn target_source_dir(repo_path: &Path) -> PathBuf {
+    revlib_dir(repo_path).join("target-source")
+}
+
+/// Returns the library `--isolate-target-deps` installs the target's
+/// dependencies into.
+fn target_library_dir(repo_path: &Path) -> PathBuf {
+    revlib_dir(repo_path).join("target-library")
+}
+
+fn library_stamp_path(repo_path: &Path) -> PathBuf {
+    revlib_dir(repo_path).join(LIBRARY_STAMP_FILE)
+}
+
+/// Identifies the target package build a revdep library was installed against.
+fn target_stamp(repo_path: &Path) -> Result<String> {
+    let description = description::Description::read(repo_path)?;
+    let package = description
+        .package()
+        .with_context(|| format!("no Package field in {}", repo_path.display()))?;
+    Ok(match description.version() {
+        Some(version) => format!("{package} {version}"),
+        None => package.to_string(),
+    })
+}
+
+/// Removes a revdep library left behind by a run against a different version
+/// of the target package, unless `reuse` is set.
+///
+/// Libraries without a stamp predate this bookkeeping and are kept.
+fn refresh_stale_library(repo_path: &Path, reuse: bool, progress: &Progress) -> Result<()> {
+    let library = library_dir(repo_path);
+    let Ok(recorded) = fs::read_to_string(library_stamp_path(repo_path)) else {
+        return Ok(());
+    };
+    let recorded = recorded.trim();
+    let current = target_stamp(repo_path)?;
+    if recorded == current || !library.exists() {
+        return Ok(());
+    }
+
+    if reuse {
+        progress.println(format!(
+            "Warning: {} was built against {recorded} but the target is now {current}; reusing it as requested by --reuse-library.",
+            library.display()
+        ));
+        return Ok(());
+    }
+
+    progress.println(format!(
+        "{} was built against {recorded} but the target is now {current}; rebuilding it. Pass --reuse-library to keep it.",
+        library.display()
+    ));
+    fs::remove_dir_all(&library)
+        .with_context(|| format!("failed to remove stale library {}", library.display()))
+}
+
+/// Removes the revdep library and the `--isolate-target-deps` library for
+/// `--clean-library`, leaving check results in place.
+fn clean_libraries(repo_path: &Path, progress: &Progress) -> Result<()> {
+    for library in [library_dir(repo_path), target_library_dir(repo_path)] {
+        if !library.exists() {
+            continue;
+        }
+        progress.println(format!(
+            "Removing {} as requested by --clean-library.",
+            library.display()
+        ));
+        fs::remove_dir_all(&library)
+            .with_context(|| format!("failed to remove library {}", library.display()))?;
+    }
+    Ok(())
+}
+
+/// Reads the packages the install script gave up on, if any.
+fn read_install_failures(repo_path: &Path) -> Vec<String> {
+    fs::read_to_string(revlib_dir(repo_path).join(INSTALL_FAILURES_FILE))
+        .map(|contents| parse_revdep_list(&contents))
+        .unwrap_or_default()
+}
+
+/// Records the revdeps this run checks, so `--continue-from` can pick them
+/// up without listing them again.
+pub fn save_revdeps(repo_path: &Path, revdeps: &[String]) -> Result<()> {
+    let dir = revlib_dir(repo_path);
+    fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    let path = dir.join(REVDEPS_FILE);
+    let mut contents = revdeps.join("\n");
+    contents.push('\n');
+    fs::write(&path, contents).with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Loads what an earlier run left for `--continue-from`: its revdep list
+/// and, when `need_library` is set, a revdep library fully installed against
+/// the current target.
+pub fn load_resume_state(repo_path: &Path, need_library: bool) -> Result<Vec<String>> {
+    let path = revlib_dir(repo_path).join(REVDEPS_FILE);
+    let contents = fs::read_to_string(&path).with_context(|| {
+        format!(
+            "found no revdep list from an earlier run at {}; run without --continue-from first",
+            path.display()
+        )
+    })?;
+    let revdeps = parse_revdep_list(&contents);
+
+    if need_library {
+        let recorded = fs::read_to_string(library_stamp_path(repo_path)).unwrap_or_default();
+        let current = target_stamp(repo_path)?;
+        if !library_dir(repo_path).is_dir() || recorded.trim() != current {
+            bail!(
+                "{} was not fully installed against {current} by an earlier run; continue from install instead",
+                library_dir(repo_path).display()
+            );
+        }
+    }
+    Ok(revdeps)
+}
+
+fn write_library_stamp(repo_path: &Path) -> Result<()> {
+    let path = library_stamp_path(repo_path);
+    fs::write(&path, format!("{}\n", target_stamp(repo_path)?))
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Setup shared by the install scripts: repositories, `install_packages()`,
+/// `package_name`, the package database `db`, `available_packages`, and
+/// `base_pkgs`. Returns the code and the R expression of the repositories to
+/// install from.
+fn install_script_setup(
+    repo_path: &Path,
+    options: &RevcheckOptions,
+    codename: Option<&str>,
+) -> (String, String) {
+    let prelude = script_prelude(repo_path, options.num_workers);
+    let target_library = target_library_snippet(options.isolate_target_deps);
+    let source_repo_setup = auth::source_repo_setup(&options.cran_mirror);
+    // With binaries available, `both` lets install.packages() pick the newer
+    // of the Posit binary and the CRAN source.
+    let bioc_setup = bioc_setup(options.bioc_version.as_ref());
+    let additional_setup = additional_repos_setup(&options.additional_repos);
+    let install_repos = match (codename, options.cran_type, &options.bioc_version) {
+        (Some(_), CranType::Both, None) => "c(posit = binary_repo, CRAN = source_repo)",
+        (Some(_), CranType::Both, Some(_)) => {
+            "c(posit = binary_repo, CRAN = source_repo, bioc_repos)"
+        }
+        (_, _, None) => "binary_repo",
+        (_, _, Some(_)) => "c(binary_repo, bioc_repos)",
+    };
+    let install_repos = with_additional_repos(install_repos, &options.additional_repos);
+    let db_repos = match options.bioc_version {
+        Some(_) => "c(CRAN = source_repo, bioc_repos)",
+        None => "source_repo",
+    };
+    let db_repos = with_additional_repos(db_repos, &options.additional_repos);
+    let repo_setup = match codename {
+        Some(codename) => {
+            let codename_literal = util::r_string_literal(&codename.to_lowercase());
+            format!(
+                r#"binary_repo <- sprintf("https://packagemanager.posit.co/cran/__linux__/%s/latest", {codename_literal})
+{source_repo_setup}"#
+            )
+        }
+        None => format!(
+            r#"{source_repo_setup}
+# Posit binaries only exist for released R on the default mirror; install
+# everything from source.
+binary_repo <- source_repo
+options(pkgType = "source")"#
+        ),
+    };
+
+    let installer_setup = match options.installer {
+        Installer::Base => INSTALL_WITH_BASE,
+        Installer::Pak => INSTALL_WITH_PAK,
+    };
 
+    let setup = format!(
+        r#"{prelude}
+{target_library}
+{repo_setup}
+{bioc_setup}{additional_setup}
 options(
-  repos = c(posit = binary_repo),
+  repos = {install_repos},
   BioC_mirror = "https://packagemanager.posit.co/bioconductor",
   Ncpus = install_workers
 )
@@ -395,28 +2049,106 @@ ensure_installed <- function(pkg, repo = source_repo) {{
 }}
 
 ensure_installed("xfun")
+{installer_setup}
+package_name <- read.dcf("DESCRIPTION", fields = "Package")[1, 1]
+if (!nzchar(package_name)) {{
+  stop("Failed to read package name from DESCRIPTION")
+}}
+
+db <- available.packages(repos = {db_repos}, type = "source")
+{REQUIRE_PACKAGE_DB}
+available_packages <- rownames(db)
+base_pkgs <- unique(c(.BaseNamespaceEnv$basePackage, rownames(installed.packages(priority = "base"))))
+"#
+    );
+
+    (setup, install_repos)
+}
 
-package_name <- read.dcf("DESCRIPTION", fields = "Package")[1, 1]
-if (!nzchar(package_name)) {{
-  stop("Failed to read package name from DESCRIPTION")
-}}
+/// Script installing the target's own hard dependency closure, into the
+/// `--isolate-target-deps` library when set and the revdep library otherwise,
+/// so the target can be built before any revdep is installed.
+fn build_target_deps_install_script(
+    repo_path: &Path,
+    options: &RevcheckOptions,
+    codename: Option<&str>,
+) -> String {
+    let (setup, install_repos) = install_script_setup(repo_path, options, codename);
+    let destination = if options.isolate_target_deps {
+        "target_library"
+    } else {
+        "library_dir"
+    };
+    let target_deps_literal = util::r_string_literal(TARGET_DEPS_FILE);
 
-db <- available.packages(repos = source_repo, type = "source")
-revdeps <- tools::package_dependencies(
-  packages = package_name,
+    format!(
+        r#"{setup}
+# Install the target's dependency closure first, so it is built against fresh
+# versions before any revdep is installed.
+target_fields <- read.dcf("DESCRIPTION", fields = c("Depends", "Imports", "LinkingTo"))
+target_direct <- unlist(lapply(target_fields[!is.na(target_fields)], function(field) {{
+  trimws(sub("\\(.*", "", strsplit(field, ",")[[1]]))
+}}))
+target_deps <- tools::package_dependencies(
+  intersect(target_direct, available_packages),
   db = db,
-  which = c("Depends", "Imports", "LinkingTo", "Suggests"),
-  reverse = TRUE
-)[[package_name]]
-
-revdeps <- sort(unique(stats::na.omit(revdeps)))
+  which = c("Depends", "Imports", "LinkingTo"),
+  recursive = TRUE
+)
+target_deps <- unique(c(target_direct, unlist(target_deps, use.names = FALSE)))
+target_deps <- sort(setdiff(intersect(target_deps, available_packages), c(base_pkgs, "R")))
+if (length(target_deps) > 0) {{
+  message("Installing ", length(target_deps), " target dependencies into ", {destination})
+  install_packages(target_deps, {install_repos}, {destination})
+}}
+writeLines(
+  intersect(target_deps, rownames(installed.packages(lib.loc = {destination}))),
+  file.path(revdep_dir, {target_deps_literal})
+)
+"#
+    )
+}
 
-base_pkgs <- unique(c(.BaseNamespaceEnv$basePackage, rownames(installed.packages(priority = "base"))))
-revdeps <- setdiff(revdeps, base_pkgs)
+fn build_revdep_install_script(
+    repo_path: &Path,
+    revdeps: &[String],
+    options: &RevcheckOptions,
+    codename: Option<&str>,
+) -> Result<String> {
+    let (setup, install_repos) = install_script_setup(repo_path, options, codename);
+    let revdeps_literal = util::r_character_vector(revdeps);
+    let dependency_kinds_literal =
+        util::r_character_vector(dependency_kinds(options.install_suggests));
+    let target_dependency_kinds_literal =
+        util::r_character_vector(dependency_kinds(options.target_suggests));
+    let marker_literal = util::r_string_literal(INSTALL_PROGRESS_MARKER);
+    let failures_literal = util::r_string_literal(INSTALL_FAILURES_FILE);
+    let install_retries = options.install_retries;
+    let (install_self, require_self) = if options.check_self {
+        ("c(package_name, revdeps)", "")
+    } else {
+        ("revdeps", REQUIRE_INSTALLED_TARGET)
+    };
+    // The target's dependencies were installed into the revdep library just
+    // before the target was built, unless they went to their own library.
+    let target_deps = if options.check_self && !options.isolate_target_deps {
+        format!(
+            r#"target_deps_file <- file.path(revdep_dir, {})
+target_deps <- if (file.exists(target_deps_file)) readLines(target_deps_file) else character()
+"#,
+            util::r_string_literal(TARGET_DEPS_FILE)
+        )
+    } else {
+        "target_deps <- character()\n".to_string()
+    };
 
-install_targets <- sort(unique(c(package_name, revdeps)))
+    let script = format!(
+        r#"{setup}
+revdeps <- {revdeps_literal}
+{require_self}
+{target_deps}
+install_targets <- sort(unique({install_self}))
 
-available_packages <- rownames(db)
 missing_packages <- setdiff(install_targets, available_packages)
 if (length(missing_packages) > 0) {{
   message(
@@ -426,50 +2158,222 @@ if (length(missing_packages) > 0) {{
 }}
 install_targets <- setdiff(install_targets, missing_packages)
 
-dependency_kinds <- c("Depends", "Imports", "LinkingTo", "Suggests")
-dependency_map <- tools::package_dependencies(
-  packages = install_targets,
-  db = db,
-  which = dependency_kinds,
-  recursive = FALSE
+dependency_kinds <- {dependency_kinds_literal}
+target_dependency_kinds <- {target_dependency_kinds_literal}
+dependency_map <- c(
+  tools::package_dependencies(
+    packages = setdiff(install_targets, package_name),
+    db = db,
+    which = dependency_kinds,
+    recursive = FALSE
+  ),
+  tools::package_dependencies(
+    packages = intersect(install_targets, package_name),
+    db = db,
+    which = target_dependency_kinds,
+    recursive = FALSE
+  )
 )
 extra_deps <- unique(unlist(dependency_map, use.names = FALSE))
 extra_deps <- extra_deps[!is.na(extra_deps) & nzchar(extra_deps)]
 extra_deps <- intersect(extra_deps, available_packages)
-extra_deps <- setdiff(extra_deps, c(base_pkgs, install_targets))
+extra_deps <- setdiff(extra_deps, c(base_pkgs, install_targets, package_name))
 install_targets <- sort(unique(c(install_targets, extra_deps)))
 
 if (length(revdeps) == 0) {{
   message("No CRAN reverse dependencies detected; installing package binary only.")
 }}
 
-if (length(install_targets) > 0) {{
-  install.packages(
-    install_targets,
-    repos = binary_repo,
-    lib = library_dir,
-    quiet = TRUE,
-    Ncpus = install_workers
-  )
-}} else {{
+if (length(install_targets) == 0) {{
   stop("No installation targets determined for install.packages().")
 }}
+
+# Install in batches so revdeprun can report progress between them.
+report_progress <- function(done) {{
+  cat(sprintf("%s %d/%d\n", {marker_literal}, done, length(install_targets)))
+  flush(stdout())
+}}
+batch_size <- max(1L, as.integer(install_workers) * 4L)
+batches <- split(install_targets, ceiling(seq_along(install_targets) / batch_size))
+installed_count <- 0L
+report_progress(installed_count)
+# Packages installed by this run, so earlier batches' dependencies are not
+# installed twice while stale copies already in the library still are.
+installed_this_run <- target_deps
+for (batch in batches) {{
+  pending <- setdiff(batch, installed_this_run)
+  if (length(pending) > 0) {{
+    before <- rownames(installed.packages(lib.loc = library_dir))
+    install_packages(pending, {install_repos}, library_dir)
+    after <- rownames(installed.packages(lib.loc = library_dir))
+    installed_this_run <- union(installed_this_run, c(pending, setdiff(after, before)))
+  }}
+  installed_count <- installed_count + length(batch)
+  report_progress(installed_count)
+}}
+
+# Retry only what is still missing so transient download failures are cheap.
+missing_targets <- function() {{
+  setdiff(install_targets, rownames(installed.packages(lib.loc = library_dir)))
+}}
+max_attempts <- 1L + {install_retries}L
+attempt <- 1L
+missing <- missing_targets()
+while (length(missing) > 0 && attempt < max_attempts) {{
+  attempt <- attempt + 1L
+  message(sprintf(
+    "Retrying installation of %d missing packages (attempt %d of %d)",
+    length(missing), attempt, max_attempts
+  ))
+  install_packages(missing, {install_repos}, library_dir)
+  missing <- missing_targets()
+}}
+if (length(missing) > 0) {{
+  message(
+    "Packages still missing after ", max_attempts, " attempts: ",
+    paste(missing, collapse = ", ")
+  )
+}}
+writeLines(missing, file.path(revdep_dir, {failures_literal}))
 "#
     );
 
     Ok(script)
 }
 
-fn build_revdep_run_script(repo_path: &Path, num_workers: usize) -> Result<String> {
-    let prelude = script_prelude(repo_path, num_workers);
+fn dependency_kinds(suggests: bool) -> &'static [&'static str] {
+    if suggests {
+        &["Depends", "Imports", "LinkingTo", "Suggests"]
+    } else {
+        &["Depends", "Imports", "LinkingTo"]
+    }
+}
+
+fn build_revdep_run_script(
+    repo_path: &Path,
+    revdeps: &[String],
+    options: &RevcheckOptions,
+) -> Result<String> {
+    let prelude = script_prelude(repo_path, options.num_workers);
+    let target_library = target_library_snippet(options.isolate_target_deps);
+    let revdeps_literal = util::r_character_vector(revdeps);
+    let failures_literal = util::r_string_literal(INSTALL_FAILURES_FILE);
+    let source_repo_setup = auth::source_repo_setup(&options.cran_mirror);
+    let status_file_literal = util::r_string_literal(summary::STATUS_FILE);
+    let bioc_setup = bioc_setup(options.bioc_version.as_ref());
+    let additional_setup = additional_repos_setup(&options.additional_repos);
+    let repos = match options.bioc_version {
+        Some(_) => "c(CRAN = source_repo, bioc_repos)",
+        None => "c(CRAN = source_repo)",
+    };
+    let repos = with_additional_repos(repos, &options.additional_repos);
+    let check_args = if options.build_vignettes || !options.check_self {
+        ""
+    } else {
+        r#"
+# xfun::rev_check() runs R CMD check through xfun::Rcmd() and has no option
+# for extra check arguments; wrap Rcmd() to skip re-building vignettes there,
+# which needs Quarto or LaTeX and dominates many checks. Stop rather than
+# check with vignettes when this xfun no longer has the expected Rcmd().
+rcmd <- get0("Rcmd", envir = asNamespace("xfun"), inherits = FALSE)
+if (!is.function(rcmd) || !identical(names(formals(rcmd))[1], "args")) {
+  stop(
+    "--no-vignettes relies on xfun::Rcmd(args, ...), which xfun ",
+    packageVersion("xfun"), " does not provide; rerun without --no-vignettes"
+  )
+}
+utils::assignInNamespace("Rcmd", function(args, ...) {
+  if (identical(args[1], "check")) {
+    args <- append(args, "--no-build-vignettes", after = 1)
+  }
+  rcmd(args, ...)
+}, "xfun")
+"#
+    };
+    let run_checks = if options.check_self {
+        r#"# Check against the target as built by revdeprun before the revdeps were
+# installed, rather than building it again.
+target_source <- normalizePath(file.path(revdep_dir, "target-source", package_name))
+results <- xfun::rev_check(package_name, src = target_source, recheck = revdeps)"#
+            .to_string()
+    } else {
+        let check_args = if options.build_vignettes {
+            "character()"
+        } else {
+            r#""--no-build-vignettes""#
+        };
+        format!(
+            r#"{REQUIRE_INSTALLED_TARGET}
+# --no-self: check the revdep sources with plain R CMD check against the
+# installed target, without building it or comparing to its CRAN release.
+message("Checking against installed ", package_name, " ", utils::packageVersion(package_name))
+Sys.setenv(R_LIBS = paste(.libPaths(), collapse = .Platform$path.sep))
+if (length(revdeps) > 0) {{
+  # Download into a fresh directory so only these revdeps are checked, then
+  # move their check folders to the results directory the summary reads.
+  sources_dir <- file.path(revdep_dir, "no-self-sources")
+  unlink(sources_dir, recursive = TRUE)
+  dir.create(sources_dir, recursive = TRUE)
+  utils::download.packages(revdeps, destdir = sources_dir, repos = getOption("repos"), type = "source")
+  tools::check_packages_in_dir(sources_dir, check_args = {check_args}, Ncpus = install_workers)
+  for (check_dir in list.files(sources_dir, pattern = "[.]Rcheck$")) {{
+    unlink(check_dir, recursive = TRUE)
+    file.rename(file.path(sources_dir, check_dir), check_dir)
+  }}
+}}
+check_status <- function(pkg) {{
+  log <- file.path(paste0(pkg, ".Rcheck"), "00check.log")
+  if (!file.exists(log)) return(2L)
+  status <- grep("^Status: ", readLines(log, warn = FALSE), value = TRUE)
+  if (length(status) == 0 || grepl("ERROR|WARNING", status[length(status)])) 1L else 0L
+}}
+results <- vapply(revdeps, check_status, integer(1))"#
+        )
+    };
+    let require_cran_release = if options.require_cran_release {
+        REQUIRE_CRAN_RELEASE
+    } else {
+        ""
+    };
+    let prioritize = if options.touched_exports.is_empty() {
+        String::new()
+    } else {
+        format!(
+            r#"
+# Check revdeps that import the changed exports first, then those importing
+# the whole namespace, then the rest.
+touched_exports <- {}
+import_score <- function(pkg) {{
+  ns_info <- tryCatch(
+    readRDS(file.path(library_dir, pkg, "Meta", "nsInfo.rds")),
+    error = function(e) NULL
+  )
+  score <- 0L
+  for (entry in ns_info$imports) {{
+    if (!identical(entry[[1]], package_name)) next
+    if (is.character(entry)) score <- max(score, 1L)
+    if (is.list(entry) && any(entry[[2]] %in% touched_exports)) score <- 2L
+  }}
+  score
+}}
+scores <- vapply(revdeps, import_score, integer(1))
+revdeps <- revdeps[order(-scores, revdeps)]
+message(
+  "Checking first the revdeps that import changed exports: ",
+  paste(revdeps[scores == 2L], collapse = ", ")
+)
+"#,
+            util::r_character_vector(&options.touched_exports)
+        )
+    };
 
     let script = format!(
         r#"{prelude}
-
-source_repo <- "https://packagemanager.posit.co/cran/latest"
-
+{target_library}
+{source_repo_setup}
+{bioc_setup}{additional_setup}
 options(
-  repos = c(CRAN = source_repo),
+  repos = {repos},
   BioC_mirror = "https://packagemanager.posit.co/bioconductor",
   Ncpus = install_workers,
   mc.cores = install_workers
@@ -488,136 +2392,941 @@ ensure_installed <- function(pkg) {{
   }}
 }}
 
-ensure_installed("xfun")
-ensure_installed("markdown")
-ensure_installed("rmarkdown")
+ensure_installed("xfun")
+ensure_installed("markdown")
+ensure_installed("rmarkdown")
+
+options(xfun.rev_check.summary = TRUE)
+{check_args}
+package_name <- read.dcf("DESCRIPTION", fields = "Package")[1, 1]
+if (!nzchar(package_name)) {{
+  stop("Failed to read package name from DESCRIPTION")
+}}
+{require_cran_release}
+revdeps <- {revdeps_literal}
+install_failures_file <- file.path(revdep_dir, {failures_literal})
+if (file.exists(install_failures_file)) {{
+  uninstalled <- intersect(revdeps, readLines(install_failures_file))
+  if (length(uninstalled) > 0) {{
+    message(
+      "Skipping revdeps that could not be installed: ",
+      paste(uninstalled, collapse = ", ")
+    )
+    revdeps <- setdiff(revdeps, uninstalled)
+  }}
+}}
+{prioritize}
+{run_checks}
+
+status_file <- file.path(revdep_dir, {status_file_literal})
+writeLines(
+  c("package\tstatus", sprintf("%s\t%d", names(results), as.integer(results))),
+  status_file
+)
+invisible(results)
+"#
+    );
+
+    Ok(script)
+}
+
+/// R code defining `install_packages()` for `--installer base`.
+const INSTALL_WITH_BASE: &str = r#"
+install_packages <- function(pkgs, repos, lib) {
+  install.packages(pkgs, repos = repos, lib = lib, quiet = TRUE, Ncpus = install_workers)
+}
+"#;
+
+/// R code defining `install_packages()` for `--installer pak`. pak installs a
+/// batch all or nothing, so a failed batch is retried one package at a time
+/// to keep what can be installed.
+const INSTALL_WITH_PAK: &str = r#"
+ensure_installed("pak")
+Sys.setenv(PKG_SYSREQS = "true")
+pak_install <- function(pkgs, lib) {
+  pak::pkg_install(pkgs, lib = lib, upgrade = FALSE, ask = FALSE)
+}
+install_packages <- function(pkgs, repos, lib) {
+  # pak reads repositories only from the option, so set it for this call.
+  old_options <- options(repos = repos)
+  on.exit(options(old_options), add = TRUE)
+  tryCatch(pak_install(pkgs, lib), error = function(e) {
+    message("pak could not install the batch (", conditionMessage(e), "); installing one at a time")
+    for (pkg in pkgs) {
+      tryCatch(pak_install(pkg, lib), error = function(e) {
+        message("pak failed to install ", pkg, ": ", conditionMessage(e))
+      })
+    }
+  })
+}
+"#;
+
+/// R code that stops when `available.packages()` listed nothing, which means
+/// the mirror is unreachable or misconfigured rather than that there is
+/// nothing to install.
+const REQUIRE_PACKAGE_DB: &str = r#"if (nrow(db) == 0) {
+  stop("package database empty - is the CRAN mirror reachable? Check --cran-mirror and the network.")
+}"#;
+
+/// R code that stops a `--no-self` run early when the target package is not
+/// installed for the revdeps to be checked against.
+const REQUIRE_INSTALLED_TARGET: &str = r#"
+if (!requireNamespace(package_name, quietly = TRUE)) {
+  stop("--no-self needs ", package_name, " to be installed already")
+}
+"#;
+
+/// R code that stops an `--only-changed-since-cran` run when the target has
+/// no CRAN release for `xfun::rev_check()` to recheck failing revdeps
+/// against, since every failure would then count as new.
+const REQUIRE_CRAN_RELEASE: &str = r#"
+if (!package_name %in% rownames(available.packages(repos = source_repo))) {
+  stop("--only-changed-since-cran needs a CRAN release of ", package_name, " to compare against")
+}
+"#;
+
+/// R code that puts the `--isolate-target-deps` library ahead of the shared
+/// revdep library.
+fn target_library_snippet(isolate: bool) -> &'static str {
+    if !isolate {
+        return "";
+    }
+    r#"
+target_library <- file.path(revdep_dir, "target-library")
+dir.create(target_library, recursive = TRUE, showWarnings = FALSE)
+.libPaths(c(target_library, .libPaths()))
+"#
+}
+
+/// R code pinning Bioconductor to `--bioc-version`: `R_BIOC_VERSION` is what
+/// BiocManager, remotes, and pak consult, and `bioc_repos` names the release's
+/// repositories on the Posit mirror for `install.packages()`.
+pub(crate) fn bioc_setup(version: Option<&BiocVersion>) -> String {
+    let Some(BiocVersion(version)) = version else {
+        return String::new();
+    };
+    let version_literal = util::r_string_literal(version);
+    format!(
+        r#"bioc_version <- {version_literal}
+Sys.setenv(R_BIOC_VERSION = bioc_version)
+bioc_repos <- setNames(
+  sprintf(
+    "https://packagemanager.posit.co/bioconductor/packages/%s/%s",
+    bioc_version,
+    c("bioc", "data/annotation", "data/experiment")
+  ),
+  c("BioCsoft", "BioCann", "BioCexp")
+)
+"#
+    )
+}
+
+/// Reads the `Additional_repositories` field of the target's DESCRIPTION,
+/// which lists CRAN-like repositories (often drat repositories) that some of
+/// its dependencies are only published in.
+pub fn additional_repositories(repo_path: &Path) -> Vec<String> {
+    let Ok(description) = description::Description::read(repo_path) else {
+        return Vec::new();
+    };
+    description
+        .get("Additional_repositories")
+        .map(|field| {
+            field
+                .split([',', '\n'])
+                .map(str::trim)
+                .filter(|repo| !repo.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// R code naming the `Additional_repositories` as `additional_repos`.
+pub(crate) fn additional_repos_setup(repos: &[String]) -> String {
+    if repos.is_empty() {
+        return String::new();
+    }
+    format!(
+        "# Additional_repositories from the target's DESCRIPTION.\nadditional_repos <- {}\n",
+        util::r_character_vector(repos)
+    )
+}
+
+/// Appends `additional_repos` to the R repository vector `repos` when the
+/// target declares any.
+pub(crate) fn with_additional_repos(repos: &str, additional: &[String]) -> String {
+    if additional.is_empty() {
+        return repos.to_string();
+    }
+    match repos
+        .strip_prefix("c(")
+        .and_then(|inner| inner.strip_suffix(')'))
+    {
+        Some(inner) => format!("c({inner}, additional_repos)"),
+        None => format!("c({repos}, additional_repos)"),
+    }
+}
+
+fn script_prelude(repo_path: &Path, num_workers: usize) -> String {
+    let path_literal = util::r_string_literal(&repo_path.to_string_lossy());
+    let workers = num_workers.max(1);
+
+    format!(
+        r#"
+setwd({path_literal})
+
+revdep_dir <- file.path("revdep")
+dir.create(revdep_dir, recursive = TRUE, showWarnings = FALSE)
+
+library_dir <- file.path(revdep_dir, "library")
+dir.create(library_dir, recursive = TRUE, showWarnings = FALSE)
+
+Sys.setenv(R_LIBS_USER = library_dir)
+.libPaths(c(library_dir, .libPaths()))
+
+# Exactly --num-workers: revdeprun already clamped it to the available CPUs
+# unless --oversubscribe was given.
+install_workers <- {workers}L
+options(Ncpus = install_workers)
+"#
+    )
+}
+
+pub(crate) fn detect_ubuntu_codename() -> Result<String> {
+    if let Ok(value) = env::var("REVDEPRUN_UBUNTU_CODENAME") {
+        let trimmed = value.trim();
+        if !trimmed.is_empty() {
+            return Ok(trimmed.to_lowercase());
+        }
+    }
+
+    let contents =
+        fs::read_to_string("/etc/os-release").context("failed to read /etc/os-release")?;
+
+    if let Some(codename) = ubuntu_codename_from_os_release(&contents) {
+        return Ok(codename);
+    }
+
+    bail!("VERSION_CODENAME not found in /etc/os-release")
+}
+
+/// Picks the Ubuntu codename from `/etc/os-release`. Derivatives such as
+/// Linux Mint or Pop!_OS (`ID_LIKE` containing `ubuntu`) have their own
+/// `VERSION_CODENAME`, so their `UBUNTU_CODENAME` base is preferred.
+fn ubuntu_codename_from_os_release(contents: &str) -> Option<String> {
+    let mut fields = HashMap::new();
+
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || !line.contains('=') {
+            continue;
+        }
+        let (key, value) = line.split_once('=')?;
+        let value = value.trim().trim_matches('"').trim_matches('\'');
+        if value.is_empty() {
+            continue;
+        }
+        fields.insert(key.trim(), value.to_lowercase());
+    }
+
+    let is_derivative = fields.get("ID").is_some_and(|id| id != "ubuntu")
+        && fields
+            .get("ID_LIKE")
+            .is_some_and(|like| like.split_whitespace().any(|id| id == "ubuntu"));
+    let preferred = if is_derivative {
+        ["UBUNTU_CODENAME", "VERSION_CODENAME"]
+    } else {
+        ["VERSION_CODENAME", "UBUNTU_CODENAME"]
+    };
+    preferred.iter().find_map(|key| fields.remove(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace;
+    use std::fs;
+    use tempfile::tempdir;
+    use xshell::Shell;
+
+    fn test_options() -> RevcheckOptions {
+        RevcheckOptions {
+            num_workers: 8,
+            connections: util::ConnectionTuning::default(),
+            cran_type: CranType::Binary,
+            env: Vec::new(),
+            reuse_library: false,
+            clean_library: false,
+            install_suggests: true,
+            target_suggests: true,
+            install_retries: 2,
+            cran_mirror: DEFAULT_CRAN_MIRROR.to_string(),
+            touched_exports: Vec::new(),
+            fail_fast: false,
+            dump_scripts: None,
+            build_vignettes: true,
+            isolate_target_deps: false,
+            bioc_version: None,
+            binary_codename: None,
+            check_self: true,
+            skip_install: false,
+            library_cache: None,
+            require_cran_release: false,
+            additional_repos: Vec::new(),
+            installer: Installer::Base,
+        }
+    }
+
+    #[test]
+    fn build_install_script_uses_binary_repo() {
+        let path = Path::new("/tmp/example");
+        let revdeps = vec!["pkgA".to_string(), "pkgB".to_string()];
+        let script = build_revdep_install_script(path, &revdeps, &test_options(), Some("noble"))
+            .expect("script must build");
+
+        assert!(script.contains("https://packagemanager.posit.co/cran/__linux__/%s/latest"));
+        assert!(script.contains(
+            "sprintf(\"https://packagemanager.posit.co/cran/__linux__/%s/latest\", 'noble')"
+        ));
+        assert!(script.contains("install.packages("));
+        assert!(script.contains("install_targets <- sort(unique(c(package_name, revdeps)))"));
+        assert!(script.contains("dependency_map <- c(\n  tools::package_dependencies("));
+        assert!(script.contains("recursive = FALSE"));
+        assert!(script.contains("repos = binary_repo"));
+        assert!(script.contains("Skipping packages not available from repository"));
+        assert!(script.contains("if (nrow(db) == 0) {\n  stop(\"package database empty"));
+        assert!(script.contains("setwd('/tmp/example')"));
+        assert!(script.contains("revdeps <- c('pkgA', 'pkgB')"));
+        assert!(
+            script.contains("dependency_kinds <- c('Depends', 'Imports', 'LinkingTo', 'Suggests')")
+        );
+    }
+
+    #[test]
+    fn build_install_script_reports_batch_progress() {
+        let path = Path::new("/tmp/example");
+        let script =
+            build_revdep_install_script(path, &["pkgA".to_string()], &test_options(), None)
+                .expect("script must build");
+
+        assert!(script.contains("'revdeprun-install-progress', done, length(install_targets)"));
+        assert!(script.contains("for (batch in batches)"));
+        assert!(script.contains("pending <- setdiff(batch, installed_this_run)"));
+    }
+
+    #[test]
+    fn build_install_script_retries_missing_packages() {
+        let path = Path::new("/tmp/example");
+        let options = RevcheckOptions {
+            install_retries: 4,
+            ..test_options()
+        };
+        let script = build_revdep_install_script(path, &["pkgA".to_string()], &options, None)
+            .expect("script must build");
+
+        assert!(script.contains("max_attempts <- 1L + 4L"));
+        assert!(script.contains(
+            "setdiff(install_targets, rownames(installed.packages(lib.loc = library_dir)))"
+        ));
+        assert!(script.contains("'revdeprun-install-failures.txt'"));
+
+        let run = build_revdep_run_script(path, &["pkgA".to_string()], &options)
+            .expect("script must build");
+        assert!(run.contains("revdeps <- setdiff(revdeps, uninstalled)"));
+    }
+
+    #[test]
+    fn highlights_check_problem_lines() {
+        assert_eq!(
+            highlight_check_line("* checking tests ... ERROR"),
+            "\x1b[1;31m* checking tests ... ERROR\x1b[0m"
+        );
+        assert_eq!(
+            highlight_check_line("* checking Rd files ... WARNING"),
+            "\x1b[1;33m* checking Rd files ... WARNING\x1b[0m"
+        );
+        assert_eq!(
+            highlight_check_line("* checking examples ... OK"),
+            "* checking examples ... OK"
+        );
+        assert_eq!(
+            highlight_check_line("Running tests: ERROR"),
+            "Running tests: ERROR"
+        );
+
+        for color in [false, true] {
+            let (shown, is_error) = prepare_check_line("* checking tests ... ERROR", color);
+            assert!(is_error, "color = {color}");
+            assert_eq!(shown.starts_with("\x1b["), color);
+            assert!(!prepare_check_line("* checking tests ... OK", color).1);
+        }
+    }
+
+    #[test]
+    fn parses_install_progress_markers() {
+        assert_eq!(
+            parse_install_progress("revdeprun-install-progress 47/214"),
+            Some((47, 214))
+        );
+        assert_eq!(
+            parse_install_progress("revdeprun-install-progress 0/3\r"),
+            Some((0, 3))
+        );
+        assert_eq!(
+            parse_install_progress("revdeprun-install-progress 5/3"),
+            None
+        );
+        assert_eq!(
+            parse_install_progress("revdeprun-install-progress n/a"),
+            None
+        );
+        assert_eq!(parse_install_progress("trying URL 'https://...'"), None);
+    }
+
+    #[test]
+    fn build_install_script_can_mix_binary_and_source_repos() {
+        let path = Path::new("/tmp/example");
+        let options = RevcheckOptions {
+            cran_type: CranType::Both,
+            ..test_options()
+        };
+        let script =
+            build_revdep_install_script(path, &["pkgA".to_string()], &options, Some("noble"))
+                .expect("script must build");
+        assert!(script.contains("repos = c(posit = binary_repo, CRAN = source_repo)"));
+        assert!(!script.contains("repos = binary_repo"));
+    }
+
+    #[test]
+    fn scripts_use_custom_cran_mirror() {
+        let path = Path::new("/tmp/example");
+        let options = RevcheckOptions {
+            cran_mirror: "file:///srv/cran".to_string(),
+            cran_type: CranType::Source,
+            ..test_options()
+        };
+        let install = build_revdep_install_script(path, &["pkgA".to_string()], &options, None)
+            .expect("script must build");
+        assert!(install.contains("source_repo <- 'file:///srv/cran'"));
+        assert!(install.contains("binary_repo <- source_repo"));
+        assert!(!install.contains("packagemanager.posit.co/cran"));
+
+        let run = build_revdep_run_script(path, &["pkgA".to_string()], &options)
+            .expect("script must build");
+        assert!(run.contains("source_repo <- 'file:///srv/cran'"));
+
+        let list_options = RevdepListOptions {
+            cran_mirror: "file:///srv/cran".to_string(),
+            ..list_options(RevdepsSource::Tools)
+        };
+        let list = build_revdep_list_script("ggsci", &list_options, Path::new("/tmp/out"));
+        assert!(list.contains("source_repo <- 'file:///srv/cran'"));
+    }
+
+    #[test]
+    fn validates_cran_mirrors() {
+        assert_eq!(
+            validate_cran_mirror("https://cloud.r-project.org/").expect("https mirror"),
+            "https://cloud.r-project.org"
+        );
+        assert!(validate_cran_mirror("ftp://example.org/cran").is_err());
+
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let url = format!("file://{}", tmp.path().display());
+        assert!(validate_cran_mirror(&url).is_err());
+        fs::create_dir_all(tmp.path().join("src/contrib")).expect("contrib");
+        fs::write(tmp.path().join("src/contrib/PACKAGES"), "").expect("index");
+        assert_eq!(validate_cran_mirror(&url).expect("file mirror"), url);
+    }
 
-options(xfun.rev_check.summary = TRUE)
+    #[test]
+    fn build_run_script_prioritises_touched_importers() {
+        let path = Path::new("/tmp/example");
+        let options = RevcheckOptions {
+            touched_exports: vec!["pal_npg".to_string()],
+            ..test_options()
+        };
+        let script = build_revdep_run_script(path, &["pkgA".to_string()], &options)
+            .expect("script must build");
+        assert!(script.contains("touched_exports <- c('pal_npg')"));
+        assert!(script.contains("revdeps <- revdeps[order(-scores, revdeps)]"));
+
+        let plain = build_revdep_run_script(path, &["pkgA".to_string()], &test_options())
+            .expect("script must build");
+        assert!(!plain.contains("touched_exports"));
+    }
 
-package_name <- read.dcf("DESCRIPTION", fields = "Package")[1, 1]
-if (!nzchar(package_name)) {{
-  stop("Failed to read package name from DESCRIPTION")
-}}
+    #[test]
+    fn filters_revdeps_by_pattern() {
+        let progress = Progress::new();
+        let revdeps = ["biocA", "pkgB", "biocC"].map(str::to_string).to_vec();
+        let filter = "^bioc".parse::<PkgFilter>().expect("pattern");
+
+        let kept = filter_revdeps(revdeps.clone(), Some(&filter), &progress).expect("matches");
+        assert_eq!(kept, vec!["biocA", "biocC"]);
+        assert_eq!(
+            filter_revdeps(revdeps.clone(), None, &progress).expect("no filter"),
+            revdeps
+        );
+        assert!(
+            filter_revdeps(Vec::new(), Some(&filter), &progress)
+                .expect("nothing to filter")
+                .is_empty()
+        );
 
-results <- xfun::rev_check(package_name, src = ".")
-invisible(results)
-"#
-    );
+        let filter = "^gg".parse::<PkgFilter>().expect("pattern");
+        let err = filter_revdeps(revdeps, Some(&filter), &progress).expect_err("no match");
+        assert!(
+            err.to_string()
+                .contains("no reverse dependencies match --pkg-filter ^gg")
+        );
+    }
 
-    Ok(script)
-}
+    #[test]
+    fn caps_revdeps_after_filtering() {
+        let progress = Progress::new();
+        let revdeps = ["biocA", "biocB", "pkgC", "pkgD"]
+            .map(str::to_string)
+            .to_vec();
+
+        let err = cap_revdeps(revdeps.clone(), 3, false, &progress).expect_err("too many");
+        assert!(
+            err.to_string()
+                .contains("4 reverse dependencies exceed --max-revdeps 3")
+        );
 
-fn script_prelude(repo_path: &Path, num_workers: usize) -> String {
-    let path_literal = util::r_string_literal(&repo_path.to_string_lossy());
-    let workers = num_workers.max(1);
+        let kept = cap_revdeps(revdeps.clone(), 4, false, &progress).expect("within cap");
+        assert_eq!(kept.len(), 4);
 
-    format!(
-        r#"
-setwd({path_literal})
+        let filter = "^bioc".parse::<PkgFilter>().expect("pattern");
+        let filtered = filter_revdeps(revdeps, Some(&filter), &progress).expect("filtered");
+        let kept = cap_revdeps(filtered.clone(), 2, false, &progress).expect("within cap");
+        assert_eq!(kept, filtered);
 
-revdep_dir <- file.path("revdep")
-dir.create(revdep_dir, recursive = TRUE, showWarnings = FALSE)
+        let kept = cap_revdeps(filtered, 1, true, &progress).expect("truncated");
+        assert_eq!(kept, vec!["biocA"]);
+    }
 
-library_dir <- file.path(revdep_dir, "library")
-dir.create(library_dir, recursive = TRUE, showWarnings = FALSE)
+    #[test]
+    fn names_tarball_sources_after_their_package() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let sources = tmp.path().join("sources");
+        let root = sources.join("my-pkg-main");
+        fs::create_dir_all(&root).expect("package dir");
+        fs::write(root.join("DESCRIPTION"), "Package: mypkg\nVersion: 1.0.0\n")
+            .expect("DESCRIPTION");
+        let tarball = tmp.path().join("my-pkg-main.tar.gz");
+        let shell = Shell::new().expect("shell");
+        cmd!(shell, "tar -czf {tarball} -C {sources} my-pkg-main")
+            .quiet()
+            .run()
+            .expect("create tarball");
+
+        let work_dir = tmp.path().join("work");
+        let workspace = workspace::prepare(Some(work_dir.clone()), false).expect("workspace");
+        let extraction =
+            prepare_tarball(&shell, &workspace, &tarball, 1, &Progress::new()).expect("extracted");
+        let extracted = extraction.path().to_path_buf();
+        assert_eq!(
+            extracted,
+            work_dir.canonicalize().expect("work dir").join("mypkg")
+        );
+        assert_eq!(
+            description::read_package_name(&extracted).expect("package name"),
+            "mypkg"
+        );
 
-Sys.setenv(R_LIBS_USER = library_dir)
-.libPaths(c(library_dir, .libPaths()))
+        drop(extraction);
+        assert!(!extracted.exists());
 
-install_workers <- max({workers}, parallel::detectCores())
-options(Ncpus = install_workers)
-"#
-    )
-}
+        let extraction =
+            prepare_tarball(&shell, &workspace, &tarball, 1, &Progress::new()).expect("extracted");
+        let kept = extraction.keep();
+        assert!(kept.join("DESCRIPTION").exists());
+    }
 
-fn detect_ubuntu_codename() -> Result<String> {
-    if let Ok(value) = env::var("REVDEPRUN_UBUNTU_CODENAME") {
-        let trimmed = value.trim();
-        if !trimmed.is_empty() {
-            return Ok(trimmed.to_lowercase());
+    #[test]
+    fn resolves_symlinked_package_directories() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let package = tmp.path().join("src").join("ggsci");
+        fs::create_dir_all(&package).expect("package dir");
+        fs::write(package.join("DESCRIPTION"), "Package: ggsci\n").expect("DESCRIPTION");
+        let link = tmp.path().join("linked");
+        std::os::unix::fs::symlink(&package, &link).expect("symlink");
+
+        let resolved = prepare_local_directory(&link, &Progress::new()).expect("resolved");
+        assert_eq!(resolved, package.canonicalize().expect("canonical"));
+        assert_eq!(
+            description::read_package_name(&resolved).expect("package name"),
+            "ggsci"
+        );
+        assert_eq!(
+            describe_local_directory(&link, &resolved),
+            format!(
+                "Using {} (resolved to {})",
+                link.display(),
+                resolved.display()
+            )
+        );
+        assert_eq!(
+            describe_local_directory(&resolved, &resolved),
+            format!("Using {}", resolved.display())
+        );
+
+        let work_dir = tmp.path().join("work");
+        let workspace = workspace::prepare(Some(work_dir), false).expect("workspace");
+        workspace
+            .check_local_package(&link)
+            .expect("work dir outside package");
+    }
+
+    #[test]
+    fn rejects_repository_specs_that_look_like_flag_values() {
+        for spec in [
+            "ggsci",
+            "data.table",
+            "gh:nanxstats/ggsci",
+            "https://github.com/nanxstats/ggsci.git",
+            "git@github.com:nanxstats/ggsci.git",
+            "../ggsci",
+            "ggsci_3.2.0.tar.gz",
+        ] {
+            assert!(validate_repository_spec(spec).is_ok(), "{spec}");
+        }
+        for spec in ["4.4.1", "8", "x", "ggsci.", "noble,jammy"] {
+            let err = validate_repository_spec(spec).expect_err(spec);
+            assert!(err.to_string().contains("did you mean a flag?"), "{err}");
         }
     }
 
-    let contents =
-        fs::read_to_string("/etc/os-release").context("failed to read /etc/os-release")?;
+    #[test]
+    fn expands_forge_short_specs() {
+        assert_eq!(
+            expand_forge_spec("gl:org/pkg").as_deref(),
+            Some("https://gitlab.com/org/pkg.git")
+        );
+        assert_eq!(
+            expand_forge_spec("gitlab:group/subgroup/pkg").as_deref(),
+            Some("https://gitlab.com/group/subgroup/pkg.git")
+        );
+        assert_eq!(
+            expand_forge_spec("bitbucket:org/pkg.git").as_deref(),
+            Some("https://bitbucket.org/org/pkg.git")
+        );
+        assert_eq!(
+            expand_forge_spec("gh:nanxstats/ggsci").as_deref(),
+            Some("https://github.com/nanxstats/ggsci.git")
+        );
+        assert_eq!(expand_forge_spec("gh:nanxstats/ggsci/extra"), None);
+        assert_eq!(expand_forge_spec("gh:ggsci"), None);
+        assert_eq!(expand_forge_spec("git@github.com:org/pkg.git"), None);
+        assert_eq!(expand_forge_spec("https://github.com/org/pkg.git"), None);
+    }
 
-    if let Some(codename) = ubuntu_codename_from_os_release(&contents) {
-        return Ok(codename);
+    #[test]
+    fn install_script_can_install_with_pak() {
+        let path = Path::new("/tmp/example");
+        let revdeps = ["pkgA".to_string()];
+        let base = build_revdep_install_script(path, &revdeps, &test_options(), Some("noble"))
+            .expect("install script");
+        assert!(base.contains("install.packages(pkgs, repos = repos, lib = lib"));
+        assert!(base.contains("install_packages(pending, binary_repo, library_dir)"));
+        assert!(!base.contains("pak::"));
+
+        let options = RevcheckOptions {
+            installer: Installer::Pak,
+            ..test_options()
+        };
+        let pak = build_revdep_install_script(path, &revdeps, &options, Some("noble"))
+            .expect("install script");
+        assert!(pak.contains("ensure_installed(\"pak\")"));
+        assert!(pak.contains("pak::pkg_install(pkgs, lib = lib, upgrade = FALSE, ask = FALSE)"));
+        assert!(pak.contains("install_packages(missing, binary_repo, library_dir)"));
+        assert!(pak.contains("on.exit(options(old_options), add = TRUE)"));
+        assert!(!pak.contains("install.packages(pkgs"));
     }
 
-    bail!("VERSION_CODENAME not found in /etc/os-release")
-}
+    #[test]
+    fn scripts_can_isolate_target_dependencies() {
+        let path = Path::new("/tmp/example");
+        let options = RevcheckOptions {
+            isolate_target_deps: true,
+            ..test_options()
+        };
+        let revdeps = ["pkgA".to_string()];
+        let install = build_revdep_install_script(path, &revdeps, &options, Some("noble"))
+            .expect("install script");
+        assert!(install.contains(r#"target_library <- file.path(revdep_dir, "target-library")"#));
+        assert!(install.contains(".libPaths(c(target_library, .libPaths()))"));
+        assert!(install.contains("target_deps <- character()"));
+        let target_deps = build_target_deps_install_script(path, &options, Some("noble"));
+        assert!(target_deps.contains("install_packages(target_deps, binary_repo, target_library)"));
+        let run = build_revdep_run_script(path, &revdeps, &options).expect("run script");
+        assert!(run.contains(".libPaths(c(target_library, .libPaths()))"));
+        assert!(!run.contains("target_library)"));
+
+        let plain = build_revdep_install_script(path, &revdeps, &test_options(), Some("noble"))
+            .expect("install script");
+        assert!(!plain.contains("target_library"));
+    }
 
-fn ubuntu_codename_from_os_release(contents: &str) -> Option<String> {
-    let mut fallback = None;
+    #[test]
+    fn target_deps_are_installed_before_the_revdeps() {
+        let path = Path::new("/tmp/example");
+        let revdeps = ["pkgA".to_string()];
+        let target_deps = build_target_deps_install_script(path, &test_options(), Some("noble"));
+        assert!(target_deps.contains("install_packages(target_deps, binary_repo, library_dir)"));
+        assert!(target_deps.contains("'revdeprun-target-deps.txt'"));
+        assert!(!target_deps.contains("revdeps"));
+
+        let install = build_revdep_install_script(path, &revdeps, &test_options(), Some("noble"))
+            .expect("install script");
+        assert!(
+            install
+                .contains("target_deps_file <- file.path(revdep_dir, 'revdeprun-target-deps.txt')")
+        );
+        assert!(install.contains("installed_this_run <- target_deps"));
+    }
 
-    for line in contents.lines() {
-        let line = line.trim();
-        if line.is_empty() || line.starts_with('#') || !line.contains('=') {
-            continue;
-        }
-        let (key, value) = line.split_once('=')?;
-        let key = key.trim();
-        let mut value = value
-            .trim()
-            .trim_matches('"')
-            .trim_matches('\'')
-            .to_string();
-        if value.is_empty() {
-            continue;
-        }
-        value = value.to_lowercase();
+    #[test]
+    fn build_run_script_can_skip_vignettes() {
+        let path = Path::new("/tmp/example");
+        let options = RevcheckOptions {
+            build_vignettes: false,
+            ..test_options()
+        };
+        let script = build_revdep_run_script(path, &["pkgA".to_string()], &options)
+            .expect("script must build");
+        assert!(script.contains(r#"append(args, "--no-build-vignettes", after = 1)"#));
+        assert!(script.contains(r#"identical(names(formals(rcmd))[1], "args")"#));
+
+        let plain = build_revdep_run_script(path, &["pkgA".to_string()], &test_options())
+            .expect("script must build");
+        assert!(!plain.contains("--no-build-vignettes"));
+    }
 
-        if key == "VERSION_CODENAME" {
-            return Some(value);
-        }
-        if key == "UBUNTU_CODENAME" {
-            fallback = Some(value);
-        }
+    #[test]
+    fn scripts_can_skip_the_target_build() {
+        let path = Path::new("/tmp/example");
+        let options = RevcheckOptions {
+            check_self: false,
+            build_vignettes: false,
+            ..test_options()
+        };
+        let revdeps = ["pkgA".to_string()];
+        let install = build_revdep_install_script(path, &revdeps, &options, Some("noble"))
+            .expect("script must build");
+        assert!(install.contains("install_targets <- sort(unique(revdeps))"));
+        assert!(install.contains(r#"stop("--no-self needs ", package_name"#));
+
+        let run = build_revdep_run_script(path, &revdeps, &options).expect("script must build");
+        assert!(!run.contains("xfun::rev_check("));
+        assert!(run.contains(r#"check_args = "--no-build-vignettes""#));
+        assert!(run.contains("destdir = sources_dir"));
+        assert!(run.contains("tools::check_packages_in_dir(sources_dir"));
+        assert!(!run.contains(r#"destdir = ".""#));
+        assert!(run.contains("results <- vapply(revdeps, check_status, integer(1))"));
+
+        let default = build_revdep_install_script(path, &revdeps, &test_options(), Some("noble"))
+            .expect("script must build");
+        assert!(default.contains("install_targets <- sort(unique(c(package_name, revdeps)))"));
+        assert!(!default.contains("--no-self"));
     }
 
-    fallback
-}
+    #[test]
+    fn scripts_pin_bioc_version() {
+        let path = Path::new("/tmp/example");
+        let options = RevcheckOptions {
+            cran_type: CranType::Both,
+            bioc_version: Some(BiocVersion("3.21".to_string())),
+            ..test_options()
+        };
+        let revdeps = ["pkgA".to_string()];
+        let install = build_revdep_install_script(path, &revdeps, &options, Some("noble"))
+            .expect("script must build");
+        assert!(install.contains("bioc_version <- '3.21'"));
+        assert!(install.contains("Sys.setenv(R_BIOC_VERSION = bioc_version)"));
+        assert!(install.contains("repos = c(posit = binary_repo, CRAN = source_repo, bioc_repos)"));
+        assert!(install.contains("available.packages(repos = c(CRAN = source_repo, bioc_repos)"));
+
+        let run = build_revdep_run_script(path, &revdeps, &options).expect("script must build");
+        assert!(run.contains("repos = c(CRAN = source_repo, bioc_repos),"));
+
+        let plain =
+            build_revdep_run_script(path, &revdeps, &test_options()).expect("script must build");
+        assert!(!plain.contains("bioc_repos"));
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::workspace;
-    use std::fs;
-    use tempfile::tempdir;
-    use xshell::Shell;
+    #[test]
+    fn build_install_script_can_skip_suggests() {
+        let path = Path::new("/tmp/example");
+        let options = RevcheckOptions {
+            install_suggests: false,
+            target_suggests: false,
+            ..test_options()
+        };
+        let script = build_revdep_install_script(path, &["pkgA".to_string()], &options, None)
+            .expect("script must build");
+
+        assert!(script.contains("dependency_kinds <- c('Depends', 'Imports', 'LinkingTo')"));
+        assert!(!script.contains("Suggests"));
+        assert!(script.contains("revdeps <- c('pkgA')"));
+
+        let options = RevcheckOptions {
+            target_suggests: false,
+            ..test_options()
+        };
+        let script = build_revdep_install_script(path, &["pkgA".to_string()], &options, None)
+            .expect("script must build");
+        assert!(
+            script.contains("dependency_kinds <- c('Depends', 'Imports', 'LinkingTo', 'Suggests')")
+        );
+        assert!(
+            script.contains("target_dependency_kinds <- c('Depends', 'Imports', 'LinkingTo')\n")
+        );
+        assert!(script.contains("packages = intersect(install_targets, package_name)"));
+    }
 
     #[test]
-    fn build_install_script_uses_binary_repo() {
+    fn build_install_script_without_codename_uses_source_repo() {
         let path = Path::new("/tmp/example");
-        let script = build_revdep_install_script(path, 8, "noble").expect("script must build");
+        let script = build_revdep_install_script(path, &[], &test_options(), None)
+            .expect("script must build");
 
-        assert!(script.contains("https://packagemanager.posit.co/cran/__linux__/%s/latest"));
-        assert!(script.contains(
-            "sprintf(\"https://packagemanager.posit.co/cran/__linux__/%s/latest\", 'noble')"
+        assert!(!script.contains("__linux__"));
+        assert!(script.contains("binary_repo <- source_repo"));
+        assert!(script.contains("options(pkgType = \"source\")"));
+    }
+
+    #[test]
+    fn adds_additional_repositories_from_description() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        fs::write(
+            dir.path().join("DESCRIPTION"),
+            "Package: ggsci\nAdditional_repositories: https://a.github.io/drat,\n    https://b.r-universe.dev\n",
+        )
+        .expect("DESCRIPTION");
+        let repos = additional_repositories(dir.path());
+        assert_eq!(
+            repos,
+            vec!["https://a.github.io/drat", "https://b.r-universe.dev"]
+        );
+        assert!(additional_repositories(&dir.path().join("missing")).is_empty());
+
+        let options = RevcheckOptions {
+            additional_repos: repos,
+            ..test_options()
+        };
+        let revdeps = vec!["pkgA".to_string()];
+        let install = build_revdep_install_script(dir.path(), &revdeps, &options, Some("noble"))
+            .expect("script must build");
+        assert!(install.contains(
+            "additional_repos <- c('https://a.github.io/drat', 'https://b.r-universe.dev')"
         ));
-        assert!(script.contains("install.packages("));
-        assert!(script.contains("install_targets <- sort(unique(c(package_name, revdeps)))"));
-        assert!(script.contains("dependency_map <- tools::package_dependencies("));
-        assert!(script.contains("recursive = FALSE"));
-        assert!(script.contains("repos = binary_repo"));
-        assert!(script.contains("Skipping packages not available from repository"));
-        assert!(script.contains("setwd('/tmp/example')"));
+        assert!(install.contains("repos = c(binary_repo, additional_repos),"));
+        assert!(install.contains("available.packages(repos = c(source_repo, additional_repos)"));
+        let run =
+            build_revdep_run_script(dir.path(), &revdeps, &options).expect("script must build");
+        assert!(run.contains("repos = c(CRAN = source_repo, additional_repos),"));
+
+        let plain = build_revdep_run_script(dir.path(), &revdeps, &test_options())
+            .expect("script must build");
+        assert!(!plain.contains("additional_repos"));
     }
 
     #[test]
     fn build_run_script_invokes_xfun() {
         let path = Path::new("/tmp/example");
-        let script = build_revdep_run_script(path, 8).expect("script must build");
+        let revdeps = vec!["pkgA".to_string()];
+        let script =
+            build_revdep_run_script(path, &revdeps, &test_options()).expect("script must build");
 
         assert!(script.contains("xfun::rev_check"));
-        assert!(script.contains("src = \".\""));
+        assert!(script.contains("src = target_source"));
         assert!(script.contains("mc.cores = install_workers"));
+        assert!(script.contains("install_workers <- 8L"));
+        assert!(!script.contains("detectCores"));
         assert!(script.contains("ensure_installed(\"markdown\")"));
         assert!(script.contains("ensure_installed(\"rmarkdown\")"));
         assert!(script.contains("options(xfun.rev_check.summary = TRUE)"));
         assert!(script.contains("setwd('/tmp/example')"));
         assert!(script.contains("library_dir <- file.path(revdep_dir, \"library\")"));
+        assert!(script.contains("'revdeprun-results.tsv'"));
+        assert!(script.contains("as.integer(results)"));
+        assert!(script.contains("revdeps <- c('pkgA')"));
+        assert!(script.contains("recheck = revdeps"));
+        assert!(!script.contains("available.packages(repos = source_repo)"));
+
+        let options = RevcheckOptions {
+            require_cran_release: true,
+            ..test_options()
+        };
+        let script = build_revdep_run_script(path, &revdeps, &options).expect("script must build");
+        assert!(
+            script.contains(
+                "if (!package_name %in% rownames(available.packages(repos = source_repo)))"
+            )
+        );
+    }
+
+    fn list_options(source: RevdepsSource) -> RevdepListOptions {
+        RevdepListOptions {
+            num_workers: 4,
+            connections: util::ConnectionTuning::default(),
+            source,
+            since: None,
+            cran_mirror: DEFAULT_CRAN_MIRROR.to_string(),
+            strong_only: false,
+        }
+    }
+
+    #[test]
+    fn build_list_script_supports_both_sources() {
+        let output = Path::new("/tmp/revdeps.txt");
+        let tools = build_revdep_list_script("ggsci", &list_options(RevdepsSource::Tools), output);
+        assert!(tools.contains("tools::package_dependencies"));
+        assert!(tools.contains("reverse = TRUE"));
+        assert!(tools.contains("available.packages"));
+        assert!(tools.contains("package database empty - is the CRAN mirror reachable?"));
+        assert!(tools.contains("setdiff(revdeps, c(base_pkgs, pkg_name))"));
+        assert!(tools.contains("writeLines(revdeps, '/tmp/revdeps.txt')"));
+        assert!(!tools.contains("CRAN_package_db"));
+
+        let revdepcheck =
+            build_revdep_list_script("ggsci", &list_options(RevdepsSource::Revdepcheck), output);
+        assert!(revdepcheck.contains("revdepcheck::cran_revdeps(pkg_name"));
+        assert!(revdepcheck.contains("setdiff(revdeps, c(base_pkgs, pkg_name))"));
+        assert!(
+            tools.contains("dependency_kinds <- c('Depends', 'Imports', 'LinkingTo', 'Suggests')")
+        );
+
+        let strong = RevdepListOptions {
+            strong_only: true,
+            ..list_options(RevdepsSource::Tools)
+        };
+        let strong = build_revdep_list_script("ggsci", &strong, output);
+        assert!(strong.contains("dependency_kinds <- c('Depends', 'Imports', 'LinkingTo')\n"));
+    }
+
+    #[test]
+    fn build_list_script_filters_by_publication_date() {
+        let options = RevdepListOptions {
+            since: Some("2025-06-01".to_string()),
+            ..list_options(RevdepsSource::Tools)
+        };
+        let script = build_revdep_list_script("ggsci", &options, Path::new("/tmp/revdeps.txt"));
+        assert!(script.contains("since <- as.Date('2025-06-01')"));
+        assert!(script.contains("tools::CRAN_package_db()"));
+        assert!(script.contains("published >= since"));
+    }
+
+    #[test]
+    fn parses_revdep_list() {
+        assert_eq!(
+            parse_revdep_list("pkgB\n\npkgA\npkgB\n"),
+            vec!["pkgA".to_string(), "pkgB".to_string()]
+        );
+        assert!(parse_revdep_list("").is_empty());
     }
 
     #[test]
@@ -630,6 +3339,24 @@ UBUNTU_CODENAME=noble
 "#;
         let codename = ubuntu_codename_from_os_release(contents);
         assert_eq!(codename.as_deref(), Some("noble"));
+
+        let mint = r#"
+NAME="Linux Mint"
+ID=linuxmint
+ID_LIKE="ubuntu debian"
+VERSION_CODENAME=wilma
+UBUNTU_CODENAME=noble
+"#;
+        assert_eq!(
+            ubuntu_codename_from_os_release(mint).as_deref(),
+            Some("noble")
+        );
+
+        let debian = "ID=debian\nVERSION_CODENAME=bookworm\n";
+        assert_eq!(
+            ubuntu_codename_from_os_release(debian).as_deref(),
+            Some("bookworm")
+        );
     }
 
     #[test]
@@ -637,8 +3364,146 @@ UBUNTU_CODENAME=noble
         assert!(is_tarball(Path::new("pkg_0.1.0.tar.gz")));
         assert!(is_tarball(Path::new("pkg.TAR.GZ")));
         assert!(!is_tarball(Path::new("pkg.zip")));
-        assert!(!is_tarball(Path::new("pkg.tar")));
+        assert!(is_tarball(Path::new("pkg_1.0.tar")));
         assert!(!is_tarball(Path::new("pkg.tgz")));
+        assert!(is_tarball(Path::new("pkg_1.0.tar.zst")));
+        assert_eq!(
+            ArchiveKind::from_path(Path::new("pkg_1.0.TAR.ZST")),
+            Some(ArchiveKind::Zstd)
+        );
+        assert_eq!(
+            ArchiveKind::from_path(Path::new("pkg_1.0.tar")),
+            Some(ArchiveKind::Plain)
+        );
+        assert_eq!(
+            ArchiveKind::supported_suffixes(),
+            ".tar.gz, .tar.zst, or .tar"
+        );
+    }
+
+    #[test]
+    fn infers_package_name_from_archive_name() {
+        assert_eq!(
+            infer_package_name(Path::new("mypkg_0.1.0.tar.zst"), ArchiveKind::Zstd).as_deref(),
+            Some("mypkg")
+        );
+        assert_eq!(
+            infer_package_name(Path::new("mypkg.TAR.GZ"), ArchiveKind::Gzip).as_deref(),
+            Some("mypkg")
+        );
+        assert_eq!(
+            infer_package_name(Path::new("mypkg_1.0.tar"), ArchiveKind::Plain).as_deref(),
+            Some("mypkg")
+        );
+    }
+
+    #[test]
+    fn detects_missing_zstd_support() {
+        assert!(lacks_zstd_support(
+            "tar: unrecognized option '--zstd'\nTry 'tar --help' for more information."
+        ));
+        assert!(lacks_zstd_support(
+            "/bin/sh: 1: zstd: not found\ntar: Child returned status 127"
+        ));
+        assert!(!lacks_zstd_support(
+            "tar: This does not look like a tar archive"
+        ));
+    }
+
+    #[test]
+    fn finds_packages_with_check_errors() {
+        assert!(is_check_error_line("* checking tests ... ERROR"));
+        assert!(!is_check_error_line("* checking tests ... OK"));
+        assert!(!is_check_error_line("Error: ERROR"));
+
+        let dir = tempfile::tempdir().expect("tempdir");
+        for (package, log) in [
+            ("pkgB", "* checking examples ... ERROR\n"),
+            ("pkgA", "* checking examples ... OK\n"),
+        ] {
+            let check_dir = dir.path().join(format!("{package}.Rcheck"));
+            fs::create_dir_all(&check_dir).expect("create check dir");
+            fs::write(check_dir.join("00check.log"), log).expect("write log");
+        }
+        assert_eq!(failed_check_packages(dir.path()), vec!["pkgB"]);
+    }
+
+    #[test]
+    fn parses_github_pull_request_urls() {
+        assert_eq!(
+            github_pull_request("https://github.com/nanxstats/ggsci/pull/123"),
+            Some(("https://github.com/nanxstats/ggsci.git".to_string(), 123))
+        );
+        assert_eq!(
+            github_pull_request("https://github.com/nanxstats/ggsci/pull/7/files/"),
+            Some(("https://github.com/nanxstats/ggsci.git".to_string(), 7))
+        );
+        assert_eq!(
+            github_pull_request("https://github.com/nanxstats/ggsci.git"),
+            None
+        );
+        assert_eq!(
+            github_pull_request("https://github.com/nanxstats/ggsci/issues/5"),
+            None
+        );
+    }
+
+    #[test]
+    fn clone_depth_controls_shallow_flags() {
+        assert_eq!(
+            clone_depth_args(CloneDepth::Shallow(1)),
+            vec!["--depth", "1", "--shallow-submodules"]
+        );
+        assert!(clone_depth_args(CloneDepth::Full).is_empty());
+    }
+
+    #[test]
+    fn detects_rate_limited_clones() {
+        let stderr = "remote: Rate limit exceeded. Retry-After: 60\n\
+                      fatal: unable to access 'https://github.com/x/y.git/': \
+                      The requested URL returned error: 429";
+        assert!(is_rate_limited(stderr));
+        assert_eq!(retry_after_hint(stderr), Some(Duration::from_secs(60)));
+        assert_eq!(
+            retry_after_hint("remote: Too many requests, retry after 30 seconds"),
+            Some(Duration::from_secs(30))
+        );
+        assert!(!is_rate_limited(
+            "fatal: repository 'https://github.com/x/y.git/' not found"
+        ));
+        assert_eq!(retry_after_hint("remote: API rate limit exceeded"), None);
+    }
+
+    #[test]
+    fn classifies_transient_clone_errors() {
+        assert!(is_transient_clone_error(
+            "fatal: unable to access 'https://github.com/x/y.git/': Could not resolve host: github.com"
+        ));
+        assert!(is_transient_clone_error(
+            "error: RPC failed; curl 18 transfer closed\nfatal: early EOF"
+        ));
+        assert!(!is_transient_clone_error(
+            "error: RPC failed; HTTP 404 Not Found from proxy\nfatal: the remote end hung up unexpectedly"
+        ));
+        assert!(!is_transient_clone_error(
+            "fatal: unable to access 'https://example.com/y.git/': The requested URL returned error: 403"
+        ));
+        assert!(is_transient_clone_error(
+            "error: RPC failed; HTTP 408 curl 22 The requested URL returned error: 408\nfatal: the remote end hung up unexpectedly"
+        ));
+        assert!(!is_transient_clone_error(
+            "remote: Repository not found.\nfatal: repository 'https://github.com/x/y.git/' not found"
+        ));
+        assert!(!is_transient_clone_error(
+            "fatal: Authentication failed for 'https://github.com/x/y.git/'"
+        ));
+        assert!(!is_transient_clone_error("fatal: something unexpected"));
+    }
+
+    #[test]
+    fn pigz_program_uses_worker_count() {
+        assert_eq!(pigz_program_arg(8), "--use-compress-program=pigz -p 8");
+        assert_eq!(pigz_program_arg(0), "--use-compress-program=pigz -p 1");
     }
 
     #[test]
@@ -661,30 +3526,282 @@ UBUNTU_CODENAME=noble
         )
         .expect("R script");
 
-        let tarball_path = tmp.path().join("mypkg_0.1.0.tar.gz");
+        for (archive, create_flags) in [("mypkg_0.1.0.tar.gz", "-czf"), ("mypkg_0.1.0.tar", "-cf")]
         {
-            let _dir = shell.push_dir(tmp.path());
-            cmd!(shell, "tar -czf {tarball_path} {package_name}")
-                .quiet()
-                .run()
-                .expect("create tarball");
+            let tarball_path = tmp.path().join(archive);
+            {
+                let _dir = shell.push_dir(tmp.path());
+                cmd!(shell, "tar {create_flags} {tarball_path} {package_name}")
+                    .quiet()
+                    .run()
+                    .expect("create tarball");
+            }
+
+            let workspace_root = tmp.path().join(format!("workspace-{archive}"));
+            let workspace =
+                workspace::prepare(Some(workspace_root.clone()), false).expect("workspace");
+            let progress = Progress::new();
+
+            let prepared = prepare_repository(
+                &shell,
+                &workspace,
+                tarball_path.to_str().expect("utf8 path"),
+                &PrepareOptions {
+                    num_workers: 2,
+                    clone_retries: 0,
+                    clone_depth: CloneDepth::Shallow(1),
+                },
+                &progress,
+            )
+            .expect("prepared repository");
+            assert!(prepared.extraction.is_some());
+            let repo_path = prepared.path.clone();
+
+            assert!(repo_path.join("DESCRIPTION").exists());
+            let expected = workspace::canonicalized(&workspace_root.join("mypkg"))
+                .expect("canonical expected path");
+            assert_eq!(repo_path, expected);
         }
+    }
 
-        let workspace_root = tmp.path().join("workspace");
-        let workspace = workspace::prepare(Some(workspace_root.clone())).expect("workspace");
+    #[test]
+    fn rebuilds_library_after_target_version_change() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let repo = tmp.path();
+        let installed = library_dir(repo).join("pkgA");
+        fs::create_dir_all(&installed).expect("library");
+        fs::write(repo.join("DESCRIPTION"), "Package: mypkg\nVersion: 1.0.0\n")
+            .expect("DESCRIPTION");
         let progress = Progress::new();
 
-        let repo_path = prepare_repository(
+        refresh_stale_library(repo, false, &progress).expect("unstamped library");
+        assert!(installed.exists());
+
+        write_library_stamp(repo).expect("stamp");
+        refresh_stale_library(repo, false, &progress).expect("matching stamp");
+        assert!(installed.exists());
+
+        fs::write(repo.join("DESCRIPTION"), "Package: mypkg\nVersion: 1.1.0\n")
+            .expect("DESCRIPTION");
+        refresh_stale_library(repo, true, &progress).expect("reused library");
+        assert!(installed.exists());
+
+        refresh_stale_library(repo, false, &progress).expect("rebuilt library");
+        assert!(!library_dir(repo).exists());
+    }
+
+    #[test]
+    fn runs_hook_scripts_from_the_repository() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let repo = tmp.path().join("mypkg");
+        fs::create_dir_all(&repo).expect("repo");
+        let script = tmp.path().join("setup.sh");
+        fs::write(&script, "echo configured > setup.txt\n").expect("script");
+        let shell = Shell::new().expect("shell");
+
+        run_hook_script(
+            &shell,
+            &repo,
+            &script,
+            "pre-check script",
+            None,
+            &Progress::new(),
+        )
+        .expect("script succeeds");
+        assert_eq!(
+            fs::read_to_string(repo.join("setup.txt")).expect("output"),
+            "configured\n"
+        );
+
+        fs::write(
+            &script,
+            format!("echo \"$1 ${RESULTS_DIR_ENV}\" > results.txt\n"),
+        )
+        .expect("post-check script");
+        let results = results_dir(&repo);
+        run_hook_script(
+            &shell,
+            &repo,
+            &script,
+            "post-check script",
+            Some(&results),
+            &Progress::new(),
+        )
+        .expect("script succeeds");
+        assert_eq!(
+            fs::read_to_string(repo.join("results.txt")).expect("output"),
+            format!("{0} {0}\n", results.display())
+        );
+
+        fs::write(&script, "exit 3\n").expect("failing script");
+        let err = run_hook_script(
             &shell,
-            &workspace,
-            tarball_path.to_str().expect("utf8 path"),
-            &progress,
+            &repo,
+            &script,
+            "pre-check script",
+            None,
+            &Progress::new(),
         )
-        .expect("prepared repository");
+        .expect_err("script fails");
+        assert!(format!("{err:#}").contains("exited with"));
+        assert!(
+            run_hook_script(
+                &shell,
+                &repo,
+                &tmp.path().join("missing.sh"),
+                "pre-check script",
+                None,
+                &Progress::new()
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn resumes_only_from_what_an_earlier_run_left() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let workspace =
+            workspace::prepare(Some(tmp.path().join("work")), false).expect("workspace");
+        let spec = "https://github.com/nanxstats/ggsci.git";
+        assert!(existing_repository(&workspace, spec).is_err());
+
+        let repo = workspace.clone_root().join("ggsci");
+        fs::create_dir_all(&repo).expect("clone");
+        fs::write(repo.join("DESCRIPTION"), "Package: ggsci\nVersion: 3.2.0\n")
+            .expect("DESCRIPTION");
+        let repo = existing_repository(&workspace, "gh:nanxstats/ggsci").expect("existing clone");
+        assert!(repo.ends_with("ggsci"));
+
+        let err = load_resume_state(&repo, false).expect_err("no revdep list yet");
+        assert!(format!("{err:#}").contains("run without --continue-from first"));
+
+        save_revdeps(&repo, &["pkgB".to_string(), "pkgA".to_string()]).expect("saved");
+        assert_eq!(
+            load_resume_state(&repo, false).expect("revdeps"),
+            vec!["pkgA".to_string(), "pkgB".to_string()]
+        );
+        let err = load_resume_state(&repo, true).expect_err("no library yet");
+        assert!(format!("{err:#}").contains("continue from install instead"));
+
+        fs::create_dir_all(library_dir(&repo)).expect("library");
+        write_library_stamp(&repo).expect("stamp");
+        assert_eq!(load_resume_state(&repo, true).expect("library").len(), 2);
+    }
+
+    #[test]
+    fn selects_revdeps_from_inline_and_file_lists() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let list = tmp.path().join("known-bad.txt");
+        fs::write(&list, "# flaky on CI\npkgB\n\n  pkgC  # times out\n").expect("list");
+        let exclude = expand_package_specs(&[
+            PackageSpec::Name("pkgD".to_string()),
+            PackageSpec::File(list),
+        ])
+        .expect("expanded");
+        assert_eq!(exclude, vec!["pkgD", "pkgB", "pkgC"]);
+
+        let revdeps = ["pkgA", "pkgB", "pkgC", "pkgD", "pkgE"]
+            .map(str::to_string)
+            .to_vec();
+        let progress = Progress::new();
+        assert_eq!(
+            select_revdeps(revdeps.clone(), &[], &exclude, &progress).expect("selected"),
+            vec!["pkgA", "pkgE"]
+        );
+        assert_eq!(
+            select_revdeps(
+                revdeps.clone(),
+                &["pkgA".to_string(), "pkgB".to_string()],
+                &exclude,
+                &progress
+            )
+            .expect("selected"),
+            vec!["pkgA"]
+        );
+        assert!(select_revdeps(revdeps, &["pkgB".to_string()], &exclude, &progress).is_err());
+        assert!(
+            expand_package_specs(&[PackageSpec::File(tmp.path().join("missing.txt"))]).is_err()
+        );
+    }
+
+    #[test]
+    fn unpacks_library_cache_without_replacing_packages() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let cached = tmp.path().join("cached");
+        for package in ["pkgA", "pkgB"] {
+            fs::create_dir_all(cached.join(package)).expect("cached package");
+            fs::write(cached.join(package).join("DESCRIPTION"), "cached\n").expect("DESCRIPTION");
+        }
+        let cache = tmp.path().join("library.tar.gz");
+        let shell = Shell::new().expect("shell");
+        cmd!(shell, "tar -czf {cache} -C {cached} .")
+            .quiet()
+            .run()
+            .expect("create cache");
+
+        let repo = tmp.path().join("mypkg");
+        fs::create_dir_all(library_dir(&repo).join("pkgA")).expect("library");
+        fs::write(library_dir(&repo).join("pkgA/DESCRIPTION"), "fresh\n").expect("DESCRIPTION");
+
+        extract_library_cache(&shell, &cache, &repo, 1, &Progress::new()).expect("unpacked");
+        let read = |package: &str| {
+            fs::read_to_string(library_dir(&repo).join(package).join("DESCRIPTION"))
+                .expect("DESCRIPTION")
+        };
+        assert_eq!(read("pkgA"), "fresh\n");
+        assert_eq!(read("pkgB"), "cached\n");
+        assert!(
+            extract_library_cache(
+                &shell,
+                &tmp.path().join("x.zip"),
+                &repo,
+                1,
+                &Progress::new()
+            )
+            .is_err()
+        );
+    }
 
-        assert!(repo_path.join("DESCRIPTION").exists());
-        let expected = workspace::canonicalized(&workspace_root.join("mypkg"))
-            .expect("canonical expected path");
-        assert_eq!(repo_path, expected);
+    #[test]
+    fn cleans_libraries_but_keeps_results() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let repo = tmp.path();
+        fs::create_dir_all(library_dir(repo).join("pkgA")).expect("library");
+        fs::create_dir_all(target_library_dir(repo).join("pkgB")).expect("target library");
+        fs::create_dir_all(repo.join("pkgA.Rcheck")).expect("check dir");
+        fs::write(revlib_dir(repo).join(summary::STATUS_FILE), "").expect("status file");
+
+        clean_libraries(repo, &Progress::new()).expect("cleaned");
+        assert!(!library_dir(repo).exists());
+        assert!(!target_library_dir(repo).exists());
+        assert!(repo.join("pkgA.Rcheck").exists());
+        assert!(revlib_dir(repo).join(summary::STATUS_FILE).exists());
+
+        clean_libraries(repo, &Progress::new()).expect("nothing to clean");
+    }
+
+    #[test]
+    fn removes_extracted_sources_but_keeps_results() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let repo = tmp.path().join("mypkg");
+        fs::create_dir_all(repo.join("R")).expect("R dir");
+        fs::create_dir_all(library_dir(&repo)).expect("library");
+        fs::write(repo.join("DESCRIPTION"), "Package: mypkg\n").expect("DESCRIPTION");
+        fs::create_dir_all(repo.join("pkgA.Rcheck")).expect("check dir");
+        fs::write(repo.join("00check_diffs.html"), "<p>diffs</p>").expect("summary");
+
+        assert!(remove_extracted_sources(&repo).expect("cleanup"));
+        assert!(!repo.join("R").exists());
+        assert!(!repo.join("DESCRIPTION").exists());
+        assert!(library_dir(&repo).exists());
+        assert!(repo.join("pkgA.Rcheck").exists());
+        assert_eq!(results_summary(&repo), repo.join("00check_diffs.html"));
+
+        fs::remove_dir_all(revlib_dir(&repo)).expect("remove results");
+        fs::remove_dir_all(repo.join("pkgA.Rcheck")).expect("remove check dir");
+        fs::remove_file(repo.join("00check_diffs.html")).expect("remove summary");
+        assert_eq!(results_summary(&repo), repo);
+        assert!(!remove_extracted_sources(&repo).expect("cleanup"));
+        assert!(fs::read_dir(&repo).expect("sources dir").next().is_none());
     }
 }
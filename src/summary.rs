@@ -0,0 +1,530 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{cli::FailOn, description::Description, progress::Progress, revdep};
+
+/// File written by the run script with one `package<TAB>status` row per revdep.
+pub const STATUS_FILE: &str = "revdeprun-results.tsv";
+
+const MAX_EXCERPT_LINES: usize = 60;
+
+/// Check log fragments that indicate `R CMD check` was stopped by a time limit.
+/// Generic "timed out" messages are left out: they usually come from network
+/// calls in tests or examples, which are ordinary failures.
+const TIMEOUT_MARKERS: &[&str] = &["reached elapsed time limit", "reached CPU time limit"];
+
+/// Outcome of checking a single reverse dependency.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckStatus {
+    /// `R CMD check` found no new problems.
+    Ok,
+    /// `R CMD check` reported problems.
+    Failed,
+    /// `R CMD check` was stopped by a time limit.
+    TimedOut,
+    /// The package was not checked, e.g. because the global timeout elapsed.
+    NotChecked,
+}
+
+impl CheckStatus {
+    /// Every status, in report order.
+    pub const ALL: [Self; 4] = [Self::Ok, Self::Failed, Self::TimedOut, Self::NotChecked];
+
+    /// Maps the numeric codes returned by `xfun::rev_check()`.
+    fn from_code(code: &str) -> Option<Self> {
+        match code.trim() {
+            "0" => Some(Self::Ok),
+            "1" => Some(Self::Failed),
+            "2" => Some(Self::NotChecked),
+            _ => None,
+        }
+    }
+
+    /// Human readable label used in reports.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Ok => "OK",
+            Self::Failed => "Failed",
+            Self::TimedOut => "Timed out",
+            Self::NotChecked => "Not checked",
+        }
+    }
+
+    /// Stable machine-readable name, e.g. for the `--db` history.
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Failed => "failed",
+            Self::TimedOut => "timed_out",
+            Self::NotChecked => "not_checked",
+        }
+    }
+}
+
+/// Worst problem level in a revdep's check log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// A check ended in NOTE.
+    Note,
+    /// A check ended in WARNING.
+    Warning,
+    /// A check ended in ERROR.
+    Error,
+}
+
+impl Severity {
+    /// The lowest severity a `--fail-on` category counts, if it is one.
+    fn threshold(category: FailOn) -> Option<Self> {
+        match category {
+            FailOn::Note => Some(Self::Note),
+            FailOn::Warning => Some(Self::Warning),
+            FailOn::Error => Some(Self::Error),
+            FailOn::Timeout => None,
+        }
+    }
+}
+
+/// Result of checking one reverse dependency.
+#[derive(Clone, Debug)]
+pub struct PackageResult {
+    /// Name of the reverse dependency.
+    pub package: String,
+    /// Classified check outcome.
+    pub status: CheckStatus,
+    /// Problem lines extracted from the package's `00check.log`, if any.
+    pub excerpt: Option<String>,
+    /// Worst of NOTE, WARNING, and ERROR in the package's `00check.log`, if
+    /// the log was kept and has any.
+    pub severity: Option<Severity>,
+}
+
+/// Structured summary of an `xfun::rev_check()` run.
+#[derive(Clone, Debug)]
+pub struct CheckSummary {
+    /// Name of the target package.
+    pub package: String,
+    /// Version of the target package from its DESCRIPTION.
+    pub version: Option<String>,
+    /// Per-revdep results, sorted by package name.
+    pub results: Vec<PackageResult>,
+}
+
+impl CheckSummary {
+    /// Collects the results of a finished run for the repository at
+    /// `repo_path`, reading check logs from `check_dir`. A revdep without a
+    /// recognised status is reported as [`CheckStatus::NotChecked`] with a
+    /// warning.
+    pub fn collect(repo_path: &Path, check_dir: &Path, progress: &Progress) -> Result<Self> {
+        let description = Description::read(repo_path)?;
+        let package = description
+            .package()
+            .map(str::to_string)
+            .with_context(|| format!("no Package field in {}", repo_path.display()))?;
+
+        let status_path = status_file(repo_path);
+        let contents = fs::read_to_string(&status_path).with_context(|| {
+            format!(
+                "failed to read check results from {}",
+                status_path.display()
+            )
+        })?;
+
+        let mut results = parse_status_table(&contents)
+            .into_iter()
+            .map(|(name, status)| {
+                let mut status = status.unwrap_or_else(|| {
+                    progress.println(format!(
+                        "Warning: no check status recorded for {name}; reporting it as not checked."
+                    ));
+                    CheckStatus::NotChecked
+                });
+                let log = fs::read_to_string(check_log_path(check_dir, &name)).ok();
+                if status == CheckStatus::Failed && log.as_deref().is_some_and(is_timeout_log) {
+                    status = CheckStatus::TimedOut;
+                }
+                let excerpt = log
+                    .as_deref()
+                    .filter(|_| status != CheckStatus::Ok)
+                    .and_then(problem_excerpt);
+                PackageResult {
+                    package: name,
+                    status,
+                    excerpt,
+                    severity: log.as_deref().and_then(worst_severity),
+                }
+            })
+            .collect::<Vec<_>>();
+        results.sort_by(|a, b| a.package.cmp(&b.package));
+
+        Ok(Self {
+            package,
+            version: description.version().map(str::to_string),
+            results,
+        })
+    }
+
+    /// Number of revdeps with the given status.
+    pub fn count(&self, status: CheckStatus) -> usize {
+        self.results
+            .iter()
+            .filter(|result| result.status == status)
+            .count()
+    }
+
+    /// Revdeps whose status is anything other than [`CheckStatus::Ok`].
+    pub fn problems(&self) -> impl Iterator<Item = &PackageResult> {
+        self.results
+            .iter()
+            .filter(|result| result.status != CheckStatus::Ok)
+    }
+
+    /// Keeps only the revdeps `xfun::rev_check()` found new problems in
+    /// compared with the CRAN release of the target, for
+    /// `--only-changed-since-cran`.
+    pub fn retain_regressions(&mut self) {
+        self.results
+            .retain(|result| result.status == CheckStatus::Failed);
+    }
+
+    /// R version recorded in the first check log of these results, e.g.
+    /// `4.5.1`, for `--summary-only` runs that never resolve one.
+    pub fn logged_r_version(&self, check_dir: &Path) -> Option<String> {
+        self.results.iter().find_map(|result| {
+            let log = fs::read_to_string(check_log_path(check_dir, &result.package)).ok()?;
+            r_version_from_log(&log)
+        })
+    }
+
+    /// Revdeps that fall into one of the `fail_on` categories. A failed
+    /// check always counts as an ERROR, whatever its log's worst level;
+    /// the severity of a passing check only matters for `warning` and `note`.
+    pub fn failures<'a>(
+        &'a self,
+        fail_on: &'a [FailOn],
+    ) -> impl Iterator<Item = &'a PackageResult> + 'a {
+        let threshold = fail_on
+            .iter()
+            .filter_map(|&category| Severity::threshold(category))
+            .min();
+        self.results.iter().filter(move |result| {
+            let severity = match result.status {
+                CheckStatus::TimedOut => return fail_on.contains(&FailOn::Timeout),
+                CheckStatus::NotChecked => return false,
+                CheckStatus::Failed => Some(Severity::Error),
+                CheckStatus::Ok => result.severity,
+            };
+            threshold
+                .zip(severity)
+                .is_some_and(|(threshold, severity)| severity >= threshold)
+        })
+    }
+}
+
+/// Summary of one run of an `--r-version` matrix.
+#[derive(Clone, Debug)]
+pub struct CheckOutcome {
+    /// R version the revdeps were checked with.
+    pub r_version: String,
+    /// Results of the run.
+    pub summary: CheckSummary,
+}
+
+/// One row per revdep checked under any of `outcomes`, sorted by package,
+/// with its status under each R version (`None` when it was not checked
+/// there).
+pub fn matrix_rows(outcomes: &[CheckOutcome]) -> Vec<(String, Vec<Option<CheckStatus>>)> {
+    let mut packages = outcomes
+        .iter()
+        .flat_map(|outcome| outcome.summary.results.iter())
+        .map(|result| result.package.clone())
+        .collect::<Vec<_>>();
+    packages.sort();
+    packages.dedup();
+
+    packages
+        .into_iter()
+        .map(|package| {
+            let statuses = outcomes
+                .iter()
+                .map(|outcome| {
+                    outcome
+                        .summary
+                        .results
+                        .iter()
+                        .find(|result| result.package == package)
+                        .map(|result| result.status)
+                })
+                .collect();
+            (package, statuses)
+        })
+        .collect()
+}
+
+/// Path of the status table written by the run script.
+pub fn status_file(repo_path: &Path) -> PathBuf {
+    revdep::revlib_dir(repo_path).join(STATUS_FILE)
+}
+
+fn check_log_path(check_dir: &Path, package: &str) -> PathBuf {
+    check_dir
+        .join(format!("{package}.Rcheck"))
+        .join("00check.log")
+}
+
+/// Rows of the status table, with `None` for a revdep whose status is
+/// missing or unrecognised, e.g. `NA` when its check never reported one.
+fn parse_status_table(contents: &str) -> Vec<(String, Option<CheckStatus>)> {
+    let mut rows = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || (index == 0 && line.starts_with("package\t")) {
+            continue;
+        }
+        let (package, status) = match line.split_once('\t') {
+            Some((package, code)) => (package, CheckStatus::from_code(code)),
+            None => (line, None),
+        };
+        rows.push((package.trim().to_string(), status));
+    }
+    rows
+}
+
+/// Version from the `* using R version ...` line of a check log.
+fn r_version_from_log(log: &str) -> Option<String> {
+    log.lines()
+        .find_map(|line| line.trim_end().strip_prefix("* using R version "))
+        .and_then(|rest| rest.split_whitespace().next())
+        .map(str::to_string)
+}
+
+fn is_timeout_log(log: &str) -> bool {
+    TIMEOUT_MARKERS.iter().any(|marker| log.contains(marker))
+}
+
+/// The worst level any `* checking ...` line of `log` ended in.
+fn worst_severity(log: &str) -> Option<Severity> {
+    log.lines()
+        .map(|line| line.trim_end())
+        .filter(|line| line.starts_with("* "))
+        .filter_map(|line| {
+            if line.ends_with("ERROR") {
+                Some(Severity::Error)
+            } else if line.ends_with("WARNING") {
+                Some(Severity::Warning)
+            } else if line.ends_with("NOTE") {
+                Some(Severity::Note)
+            } else {
+                None
+            }
+        })
+        .max()
+}
+
+/// Extracts the `* checking ...` blocks that ended in NOTE, WARNING, or ERROR.
+fn problem_excerpt(log: &str) -> Option<String> {
+    let mut excerpt = Vec::new();
+    let mut in_problem = false;
+
+    for line in log.lines() {
+        let line = line.trim_end_matches('\r');
+        if line.starts_with("* ") {
+            in_problem = ["ERROR", "WARNING", "NOTE"]
+                .iter()
+                .any(|marker| line.trim_end().ends_with(marker));
+        }
+        if in_problem {
+            excerpt.push(line);
+        }
+    }
+
+    if excerpt.is_empty() {
+        return None;
+    }
+    if excerpt.len() > MAX_EXCERPT_LINES {
+        let omitted = excerpt.len() - MAX_EXCERPT_LINES;
+        excerpt.truncate(MAX_EXCERPT_LINES);
+        return Some(format!(
+            "{}\n... ({omitted} more lines)",
+            excerpt.join("\n")
+        ));
+    }
+    Some(excerpt.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_status_table() {
+        let rows = parse_status_table("package\tstatus\npkgA\t0\npkgB\t1\npkgC\t2\n");
+        assert_eq!(
+            rows,
+            vec![
+                ("pkgA".to_string(), Some(CheckStatus::Ok)),
+                ("pkgB".to_string(), Some(CheckStatus::Failed)),
+                ("pkgC".to_string(), Some(CheckStatus::NotChecked)),
+            ]
+        );
+        assert_eq!(
+            parse_status_table("pkgA\tNA\npkgB\n"),
+            vec![("pkgA".to_string(), None), ("pkgB".to_string(), None)]
+        );
+    }
+
+    #[test]
+    fn reports_missing_statuses_as_not_checked() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        fs::write(dir.path().join("DESCRIPTION"), "Package: ggsci\n").expect("DESCRIPTION");
+        fs::create_dir_all(revdep::revlib_dir(dir.path())).expect("revdep dir");
+        fs::write(
+            status_file(dir.path()),
+            "package\tstatus\npkgA\t0\npkgB\tNA\n",
+        )
+        .expect("status file");
+
+        let summary =
+            CheckSummary::collect(dir.path(), dir.path(), &Progress::new()).expect("summary");
+        assert_eq!(summary.count(CheckStatus::Ok), 1);
+        assert_eq!(summary.results[1].package, "pkgB");
+        assert_eq!(summary.results[1].status, CheckStatus::NotChecked);
+    }
+
+    #[test]
+    fn extracts_problem_blocks_from_check_log() {
+        let log = "* using R version 4.5.1\n\
+                   * checking examples ... OK\n\
+                   * checking tests ... ERROR\n\
+                   \x20 Running 'testthat.R'\n\
+                   \x20 Error: object 'foo' not found\n\
+                   * checking PDF version of manual ... OK\n\
+                   * DONE\n";
+        let excerpt = problem_excerpt(log).expect("excerpt");
+        assert!(excerpt.starts_with("* checking tests ... ERROR"));
+        assert!(excerpt.contains("object 'foo' not found"));
+        assert!(!excerpt.contains("manual"));
+
+        assert!(problem_excerpt("* checking examples ... OK\n").is_none());
+    }
+
+    #[test]
+    fn reads_r_version_from_check_log() {
+        assert_eq!(
+            r_version_from_log(
+                "* using log directory '/x'\n* using R version 4.5.1 (2025-06-13)\n"
+            )
+            .as_deref(),
+            Some("4.5.1")
+        );
+        assert_eq!(r_version_from_log("* checking examples ... OK\n"), None);
+    }
+
+    #[test]
+    fn lines_up_statuses_across_r_versions() {
+        let outcome = |r_version: &str, results: &[(&str, CheckStatus)]| CheckOutcome {
+            r_version: r_version.to_string(),
+            summary: CheckSummary {
+                package: "ggsci".to_string(),
+                version: None,
+                results: results
+                    .iter()
+                    .map(|&(package, status)| PackageResult {
+                        package: package.to_string(),
+                        status,
+                        excerpt: None,
+                        severity: None,
+                    })
+                    .collect(),
+            },
+        };
+        let rows = matrix_rows(&[
+            outcome(
+                "4.5.1",
+                &[("pkgB", CheckStatus::Ok), ("pkgA", CheckStatus::Ok)],
+            ),
+            outcome("4.4.3", &[("pkgA", CheckStatus::Failed)]),
+        ]);
+        assert_eq!(
+            rows,
+            vec![
+                (
+                    "pkgA".to_string(),
+                    vec![Some(CheckStatus::Ok), Some(CheckStatus::Failed)]
+                ),
+                ("pkgB".to_string(), vec![Some(CheckStatus::Ok), None]),
+            ]
+        );
+    }
+
+    #[test]
+    fn detects_timeouts_and_filters_failures() {
+        assert!(is_timeout_log(
+            "* checking tests ... ERROR\n  Error: reached elapsed time limit\n"
+        ));
+        assert!(!is_timeout_log(
+            "* checking tests ... ERROR\n  Error: boom\n"
+        ));
+        assert!(!is_timeout_log(
+            "* checking examples ... ERROR\n  Error in curl::curl_fetch_memory(url): Operation timed out after 10001 milliseconds\n"
+        ));
+
+        let result = |package: &str, status| PackageResult {
+            package: package.to_string(),
+            status,
+            excerpt: None,
+            severity: None,
+        };
+        let summary = CheckSummary {
+            package: "ggsci".to_string(),
+            version: None,
+            results: vec![
+                result("pkgA", CheckStatus::Ok),
+                result("pkgB", CheckStatus::Failed),
+                result("pkgC", CheckStatus::TimedOut),
+                result("pkgD", CheckStatus::NotChecked),
+            ],
+        };
+        let names = |fail_on: &[FailOn]| {
+            summary
+                .failures(fail_on)
+                .map(|result| result.package.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&[FailOn::Error]), vec!["pkgB"]);
+        assert_eq!(
+            names(&[FailOn::Error, FailOn::Timeout]),
+            vec!["pkgB", "pkgC"]
+        );
+
+        let mut graded = summary.clone();
+        graded.results[0].severity = Some(Severity::Note);
+        graded.results[1].severity = Some(Severity::Warning);
+        let graded_names = |fail_on: &[FailOn]| {
+            graded
+                .failures(fail_on)
+                .map(|result| result.package.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(graded_names(&[FailOn::Error]), vec!["pkgB"]);
+        assert_eq!(graded_names(&[FailOn::Warning]), vec!["pkgB"]);
+        assert_eq!(graded_names(&[FailOn::Note]), vec!["pkgA", "pkgB"]);
+        assert_eq!(
+            worst_severity("* checking examples ... NOTE\n* checking tests ... WARNING\n"),
+            Some(Severity::Warning)
+        );
+        assert_eq!(worst_severity("* checking tests ... OK\n"), None);
+
+        let mut regressions = summary.clone();
+        regressions.retain_regressions();
+        let packages = regressions
+            .results
+            .iter()
+            .map(|result| result.package.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(packages, vec!["pkgB"]);
+    }
+}
@@ -1,11 +1,39 @@
-use std::{fs, io::Write, path::Path};
-
-use anyhow::{Context, Result, anyhow, bail};
+use std::{
+    collections::HashSet,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
 use serde::{Deserialize, Deserializer};
 use tempfile::NamedTempFile;
 use xshell::{Shell, cmd};
 
-use crate::{progress::Progress, util, workspace::Workspace};
+use crate::{
+    auth,
+    cli::{BiocVersion, EnvVar},
+    description,
+    progress::Progress,
+    r_version, revdep, util,
+    workspace::Workspace,
+};
+
+/// Distributions `pak::pkg_sysreqs()` can resolve system requirements for.
+const SUPPORTED_PLATFORM_DISTROS: &[&str] = &[
+    "ubuntu",
+    "debian",
+    "centos",
+    "redhat",
+    "rockylinux",
+    "almalinux",
+    "fedora",
+    "opensuse",
+    "sle",
+];
+
+/// Platform used when the local distribution cannot be detected.
+const FALLBACK_PLATFORM: &str = "ubuntu";
 
 #[derive(Debug, Deserialize)]
 struct SysreqsPayload {
@@ -13,6 +41,71 @@ struct SysreqsPayload {
     install_scripts: Vec<String>,
     #[serde(default, deserialize_with = "string_or_vec")]
     post_install: Vec<String>,
+    #[serde(default, deserialize_with = "string_or_vec")]
+    revdeps: Vec<String>,
+}
+
+/// Controls how resolved system requirements are reported and applied.
+#[derive(Clone, Debug, Default)]
+pub struct SysreqsOptions {
+    /// Print the resolved commands and the reverse dependencies behind them.
+    pub print: bool,
+    /// Resolve and print the commands without running them.
+    pub dry_run: bool,
+    /// Extra environment variables for the `Rscript` process.
+    pub env: Vec<EnvVar>,
+    /// `sysreqs_platform` passed to `pak::pkg_sysreqs()`, e.g. `ubuntu-22.04`.
+    pub platform: String,
+    /// JSON payload to use instead of resolving system requirements with pak.
+    pub file: Option<PathBuf>,
+    /// CRAN-like repository pak resolves packages from.
+    pub cran_mirror: String,
+    /// Print apt output live while the install scripts run.
+    pub verbose_apt: bool,
+    /// Directory to save a copy of the generated R script in.
+    pub dump_scripts: Option<PathBuf>,
+    /// Bioconductor release pak resolves Bioconductor packages from.
+    pub bioc_version: Option<BiocVersion>,
+    /// `Additional_repositories` from the target's DESCRIPTION.
+    pub additional_repos: Vec<String>,
+    /// Tunables for R's `--max-connections`.
+    pub connections: util::ConnectionTuning,
+}
+
+/// Derives the sysreqs platform (e.g. `ubuntu-22.04`) from the detected
+/// distribution, falling back to plain `ubuntu`.
+pub fn default_platform() -> String {
+    r_version::linux_platform()
+        .ok()
+        .map(|platform| {
+            platform
+                .strip_prefix("linux-")
+                .unwrap_or(&platform)
+                .to_string()
+        })
+        .filter(|platform| validate_platform(platform).is_ok())
+        .unwrap_or_else(|| FALLBACK_PLATFORM.to_string())
+}
+
+/// Checks that `platform` is a `<distro>` or `<distro>-<version>` string for a
+/// distribution pak supports.
+pub fn validate_platform(platform: &str) -> Result<()> {
+    let (distro, version) = match platform.split_once('-') {
+        Some((distro, version)) => (distro, Some(version)),
+        None => (platform, None),
+    };
+    if !SUPPORTED_PLATFORM_DISTROS.contains(&distro) {
+        bail!(
+            "unsupported sysreqs platform '{platform}'; expected <distro> or <distro>-<version> with distro one of: {}",
+            SUPPORTED_PLATFORM_DISTROS.join(", ")
+        );
+    }
+    if let Some(version) = version {
+        if version.is_empty() || !version.chars().all(|ch| ch.is_ascii_digit() || ch == '.') {
+            bail!("invalid version '{version}' in sysreqs platform '{platform}'");
+        }
+    }
+    Ok(())
 }
 
 fn string_or_vec<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
@@ -40,17 +133,157 @@ where
     }
 }
 
-/// Resolves and installs system requirements for reverse dependencies.
+/// Resolves and installs system requirements for the reverse dependencies in
+/// `revdeps`.
 pub fn install_reverse_dep_sysreqs(
     shell: &Shell,
     workspace: &Workspace,
     repo_path: &Path,
+    revdeps: &[String],
     num_workers: usize,
+    options: SysreqsOptions,
     progress: &Progress,
 ) -> Result<()> {
-    let max_connections = util::optimal_max_connections(num_workers);
-    let package_name = read_package_name(repo_path)?;
-    let script_contents = build_sysreqs_script(&package_name, num_workers)?;
+    let package_name = description::read_package_name(repo_path)?;
+    let mut payload = match &options.file {
+        Some(path) => {
+            progress.println(format!(
+                "Using system requirements from {} instead of resolving them",
+                path.display()
+            ));
+            read_payload_file(path)?
+        }
+        None => resolve_payload(
+            shell,
+            workspace,
+            &package_name,
+            revdeps,
+            num_workers,
+            &options,
+            progress,
+        )?,
+    };
+    payload.install_scripts = coalesce_apt_installs(&payload.install_scripts);
+
+    if options.print || options.dry_run {
+        progress.println(describe_payload(&package_name, &payload));
+    }
+    if options.dry_run {
+        progress.println("Dry run: not running system requirement commands.");
+        return Ok(());
+    }
+
+    let mut completed = CompletedCommands::load(workspace.sysreqs_done_path());
+    install_scripts(
+        shell,
+        &package_name,
+        &payload.install_scripts,
+        options.verbose_apt,
+        &mut completed,
+        progress,
+    )?;
+    run_post_install(
+        shell,
+        &package_name,
+        &payload.post_install,
+        &mut completed,
+        progress,
+    )?;
+    // The record only serves a rerun after a failure; a later run on a fresh
+    // machine reusing the workspace must install everything again.
+    completed.clear()?;
+
+    Ok(())
+}
+
+/// System requirement commands that already succeeded in this workspace
+/// during a sysreqs phase that then failed, keyed by a stable hash of the
+/// command line.
+struct CompletedCommands {
+    path: PathBuf,
+    keys: HashSet<String>,
+}
+
+impl CompletedCommands {
+    fn load(path: PathBuf) -> Self {
+        let keys = fs::read_to_string(&path)
+            .map(|contents| contents.lines().map(str::to_string).collect())
+            .unwrap_or_default();
+        Self { path, keys }
+    }
+
+    fn contains(&self, command: &str) -> bool {
+        self.keys.contains(&command_key(command))
+    }
+
+    /// Records that `command` succeeded. `apt-get update` is never recorded:
+    /// it must run again before installs on a resumed run.
+    fn record(&mut self, command: &str) -> Result<()> {
+        if parse_apt_command(command) == Some(AptCommand::Update) {
+            return Ok(());
+        }
+        let key = command_key(command);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("failed to open {}", self.path.display()))?;
+        writeln!(file, "{key}")
+            .with_context(|| format!("failed to write {}", self.path.display()))?;
+        self.keys.insert(key);
+        Ok(())
+    }
+
+    /// Forgets every recorded command, once the whole phase succeeded.
+    fn clear(&mut self) -> Result<()> {
+        self.keys.clear();
+        match fs::remove_file(&self.path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(err).with_context(|| format!("failed to remove {}", self.path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// FNV-1a hash of `command`, which unlike `DefaultHasher` is stable across
+/// Rust releases.
+fn command_key(command: &str) -> String {
+    let hash = command
+        .trim()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+        });
+    format!("{hash:016x}")
+}
+
+/// Reads a user-supplied sysreqs payload in the same JSON shape that the
+/// resolution script emits.
+fn read_payload_file(path: &Path) -> Result<SysreqsPayload> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("failed to read sysreqs file {}", path.display()))?;
+    serde_json::from_str(&contents)
+        .with_context(|| format!("failed to parse sysreqs file {}", path.display()))
+}
+
+fn resolve_payload(
+    shell: &Shell,
+    workspace: &Workspace,
+    package_name: &str,
+    revdeps: &[String],
+    num_workers: usize,
+    options: &SysreqsOptions,
+    progress: &Progress,
+) -> Result<SysreqsPayload> {
+    let max_connections = util::optimal_max_connections(num_workers, options.connections);
+    let script_contents = build_sysreqs_script(package_name, revdeps, num_workers, options)?;
+    util::dump_script(
+        options.dump_scripts.as_deref(),
+        "sysreqs.R",
+        &script_contents,
+        progress,
+    )?;
     let mut script = NamedTempFile::new_in(workspace.temp_dir())
         .context("failed to create temporary sysreqs R script")?;
     script
@@ -58,7 +291,6 @@ pub fn install_reverse_dep_sysreqs(
         .context("failed to write sysreqs R script")?;
 
     let script_path = script.path().to_owned();
-    let _dir_guard = shell.push_dir(repo_path);
 
     let task = progress.task(format!(
         "Resolving system requirements for reverse dependencies of {package_name}"
@@ -68,6 +300,7 @@ pub fn install_reverse_dep_sysreqs(
         shell,
         "Rscript --vanilla --max-connections={max_connections_arg} {script_path}"
     )
+    .envs(options.env.iter().map(|var| (&var.key, &var.value)))
     .quiet()
     .ignore_status()
     .output();
@@ -88,8 +321,8 @@ pub fn install_reverse_dep_sysreqs(
                 &output.stderr,
             );
             bail!(
-                "sysreq resolution script failed with status {}",
-                output.status
+                "sysreq resolution script {}",
+                util::describe_exit(output.status)
             );
         }
         Err(err) => {
@@ -102,21 +335,135 @@ pub fn install_reverse_dep_sysreqs(
 
     let stdout =
         String::from_utf8(output.stdout).context("sysreq resolution emitted non-UTF-8 output")?;
-    let payload: SysreqsPayload =
-        serde_json::from_str(stdout.trim()).context("failed to parse sysreq resolution output")?;
+    serde_json::from_str(stdout.trim()).context("failed to parse sysreq resolution output")
+}
+
+fn describe_payload(package_name: &str, payload: &SysreqsPayload) -> String {
+    let mut lines = vec![format!(
+        "System requirements for {} reverse dependencies of {package_name}:",
+        payload.revdeps.len()
+    )];
+    if !payload.revdeps.is_empty() {
+        lines.push(format!("  revdeps: {}", payload.revdeps.join(", ")));
+    }
+    if payload.install_scripts.is_empty() && payload.post_install.is_empty() {
+        lines.push("  (no commands required)".to_string());
+    }
+    lines.extend(
+        payload
+            .install_scripts
+            .iter()
+            .map(|script| format!("  install: {script}")),
+    );
+    lines.extend(
+        payload
+            .post_install
+            .iter()
+            .map(|command| format!("  post-install: {command}")),
+    );
+    lines.join("\n")
+}
+
+/// Merges each contiguous run of `apt-get` lines into a single install of the
+/// union of its packages, preceded by one `apt-get update`.
+///
+/// Other scripts stay where they are, so an apt install that follows a
+/// repository setup script still runs after it, against refreshed indexes.
+fn coalesce_apt_installs(scripts: &[String]) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut run: Option<Vec<String>> = None;
+
+    for script in scripts {
+        match parse_apt_command(script) {
+            Some(AptCommand::Update) => {
+                run.get_or_insert_with(Vec::new);
+            }
+            Some(AptCommand::Install(names)) => {
+                let packages = run.get_or_insert_with(Vec::new);
+                for name in names {
+                    if !packages.contains(&name) {
+                        packages.push(name);
+                    }
+                }
+            }
+            None => {
+                if let Some(packages) = run.take() {
+                    commands.extend(merged_apt_run(&packages));
+                }
+                commands.push(script.clone());
+            }
+        }
+    }
+    if let Some(packages) = run {
+        commands.extend(merged_apt_run(&packages));
+    }
 
-    install_scripts(shell, &package_name, &payload.install_scripts, progress)?;
-    run_post_install(shell, &package_name, &payload.post_install, progress)?;
+    commands
+}
 
-    Ok(())
+fn merged_apt_run(packages: &[String]) -> Vec<String> {
+    let mut merged = vec!["DEBIAN_FRONTEND=noninteractive apt-get -y update".to_string()];
+    if !packages.is_empty() {
+        merged.push(format!(
+            "DEBIAN_FRONTEND=noninteractive apt-get -y install {}",
+            packages.join(" ")
+        ));
+    }
+    merged
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum AptCommand {
+    Update,
+    Install(Vec<String>),
+}
+
+/// Recognises plain `apt-get update` / `apt-get install pkg...` lines that are
+/// safe to merge. Anything with shell syntax or unusual flags returns `None`.
+fn parse_apt_command(script: &str) -> Option<AptCommand> {
+    const MERGEABLE_FLAGS: &[&str] = &["-y", "--yes", "-q", "-qq", "--quiet"];
+
+    if script.contains(|ch: char| ";&|$`<>()\n".contains(ch)) {
+        return None;
+    }
+
+    let mut tokens = script
+        .split_whitespace()
+        .skip_while(|token| *token == "sudo" || token.starts_with("DEBIAN_FRONTEND="));
+    if !matches!(tokens.next(), Some("apt-get" | "apt")) {
+        return None;
+    }
+
+    let mut subcommand = None;
+    let mut packages = Vec::new();
+    for token in tokens {
+        if token.starts_with('-') {
+            if !MERGEABLE_FLAGS.contains(&token) {
+                return None;
+            }
+        } else if subcommand.is_none() {
+            subcommand = Some(token);
+        } else {
+            packages.push(token.to_string());
+        }
+    }
+
+    match subcommand {
+        Some("update") if packages.is_empty() => Some(AptCommand::Update),
+        Some("install") if !packages.is_empty() => Some(AptCommand::Install(packages)),
+        _ => None,
+    }
 }
 
 fn install_scripts(
     shell: &Shell,
     package_name: &str,
     install_scripts: &[String],
+    verbose_apt: bool,
+    completed: &mut CompletedCommands,
     progress: &Progress,
 ) -> Result<()> {
+    let sudo = util::sudo();
     if install_scripts.is_empty() {
         progress.println(format!(
             "No additional dependencies required for checking reverse dependencies of {package_name}."
@@ -129,8 +476,23 @@ fn install_scripts(
     ));
     for script in install_scripts {
         let label = format!("sudo sh -c {}", script);
+        if completed.contains(script) {
+            progress.println(format!("Skipping {label}; it succeeded in an earlier run"));
+            continue;
+        }
+        if verbose_apt {
+            util::stream_to_progress(
+                cmd!(shell, "{sudo} sh -c {script}"),
+                progress,
+                &format!("Running {label}"),
+                &format!("{label} succeeded"),
+            )
+            .context("revdep dependency package installation failed")?;
+            completed.record(script)?;
+            continue;
+        }
         let task = progress.task(format!("Running {label}"));
-        let output = cmd!(shell, "sudo sh -c {script}")
+        let output = cmd!(shell, "{sudo} sh -c {script}")
             .quiet()
             .ignore_status()
             .output();
@@ -138,6 +500,7 @@ fn install_scripts(
         match output {
             Ok(output) if output.status.success() => {
                 task.finish_with_message(format!("{label} succeeded"));
+                completed.record(script)?;
             }
             Ok(output) => {
                 task.fail(format!("{label} failed"));
@@ -158,8 +521,10 @@ fn run_post_install(
     shell: &Shell,
     package_name: &str,
     post_install: &[String],
+    completed: &mut CompletedCommands,
     progress: &Progress,
 ) -> Result<()> {
+    let sudo = util::sudo();
     if post_install.is_empty() {
         return Ok(());
     }
@@ -169,8 +534,12 @@ fn run_post_install(
     ));
     for command in post_install {
         let label = format!("sudo sh -c {}", command);
+        if completed.contains(command) {
+            progress.println(format!("Skipping {label}; it succeeded in an earlier run"));
+            continue;
+        }
         let task = progress.task(format!("Running {label}"));
-        let output = cmd!(shell, "sudo sh -c {command}")
+        let output = cmd!(shell, "{sudo} sh -c {command}")
             .quiet()
             .ignore_status()
             .output();
@@ -178,6 +547,7 @@ fn run_post_install(
         match output {
             Ok(output) if output.status.success() => {
                 task.finish_with_message(format!("{label} succeeded"));
+                completed.record(command)?;
             }
             Ok(output) => {
                 task.fail(format!("{label} failed"));
@@ -194,43 +564,33 @@ fn run_post_install(
     Ok(())
 }
 
-fn read_package_name(repo_path: &Path) -> Result<String> {
-    let description_path = repo_path.join("DESCRIPTION");
-    let contents = fs::read_to_string(&description_path).with_context(|| {
-        format!(
-            "failed to read package DESCRIPTION at {}",
-            description_path.display()
-        )
-    })?;
-
-    for line in contents.lines() {
-        if let Some(rest) = line.strip_prefix("Package:") {
-            let name = rest.trim();
-            if name.is_empty() {
-                bail!("package DESCRIPTION has empty Package field");
-            }
-            return Ok(name.to_string());
-        }
-    }
-
-    Err(anyhow!(
-        "could not find Package field in {}",
-        description_path.display()
-    ))
-}
-
-fn build_sysreqs_script(package_name: &str, num_workers: usize) -> Result<String> {
+fn build_sysreqs_script(
+    package_name: &str,
+    revdeps: &[String],
+    num_workers: usize,
+    options: &SysreqsOptions,
+) -> Result<String> {
     let package_literal = util::r_string_literal(package_name);
+    let platform_literal = util::r_string_literal(&options.platform);
+    let source_repo_setup = auth::source_repo_setup(&options.cran_mirror);
+    let revdeps_literal = util::r_character_vector(revdeps);
     let workers = num_workers.max(1);
+    let bioc_setup = revdep::bioc_setup(options.bioc_version.as_ref());
+    let additional_setup = revdep::additional_repos_setup(&options.additional_repos);
+    let repos = match options.bioc_version {
+        Some(_) => "c(CRAN = source_repo, bioc_repos)",
+        None => "c(CRAN = source_repo)",
+    };
+    let repos = revdep::with_additional_repos(repos, &options.additional_repos);
 
     let script = format!(
         r#"
 options(warn = 2)
 
-source_repo <- "https://packagemanager.posit.co/cran/latest"
-
+{source_repo_setup}
+{bioc_setup}{additional_setup}
 options(
-  repos = c(CRAN = source_repo),
+  repos = {repos},
   BioC_mirror = "https://packagemanager.posit.co/bioconductor",
   Ncpus = {workers}
 )
@@ -259,35 +619,21 @@ ensure_installed("pak")
 ensure_installed("jsonlite")
 
 pkg_name <- {package_literal}
-
-db <- available.packages(repos = source_repo, type = "source")
-revdeps <- tools::package_dependencies(
-  packages = pkg_name,
-  db = db,
-  which = c("Depends", "Imports", "LinkingTo", "Suggests"),
-  reverse = TRUE
-)[[pkg_name]]
-if (is.null(revdeps)) {{
-  revdeps <- character()
-}}
-revdeps <- sort(unique(stats::na.omit(revdeps)))
-if (length(revdeps) > 0) {{
-  base_pkgs <- unique(c(.BaseNamespaceEnv$basePackage, rownames(installed.packages(priority = "base"))))
-  revdeps <- setdiff(revdeps, base_pkgs)
-}}
+revdeps <- {revdeps_literal}
 
 sysreqs <- if (length(revdeps) == 0) {{
   list(install_scripts = character(), post_install = character())
 }} else {{
-  pak::pkg_sysreqs(revdeps, sysreqs_platform = "ubuntu")
+  pak::pkg_sysreqs(revdeps, sysreqs_platform = {platform_literal})
 }}
 
 if (!is.list(sysreqs) || is.null(sysreqs$install_scripts) || is.null(sysreqs$post_install)) {{
   stop("unexpected sysreqs payload")
 }}
 sysreqs$post_install <- unique(sysreqs$post_install)
+sysreqs$revdeps <- revdeps
 
-cat(jsonlite::toJSON(sysreqs[c('install_scripts', 'post_install')], auto_unbox = TRUE))
+cat(jsonlite::toJSON(sysreqs[c('install_scripts', 'post_install', 'revdeps')], auto_unbox = TRUE))
 "#
     );
 
@@ -297,30 +643,100 @@ cat(jsonlite::toJSON(sysreqs[c('install_scripts', 'post_install')], auto_unbox =
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs::File;
-    use std::io::Write;
-    use tempfile::tempdir;
 
     #[test]
-    fn reads_package_name_from_description() {
-        let dir = tempdir().expect("tempdir");
-        let description_path = dir.path().join("DESCRIPTION");
-        let mut file = File::create(&description_path).expect("create DESCRIPTION");
-        writeln!(file, "Package: example").expect("write package");
-        let name = read_package_name(dir.path()).expect("package name");
-        assert_eq!(name, "example");
+    fn remembers_completed_commands() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("done");
+        let install = "apt-get -y install libcurl4-openssl-dev";
+
+        let mut completed = CompletedCommands::load(path.clone());
+        assert!(!completed.contains(install));
+        completed.record(install).expect("record");
+
+        let update = "DEBIAN_FRONTEND=noninteractive apt-get -y update";
+        completed.record(update).expect("record update");
+
+        let mut reloaded = CompletedCommands::load(path.clone());
+        assert!(reloaded.contains(install));
+        assert!(!reloaded.contains(update));
+        assert!(!reloaded.contains("apt-get -y install libxml2-dev"));
+
+        reloaded.clear().expect("clear");
+        assert!(!path.exists());
+        assert!(!CompletedCommands::load(path).contains(install));
+        assert_eq!(command_key("abc"), "e71fa2190541574b");
     }
 
     #[test]
     fn build_script_contains_expected_fragments() {
-        let script = build_sysreqs_script("ggsci", 4).expect("script must render");
-        assert!(script.contains("tools::package_dependencies"));
-        assert!(script.contains("pak::pkg_sysreqs"));
+        let revdeps = vec!["pkgA".to_string(), "pkgB".to_string()];
+        let options = SysreqsOptions {
+            platform: "ubuntu-22.04".to_string(),
+            cran_mirror: "https://cloud.r-project.org".to_string(),
+            ..SysreqsOptions::default()
+        };
+        let script =
+            build_sysreqs_script("ggsci", &revdeps, 4, &options).expect("script must render");
+        assert!(script.contains("source_repo <- 'https://cloud.r-project.org'"));
+        assert!(script.contains("revdeps <- c('pkgA', 'pkgB')"));
+        assert!(script.contains("pak::pkg_sysreqs(revdeps, sysreqs_platform = 'ubuntu-22.04')"));
         assert!(script.contains("ensure_installed(\"pak\")"));
-        assert!(script.contains("available.packages"));
         assert!(script.contains("jsonlite::toJSON"));
         assert!(script.contains("Sys.setenv(NOT_CRAN = \"true\")"));
-        assert!(script.contains("setdiff(revdeps, base_pkgs)"));
+        assert!(script.contains("repos = c(CRAN = source_repo),"));
+        assert!(!script.contains("R_BIOC_VERSION"));
+
+        let options = SysreqsOptions {
+            bioc_version: Some(BiocVersion("3.21".to_string())),
+            ..options
+        };
+        let script =
+            build_sysreqs_script("ggsci", &revdeps, 4, &options).expect("script must render");
+        assert!(script.contains("bioc_version <- '3.21'"));
+        assert!(script.contains("Sys.setenv(R_BIOC_VERSION = bioc_version)"));
+        assert!(script.contains("repos = c(CRAN = source_repo, bioc_repos),"));
+
+        let options = SysreqsOptions {
+            additional_repos: vec!["https://a.github.io/drat".to_string()],
+            ..options
+        };
+        let script =
+            build_sysreqs_script("ggsci", &revdeps, 4, &options).expect("script must render");
+        assert!(script.contains("additional_repos <- c('https://a.github.io/drat')"));
+        assert!(script.contains("repos = c(CRAN = source_repo, bioc_repos, additional_repos),"));
+    }
+
+    #[test]
+    fn reads_payload_file() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("sysreqs.json");
+        fs::write(
+            &path,
+            r#"{"install_scripts": "apt-get install -y libxml2-dev", "post_install": null}"#,
+        )
+        .expect("write payload");
+        let payload = read_payload_file(&path).expect("valid payload");
+        assert_eq!(
+            payload.install_scripts,
+            vec!["apt-get install -y libxml2-dev".to_string()]
+        );
+        assert!(payload.post_install.is_empty());
+        assert!(payload.revdeps.is_empty());
+
+        fs::write(&path, r#"{"install_scripts": 42}"#).expect("write payload");
+        let err = read_payload_file(&path).expect_err("invalid payload");
+        assert!(err.to_string().contains("failed to parse sysreqs file"));
+    }
+
+    #[test]
+    fn validates_sysreqs_platforms() {
+        assert!(validate_platform("ubuntu").is_ok());
+        assert!(validate_platform("ubuntu-22.04").is_ok());
+        assert!(validate_platform("debian-12").is_ok());
+        assert!(validate_platform("windows").is_err());
+        assert!(validate_platform("ubuntu-").is_err());
+        assert!(validate_platform("ubuntu-jammy").is_err());
     }
 
     #[test]
@@ -340,6 +756,87 @@ mod tests {
         assert!(payload.post_install.is_empty());
     }
 
+    #[test]
+    fn describes_payload_commands_and_revdeps() {
+        let json = r#"
+            {
+                "install_scripts": "apt-get install -y libxml2-dev",
+                "post_install": [],
+                "revdeps": ["pkgA", "pkgB"]
+            }
+        "#;
+        let payload: SysreqsPayload = serde_json::from_str(json).expect("payload");
+        let description = describe_payload("ggsci", &payload);
+        assert!(description.contains("2 reverse dependencies of ggsci"));
+        assert!(description.contains("revdeps: pkgA, pkgB"));
+        assert!(description.contains("install: apt-get install -y libxml2-dev"));
+    }
+
+    #[test]
+    fn coalesces_apt_install_scripts() {
+        let scripts = [
+            "apt-get -y update",
+            "apt-get -y install libcurl4-openssl-dev libssl-dev",
+            "apt-get -y install libxml2-dev libssl-dev",
+        ]
+        .map(String::from);
+
+        assert_eq!(
+            coalesce_apt_installs(&scripts),
+            vec![
+                "DEBIAN_FRONTEND=noninteractive apt-get -y update".to_string(),
+                "DEBIAN_FRONTEND=noninteractive apt-get -y install libcurl4-openssl-dev libssl-dev libxml2-dev"
+                    .to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_repo_setup_between_apt_installs() {
+        let scripts = [
+            "apt-get -y install libcurl4-openssl-dev",
+            "apt-get -y install libssl-dev",
+            "add-apt-repository -y ppa:ubuntugis/ppa",
+            "apt-get -y install libgdal-dev libssl-dev",
+        ]
+        .map(String::from);
+
+        assert_eq!(
+            coalesce_apt_installs(&scripts),
+            vec![
+                "DEBIAN_FRONTEND=noninteractive apt-get -y update".to_string(),
+                "DEBIAN_FRONTEND=noninteractive apt-get -y install libcurl4-openssl-dev libssl-dev"
+                    .to_string(),
+                "add-apt-repository -y ppa:ubuntugis/ppa".to_string(),
+                "DEBIAN_FRONTEND=noninteractive apt-get -y update".to_string(),
+                "DEBIAN_FRONTEND=noninteractive apt-get -y install libgdal-dev libssl-dev"
+                    .to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_unusual_apt_scripts_separate() {
+        assert_eq!(
+            parse_apt_command("apt-get install -y --no-install-recommends foo"),
+            None
+        );
+        assert_eq!(
+            parse_apt_command("apt-get install -y foo && echo done"),
+            None
+        );
+        assert_eq!(
+            parse_apt_command("sudo apt-get install -y foo bar"),
+            Some(AptCommand::Install(vec![
+                "foo".to_string(),
+                "bar".to_string()
+            ]))
+        );
+
+        let scripts = ["add-apt-repository -y ppa:x/y".to_string()];
+        assert_eq!(coalesce_apt_installs(&scripts), scripts.to_vec());
+    }
+
     #[test]
     fn deserializes_null_install_scripts() {
         let json = r#"
@@ -3,13 +3,34 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use xshell::{Shell, cmd};
+
+use crate::util;
+
+const DEFAULT_TEMP_DIR_NAME: &str = "revdeprun-work";
+
+/// File in the temporary directory recording succeeded sysreqs commands.
+pub const SYSREQS_DONE_FILE: &str = "revdeprun-sysreqs-done";
+
+/// Workspace used by `--tmpfs-workspace` when no `--work-dir` is given.
+pub const DEFAULT_TMPFS_WORKSPACE: &str = "/dev/shm/revdeprun";
+
+/// Rough RAM footprint of one revdep: its check directory plus its share of
+/// the dependency library.
+pub const ESTIMATED_BYTES_PER_REVDEP: u64 = 200 * 1024 * 1024;
 
 /// Describes the directories managed for a `revdeprun` invocation.
 #[derive(Clone, Debug)]
 pub struct Workspace {
     temp_dir: PathBuf,
     clone_root: PathBuf,
+    require_clean_clone_root: bool,
+    /// Whether the directories come from `--work-dir`.
+    custom: bool,
+    /// Whether to reject clones that would nest confusingly; see
+    /// [`check_nesting`].
+    reject_nesting: bool,
 }
 
 impl Workspace {
@@ -22,6 +43,124 @@ impl Workspace {
     pub fn clone_root(&self) -> &Path {
         &self.clone_root
     }
+
+    /// File recording the date of the last completed run for `package`.
+    pub fn last_run_path(&self, package: &str) -> PathBuf {
+        self.temp_dir.join(format!("revdeprun-last-run-{package}"))
+    }
+
+    /// Returns the date of the last completed run for `package`, if recorded.
+    pub fn last_run(&self, package: &str) -> Option<String> {
+        fs::read_to_string(self.last_run_path(package))
+            .ok()
+            .map(|contents| contents.trim().to_string())
+            .filter(|date| crate::util::is_iso_date(date))
+    }
+
+    /// Records `date` as the last completed run for `package`.
+    pub fn record_last_run(&self, package: &str, date: &str) -> Result<()> {
+        let path = self.last_run_path(package);
+        fs::write(&path, format!("{date}\n"))
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// File listing the keys of system requirement commands that already
+    /// succeeded, so a rerun after a failed sysreqs phase can skip them.
+    pub fn sysreqs_done_path(&self) -> PathBuf {
+        self.temp_dir.join(SYSREQS_DONE_FILE)
+    }
+
+    /// Creates the clone root and, for the default workspace, refuses to clone
+    /// into a directory that already holds unrelated files.
+    pub fn ensure_clone_root_ready(&self) -> Result<()> {
+        fs::create_dir_all(&self.clone_root).with_context(|| {
+            format!(
+                "failed to create clone root directory {}",
+                self.clone_root.display()
+            )
+        })?;
+
+        if !self.require_clean_clone_root {
+            return Ok(());
+        }
+
+        let entries = visible_entries(&self.clone_root)?;
+        if !entries.is_empty() {
+            let mut preview = entries.iter().take(5).cloned().collect::<Vec<_>>();
+            if entries.len() > preview.len() {
+                preview.push(format!("... ({} more)", entries.len() - preview.len()));
+            }
+            bail!(
+                "the current directory {} is not empty ({}); clones and revdep/ results would mingle with existing files. \
+                 Pass --work-dir to use a dedicated directory or --allow-dirty-workspace to proceed anyway",
+                self.clone_root.display(),
+                preview.join(", ")
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Rejects a clone of `name` into a `--work-dir` when that would nest it
+    /// confusingly; see [`check_nesting`]. `--allow-dirty-workspace` skips
+    /// this.
+    pub fn check_clone_destination(&self, name: &str) -> Result<()> {
+        if !self.reject_nesting {
+            return Ok(());
+        }
+        check_nesting(&self.clone_root, name)
+    }
+
+    /// Like [`Workspace::check_clone_destination`], for sources extracted
+    /// from a tarball into the temporary directory.
+    pub fn check_extract_destination(&self, name: &str) -> Result<()> {
+        if !self.reject_nesting {
+            return Ok(());
+        }
+        check_nesting(&self.temp_dir, name)
+    }
+
+    /// Rejects a `--work-dir` inside the local package at `package_dir`,
+    /// where revdeprun's scripts and extracted sources would end up in the
+    /// package build.
+    pub fn check_local_package(&self, package_dir: &Path) -> Result<()> {
+        if !self.custom {
+            return Ok(());
+        }
+        let work_dir = canonicalized(&self.temp_dir)?;
+        let package_dir = canonicalized(package_dir)?;
+        if work_dir.starts_with(&package_dir) {
+            bail!(
+                "--work-dir {} is inside the package being checked ({}), so revdeprun's files \
+                 would be built into the package; choose a --work-dir outside it",
+                work_dir.display(),
+                package_dir.display()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Fails when placing `name` under `root` would nest it confusingly: `root`
+/// is already named after it (`pkg/pkg`), or `root` is itself an R package
+/// source tree.
+fn check_nesting(root: &Path, name: &str) -> Result<()> {
+    if root.file_name().is_some_and(|root_name| root_name == name) {
+        bail!(
+            "the workspace {} is named after {name}, so it would be placed in {}; \
+             pass the parent directory or a fresh one as --work-dir",
+            root.display(),
+            root.join(name).display()
+        );
+    }
+    if root.join("DESCRIPTION").is_file() {
+        bail!(
+            "the workspace {} is an R package source tree, so {name} would be nested inside it; \
+             pass a dedicated directory as --work-dir",
+            root.display()
+        );
+    }
+    Ok(())
 }
 
 /// Prepares and returns the workspace directories used for cloning repositories
@@ -30,36 +169,161 @@ impl Workspace {
 /// When `custom` is `Some`, it is created if necessary and used both as the
 /// clone root and temporary directory. Otherwise repositories are cloned into
 /// the current working directory and temporary files are placed under
-/// `./revdeprun-work`.
-pub fn prepare(custom: Option<PathBuf>) -> Result<Workspace> {
+/// `./revdeprun-work`. Unless `allow_dirty` is set, cloning into a default
+/// workspace that already contains files, or nesting a clone confusingly in a
+/// custom one, is rejected.
+pub fn prepare(custom: Option<PathBuf>, allow_dirty: bool) -> Result<Workspace> {
     match custom {
-        Some(path) => prepare_custom_workspace(path),
-        None => prepare_default_workspace(),
+        Some(path) => prepare_custom_workspace(path, allow_dirty),
+        None => prepare_default_workspace(allow_dirty),
+    }
+}
+
+/// Returns the temporary directory [`prepare`] would use, without creating it.
+pub fn temp_dir_path(custom: Option<&Path>) -> Result<PathBuf> {
+    match custom {
+        Some(path) => Ok(path.to_path_buf()),
+        None => Ok(env::current_dir()
+            .context("failed to resolve current directory")?
+            .join(DEFAULT_TEMP_DIR_NAME)),
+    }
+}
+
+/// Workspace for `--check-dir`: the package at `package_dir` is checked in
+/// place and serves as the clone root, and temporary files go to
+/// `revdeprun-work` under `results_dir`, its `revdep/` directory, so nothing
+/// is written outside the package. Paths are made absolute but symlinks are
+/// kept as given.
+pub fn in_place(package_dir: &Path, results_dir: &Path) -> Result<Workspace> {
+    let clone_root = std::path::absolute(package_dir)
+        .with_context(|| format!("failed to resolve {}", package_dir.display()))?;
+    if !clone_root.join("DESCRIPTION").is_file() {
+        bail!(
+            "{} has no DESCRIPTION file; --check-dir needs an R package source directory",
+            clone_root.display()
+        );
     }
+    let temp_dir = std::path::absolute(results_dir)
+        .with_context(|| format!("failed to resolve {}", results_dir.display()))?
+        .join(DEFAULT_TEMP_DIR_NAME);
+    fs::create_dir_all(&temp_dir)
+        .with_context(|| format!("failed to create workspace at {}", temp_dir.display()))?;
+
+    Ok(Workspace {
+        temp_dir,
+        clone_root,
+        require_clean_clone_root: false,
+        custom: false,
+        reject_nesting: false,
+    })
 }
 
-fn prepare_custom_workspace(path: PathBuf) -> Result<Workspace> {
+fn prepare_custom_workspace(path: PathBuf, allow_dirty: bool) -> Result<Workspace> {
     fs::create_dir_all(&path)
         .with_context(|| format!("failed to create custom workspace at {}", path.display()))?;
 
     Ok(Workspace {
         temp_dir: path.clone(),
         clone_root: path,
+        require_clean_clone_root: false,
+        custom: true,
+        reject_nesting: !allow_dirty,
     })
 }
 
-fn prepare_default_workspace() -> Result<Workspace> {
+fn prepare_default_workspace(allow_dirty: bool) -> Result<Workspace> {
     let clone_root = env::current_dir().context("failed to resolve current directory")?;
-    let temp_dir = clone_root.join("revdeprun-work");
+    let temp_dir = clone_root.join(DEFAULT_TEMP_DIR_NAME);
     fs::create_dir_all(&temp_dir)
         .with_context(|| format!("failed to create workspace at {}", temp_dir.display()))?;
 
     Ok(Workspace {
         temp_dir,
         clone_root,
+        require_clean_clone_root: !allow_dirty,
+        custom: false,
+        reject_nesting: false,
     })
 }
 
+/// Lists non-hidden entries in `dir`, ignoring the default temporary directory.
+fn visible_entries(dir: &Path) -> Result<Vec<String>> {
+    let entries =
+        fs::read_dir(dir).with_context(|| format!("failed to inspect {}", dir.display()))?;
+
+    let mut names = Vec::new();
+    for entry in entries {
+        let entry = entry.with_context(|| format!("failed to inspect {}", dir.display()))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') || name == DEFAULT_TEMP_DIR_NAME {
+            continue;
+        }
+        names.push(name);
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Makes `path` RAM-backed: an existing tmpfs mount is used as-is, otherwise a
+/// tmpfs is mounted on it with `sudo`. Returns `true` if a mount was created.
+pub fn ensure_tmpfs(shell: &Shell, path: &Path) -> Result<bool> {
+    let sudo = util::sudo();
+    fs::create_dir_all(path)
+        .with_context(|| format!("failed to create tmpfs workspace at {}", path.display()))?;
+    if is_tmpfs(path) {
+        return Ok(false);
+    }
+    cmd!(shell, "{sudo} mount -t tmpfs -o size=90% revdeprun {path}")
+        .quiet()
+        .run()
+        .with_context(|| format!("failed to mount a tmpfs on {}", path.display()))?;
+    if !is_tmpfs(path) {
+        bail!(
+            "{} is still not backed by tmpfs after mounting",
+            path.display()
+        );
+    }
+    Ok(true)
+}
+
+/// Returns whether `path` lives on a tmpfs mount according to `/proc/mounts`.
+pub fn is_tmpfs(path: &Path) -> bool {
+    let Ok(path) = path.canonicalize() else {
+        return false;
+    };
+    let mounts = fs::read_to_string("/proc/mounts").unwrap_or_default();
+    mount_fs_type(&mounts, &path) == Some("tmpfs")
+}
+
+/// Bytes of memory available for new allocations, from `/proc/meminfo`.
+pub fn available_memory() -> Option<u64> {
+    parse_mem_available(&fs::read_to_string("/proc/meminfo").ok()?)
+}
+
+/// File system type of the most specific mount point containing `path`.
+fn mount_fs_type<'a>(mounts: &'a str, path: &Path) -> Option<&'a str> {
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let _device = fields.next()?;
+            let mount_point = fields.next()?;
+            let fs_type = fields.next()?;
+            path.starts_with(mount_point)
+                .then_some((mount_point.len(), fs_type))
+        })
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, fs_type)| fs_type)
+}
+
+fn parse_mem_available(meminfo: &str) -> Option<u64> {
+    let line = meminfo
+        .lines()
+        .find(|line| line.starts_with("MemAvailable:"))?;
+    let kib = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kib * 1024)
+}
+
 /// Returns the absolute path of `path` if it already exists.
 ///
 /// This helper is used by modules that need to communicate user-facing paths.
@@ -77,10 +341,128 @@ mod tests {
     fn custom_workspace_uses_provided_path() {
         let tmp = tempdir().expect("tempdir");
         let base = tmp.path().join("workspace");
-        let workspace = prepare(Some(base.clone())).expect("prepare custom workspace");
+        let workspace = prepare(Some(base.clone()), false).expect("prepare custom workspace");
 
         assert_eq!(workspace.clone_root(), base.as_path());
         assert_eq!(workspace.temp_dir(), base.as_path());
         assert!(base.exists());
     }
+
+    #[test]
+    fn in_place_workspace_stays_inside_the_package() {
+        let tmp = tempdir().expect("tempdir");
+        let package = tmp.path().join("ggsci");
+        assert!(in_place(&package, &package.join("revdep")).is_err());
+
+        fs::create_dir(&package).expect("package dir");
+        fs::write(package.join("DESCRIPTION"), "Package: ggsci\n").expect("DESCRIPTION");
+        let workspace = in_place(&package, &package.join("revdep")).expect("workspace");
+        assert_eq!(workspace.clone_root(), package.as_path());
+        assert_eq!(
+            workspace.temp_dir(),
+            package.join("revdep").join(DEFAULT_TEMP_DIR_NAME).as_path()
+        );
+        assert!(workspace.temp_dir().is_dir());
+    }
+
+    #[test]
+    fn records_last_run_per_package() {
+        let tmp = tempdir().expect("tempdir");
+        let workspace = prepare(Some(tmp.path().to_path_buf()), false).expect("workspace");
+
+        assert_eq!(workspace.last_run("ggsci"), None);
+        workspace
+            .record_last_run("ggsci", "2025-06-01")
+            .expect("record");
+        assert_eq!(workspace.last_run("ggsci").as_deref(), Some("2025-06-01"));
+        assert_eq!(workspace.last_run("other"), None);
+    }
+
+    #[test]
+    fn rejects_confusing_nesting() {
+        let tmp = tempdir().expect("tempdir");
+        let workspace = prepare(Some(tmp.path().join("ggsci")), false).expect("workspace");
+        let err = workspace
+            .check_clone_destination("ggsci")
+            .expect_err("ggsci/ggsci");
+        assert!(err.to_string().contains("is named after ggsci"));
+        workspace
+            .check_clone_destination("ggplot2")
+            .expect("different name");
+
+        fs::write(tmp.path().join("ggsci/DESCRIPTION"), "Package: ggsci\n").expect("DESCRIPTION");
+        let err = workspace
+            .check_clone_destination("ggplot2")
+            .expect_err("inside a package");
+        assert!(err.to_string().contains("is an R package source tree"));
+
+        let allowed = prepare(Some(tmp.path().join("ggsci")), true).expect("workspace");
+        allowed
+            .check_clone_destination("ggplot2")
+            .expect("allowed with --allow-dirty-workspace");
+    }
+
+    #[test]
+    fn rejects_work_dir_inside_local_package() {
+        let tmp = tempdir().expect("tempdir");
+        let package = tmp.path().join("ggsci");
+        let inside = prepare(Some(package.join("work")), false).expect("workspace");
+        let err = inside
+            .check_local_package(&package)
+            .expect_err("work dir inside package");
+        assert!(err.to_string().contains("is inside the package"));
+
+        let outside = prepare(Some(tmp.path().join("work")), false).expect("workspace");
+        outside
+            .check_local_package(&package)
+            .expect("separate work dir");
+    }
+
+    #[test]
+    fn detects_tmpfs_mounts_and_available_memory() {
+        let mounts = "/dev/sda1 / ext4 rw,relatime 0 0\n\
+                      tmpfs /dev/shm tmpfs rw,nosuid,nodev 0 0\n\
+                      /dev/sdb1 /dev/shm/disk ext4 rw 0 0\n";
+        assert_eq!(
+            mount_fs_type(mounts, Path::new("/dev/shm/revdeprun")),
+            Some("tmpfs")
+        );
+        assert_eq!(
+            mount_fs_type(mounts, Path::new("/dev/shm/disk/x")),
+            Some("ext4")
+        );
+        assert_eq!(mount_fs_type(mounts, Path::new("/home/user")), Some("ext4"));
+
+        let meminfo = "MemTotal:       65843080 kB\nMemAvailable:   51200000 kB\n";
+        assert_eq!(parse_mem_available(meminfo), Some(51_200_000 * 1024));
+        assert_eq!(parse_mem_available("MemTotal: 1 kB\n"), None);
+    }
+
+    #[test]
+    fn default_clone_root_rejects_unrelated_files() {
+        let tmp = tempdir().expect("tempdir");
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join(DEFAULT_TEMP_DIR_NAME)).expect("temp dir");
+        fs::write(root.join(".hidden"), "").expect("hidden file");
+
+        let workspace = |require_clean_clone_root| Workspace {
+            temp_dir: root.join(DEFAULT_TEMP_DIR_NAME),
+            clone_root: root.clone(),
+            require_clean_clone_root,
+            custom: false,
+            reject_nesting: false,
+        };
+        workspace(true)
+            .ensure_clone_root_ready()
+            .expect("only hidden and temporary entries");
+
+        fs::write(root.join("notes.txt"), "").expect("unrelated file");
+        let err = workspace(true)
+            .ensure_clone_root_ready()
+            .expect_err("unrelated file must be rejected");
+        assert!(err.to_string().contains("notes.txt"));
+        workspace(false)
+            .ensure_clone_root_ready()
+            .expect("dirty workspace allowed");
+    }
 }